@@ -0,0 +1,97 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput, BenchmarkId, black_box};
+
+use raytracing::math::math3::{Vec3, random_unit_vector};
+use raytracing::math::bvh::{build_bvh};
+use raytracing::math::raytracing::{Ray, HitRecord, Hittable, face_normal_adjustment};
+use raytracing::math::signed_distance::{SignedDistanceField, MarchSettings};
+use raytracing::math::materials::{Material};
+use raytracing::math::utils::{Float};
+
+const NUM_RAYS: usize = 1024;
+
+/// Rays from a fixed origin fanned over a small cone toward -z, so most hit the
+/// objects centred at (0, 0, -1) and some miss.
+fn fan_rays() -> Vec<Ray> {
+    let side = (NUM_RAYS as Float).sqrt() as usize;
+    (0..side * side).map(|index| {
+        let u = (index % side) as Float / (side - 1) as Float - 0.5;
+        let v = (index / side) as Float / (side - 1) as Float - 0.5;
+        Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.2 * u, 1.2 * v, -1.0))
+    }).collect()
+}
+
+fn count_hits(world: &Hittable, rays: &[Ray]) -> usize {
+    rays.iter().filter(|ray| world.hit(ray, 0.001, 100.0).is_some()).count()
+}
+
+fn sphere_hits(c: &mut Criterion) {
+    let rays = fan_rays();
+    let material = || Material::Lambertian(Vec3::new(0.5, 0.5, 0.5));
+    let single = Hittable::Sphere(-Vec3::z_axis(), 0.5, material());
+
+    let mut group = c.benchmark_group("hit_sphere");
+    group.throughput(Throughput::Elements(rays.len() as u64));
+    group.bench_function("single", |b| b.iter(|| count_hits(&single, &rays)));
+    for &num_spheres in [4, 16, 64].iter() {
+        let spheres = || (0..num_spheres).map(|i| {
+            let x = (i as Float / (num_spheres - 1) as Float - 0.5) * 2.0;
+            Hittable::Sphere(Vec3::new(x, 0.0, -1.0 - 0.1 * i as Float), 0.5 / num_spheres as Float + 0.05, material())
+        }).collect::<Vec<_>>();
+        let list = Hittable::HittableList(spheres());
+        group.bench_with_input(BenchmarkId::new("list", num_spheres), &list, |b, list| {
+            b.iter(|| count_hits(list, &rays))
+        });
+        let bvh = build_bvh(spheres());
+        group.bench_with_input(BenchmarkId::new("bvh", num_spheres), &bvh, |b, bvh| {
+            b.iter(|| count_hits(bvh, &rays))
+        });
+    }
+    group.finish();
+}
+
+fn raymarcher_hits(c: &mut Criterion) {
+    let rays = fan_rays();
+
+    let mut group = c.benchmark_group("hit_raymarcher");
+    group.throughput(Throughput::Elements(rays.len() as u64));
+    group.sample_size(20);
+    for &iterations in [2, 4, 6, 8, 10].iter() {
+        let fractal = Hittable::Raymarcher(
+            SignedDistanceField::SierpinskiTetrasphere(Vec3::new(0.0, 0.0, -1.0), iterations),
+            MarchSettings::new(100, 0.000005).with_pixel_footprint(0.0005),
+            Material::Lambertian(Vec3::new(0.5, 0.4, 0.7)));
+        group.bench_with_input(BenchmarkId::new("sierpinski_tetrasphere", iterations), &fractal, |b, fractal| {
+            b.iter(|| count_hits(fractal, &rays))
+        });
+    }
+    group.finish();
+}
+
+fn material_scatter(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    let ray = Ray::new(Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+    let (normal, front_face) = face_normal_adjustment(ray.dir, Vec3::y_axis());
+    let materials = [
+        ("lambertian", Material::Lambertian(Vec3::new(0.5, 0.4, 0.7))),
+        ("metal_polished", Material::Metal(Vec3::new(0.8, 0.8, 0.8), 0.0)),
+        ("metal_fuzzy", Material::Metal(Vec3::new(0.8, 0.6, 0.2), 0.7)),
+        ("absorb", Material::Absorb),
+    ];
+
+    let mut group = c.benchmark_group("scatter");
+    for (name, material) in materials.iter() {
+        group.bench_function(*name, |b| b.iter(|| {
+            let hit_record = HitRecord::new(Vec3::zero(), normal, material, 1.0, front_face);
+            material.scatter(&mut rng, black_box(&ray), hit_record)
+        }));
+    }
+    group.finish();
+}
+
+fn sampling(c: &mut Criterion) {
+    let mut rng = rand::thread_rng();
+    c.bench_function("random_unit_vector", |b| b.iter(|| random_unit_vector(&mut rng)));
+}
+
+criterion_group!(benches, sphere_hits, raymarcher_hits, material_scatter, sampling);
+criterion_main!(benches);
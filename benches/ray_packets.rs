@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput, BenchmarkId};
+use rand::Rng;
+
+use raytracing::math::math3::{Vec3};
+use raytracing::math::raytracing::{Ray, Hittable};
+use raytracing::math::imaging::{Camera};
+use raytracing::math::utils::{Float};
+use raytracing::math::simd::{SimdFloat, RayPacket, F64x4, F32x8};
+use raytracing::scenes::spherion::{spherion_scene};
+use raytracing::scenes::three_spheres::{three_spheres_scene};
+use raytracing::scenes::first_fractal::{first_fractal_scene};
+use raytracing::scenes::spherion_meets_fractalius::{spherion_meets_fractalius_scene};
+
+const IMAGE_WIDTH: usize = 32;
+const IMAGE_HEIGHT: usize = 18;
+const SAMPLES_PER_PIXEL: usize = 8;
+const NUM_RAYS: usize = IMAGE_WIDTH * IMAGE_HEIGHT * SAMPLES_PER_PIXEL;
+
+/// Jittered samples grouped by pixel, the same ray order `raytrace` packs into packets.
+fn camera_rays() -> Vec<Ray> {
+    let cam = Camera::new(Vec3::new(-3.3, 2.0, 1.75), Vec3::zero(), Vec3::y_axis(), 45.0, 16.0 / 9.0);
+    let mut rng = rand::thread_rng();
+    (0..IMAGE_WIDTH * IMAGE_HEIGHT).flat_map(|index| {
+        let i = (index % IMAGE_WIDTH) as Float;
+        let j = (index / IMAGE_WIDTH) as Float;
+        (0..SAMPLES_PER_PIXEL).map(|_| {
+            let u = (i + rng.gen_range(0.0, 1.0)) / (IMAGE_WIDTH - 1) as Float;
+            let v = (j + rng.gen_range(0.0, 1.0)) / (IMAGE_HEIGHT - 1) as Float;
+            cam.get_ray(u, v)
+        }).collect::<Vec<Ray>>()
+    }).collect()
+}
+
+fn trace_packets<L: SimdFloat>(world: &Hittable, rays: &[Ray]) -> usize {
+    rays.chunks(L::LANES)
+        .map(|chunk| world.hit_packet(&RayPacket::<L>::new(chunk), 0.001, 100.0)
+            .iter().filter(|hit| hit.is_some()).count())
+        .sum()
+}
+
+fn primary_hits(c: &mut Criterion) {
+    let rays = camera_rays();
+    let scenes = [
+        ("three_spheres", three_spheres_scene()),
+        ("spherion", spherion_scene()),
+        ("first_fractal", first_fractal_scene()),
+        ("spherion_meets_fractalius", spherion_meets_fractalius_scene()),
+    ];
+
+    let mut group = c.benchmark_group("primary_hits");
+    group.throughput(Throughput::Elements(NUM_RAYS as u64));
+    for (name, world) in scenes.iter() {
+        group.bench_with_input(BenchmarkId::new("scalar", name), world, |b, world| {
+            b.iter(|| rays.iter().filter(|ray| world.hit(ray, 0.001, 100.0).is_some()).count())
+        });
+        group.bench_with_input(BenchmarkId::new("packet4_f64", name), world, |b, world| {
+            b.iter(|| trace_packets::<F64x4>(world, &rays))
+        });
+        group.bench_with_input(BenchmarkId::new("packet8_f32", name), world, |b, world| {
+            b.iter(|| trace_packets::<F32x8>(world, &rays))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, primary_hits);
+criterion_main!(benches);
@@ -0,0 +1,57 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput, BenchmarkId};
+use rand::Rng;
+
+use raytracing::math::math3::{Vec3};
+use raytracing::math::raytracing::{Hittable};
+use raytracing::math::imaging::{Camera, ray_color};
+use raytracing::math::utils::{Float};
+use raytracing::scenes::spherion::{spherion_scene};
+use raytracing::scenes::three_spheres::{three_spheres_scene};
+use raytracing::scenes::first_fractal::{first_fractal_scene};
+use raytracing::scenes::spherion_meets_fractalius::{spherion_meets_fractalius_scene};
+
+const IMAGE_WIDTH: usize = 32;
+const IMAGE_HEIGHT: usize = 18;
+const SAMPLES_PER_PIXEL: usize = 4;
+const MAX_DEPTH: i32 = 5;
+/// Camera rays per render; criterion reports throughput in these, i.e. rays/second.
+const NUM_RAYS: usize = IMAGE_WIDTH * IMAGE_HEIGHT * SAMPLES_PER_PIXEL;
+
+/// Single threaded version of the `raytrace` render loop, with the same camera.
+fn render(world: &Hittable) -> Vec3 {
+    let cam = Camera::new(Vec3::new(-3.3, 2.0, 1.75), Vec3::zero(), Vec3::y_axis(), 45.0,
+                          IMAGE_WIDTH as Float / IMAGE_HEIGHT as Float);
+    let mut rng = rand::thread_rng();
+    (0..IMAGE_WIDTH * IMAGE_HEIGHT).map(|index| {
+        let i = (index % IMAGE_WIDTH) as Float;
+        let j = (index / IMAGE_WIDTH) as Float;
+        (0..SAMPLES_PER_PIXEL).map(|_| {
+            let u = (i + rng.gen_range(0.0, 1.0)) / (IMAGE_WIDTH - 1) as Float;
+            let v = (j + rng.gen_range(0.0, 1.0)) / (IMAGE_HEIGHT - 1) as Float;
+            let ray = cam.get_ray(u, v);
+            ray_color(&mut rng, ray, world, MAX_DEPTH)
+        }).sum::<Vec3>()
+    }).sum()
+}
+
+fn scene_renders(c: &mut Criterion) {
+    let scenes = [
+        ("three_spheres", three_spheres_scene()),
+        ("spherion", spherion_scene()),
+        ("first_fractal", first_fractal_scene()),
+        ("spherion_meets_fractalius", spherion_meets_fractalius_scene()),
+    ];
+
+    let mut group = c.benchmark_group("render");
+    group.throughput(Throughput::Elements(NUM_RAYS as u64));
+    group.sample_size(10);
+    for (name, world) in scenes.iter() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), world, |b, world| {
+            b.iter(|| render(world))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scene_renders);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+use std::time::SystemTime;
+
+use raytracing::math::math3::{Vec3};
+use raytracing::math::mesh::{extract_mesh};
+use raytracing::math::signed_distance::{SignedDistanceField};
+use raytracing::math::voxel_grid::{VoxelGrid};
+
+
+fn main() -> std::io::Result<()> {
+
+    let field = SignedDistanceField::SierpinskiTetrasphere(Vec3::new(0.0, 0.0, 0.0), 6);
+    // cells along the longest side of the field's bounds
+    let resolution = 192;
+    // obj, ply or stl, picked by extension
+    let savepaths = ["./output/fractal.obj", "./output/fractal.ply", "./output/fractal.stl"];
+    // also bake the field into a voxel grid of this resolution, for loading with `VoxelGrid::load_raw`
+    let voxel_resolution: Option<usize> = Some(128);
+
+    let start = SystemTime::now();
+    let (min, max) = field.bounds();
+    let mesh = extract_mesh(&field, min, max, resolution);
+    println!("Extracted {} vertices and {} triangles in {:?}", mesh.vertices.len(), mesh.triangles.len(),
+             start.elapsed().unwrap());
+    for savepath in savepaths.iter() {
+        mesh.save(savepath)?;
+        println!("Saved {}", savepath);
+    }
+
+    if let Some(voxel_resolution) = voxel_resolution {
+        let grid = VoxelGrid::from_sdf(&field, voxel_resolution);
+        grid.save_raw("./output/fractal.raw")?;
+        println!("Saved ./output/fractal.raw: {:?} samples, origin {:?}, voxel size {}",
+                 grid.dims, grid.origin, grid.voxel_size);
+    }
+
+    Ok(())
+}
@@ -2,11 +2,9 @@ extern crate image;
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::{Duration, SystemTime};
-use rand::Rng;
-use rand::prelude::ThreadRng;
+use std::time::{SystemTime};
 use rayon::prelude::*;
-use image::{RgbImage, ImageFormat};
+use image::{RgbImage};
 
 
 //mod math;
@@ -14,16 +12,28 @@ use image::{RgbImage, ImageFormat};
 //mod math;
 //mod super::math;
 //pub use crate::math;
-use raytracing::math::math3::{Vec3, dot, random_vec_in_unit_sphere, reflect, random_unit_vector};
-use raytracing::math::raytracing::{Ray, HitRecord, Hittable};
-
-use raytracing::math::imaging::{Camera, to_color, ray_color};
-use raytracing::math::materials::{Material};
+use raytracing::math::math3::{Vec3};
+use raytracing::math::raytracing::{Ray};
+
+use raytracing::math::imaging::{Camera, to_color, ray_color, ray_color_aov, ray_color_packet, ray_color_packet_aov,
+                                ray_color_spectral, step_heatmap_color,
+                                PreviewLighting, preview_color, turntable_lookfrom};
+use raytracing::math::aov::{AovBuffers, AovPixel, AovSample, SceneIds};
+use raytracing::math::denoise::{DenoiseSettings, denoise};
+use raytracing::math::film::{Film, Filter};
+use raytracing::math::sampling::{Sampler, SamplerKind};
+use raytracing::math::spectrum::{SampledWavelengths};
+use raytracing::math::simd::{RayPacket4};
+use raytracing::math::utils::{Float};
 
 //use crate::scenes;
 //mod super::scenes;
+// kept to hand for swapping into `world` below
+#[allow(unused_imports)]
 use raytracing::scenes::spherion::{spherion_scene};
+#[allow(unused_imports)]
 use raytracing::scenes::three_spheres::{three_spheres_scene};
+#[allow(unused_imports)]
 use raytracing::scenes::first_fractal::{first_fractal_scene};
 use raytracing::scenes::spherion_meets_fractalius::{spherion_meets_fractalius_scene};
 
@@ -37,16 +47,35 @@ fn main() -> std::io::Result<()> {
 
     let print_every_n_rows: u32 = 20;
     let image_width: u32 = 100;
-    let image_height: u32 = (image_width as f64 / aspect_ratio).floor() as u32;
+    let image_height: u32 = (image_width as Float / aspect_ratio).floor() as u32;
     let samples_per_pixel = 100;
     let max_depth = 5;
+    // pixel reconstruction filter: Box(0.5) is a plain per-pixel average, or Tent(1.0),
+    // Gaussian(1.5, 2.0), Mitchell(2.0, 1/3, 1/3), Lanczos(3.0)
+    let filter = Filter::Box(0.5);
+    // Independent, Stratified, Halton, Sobol or BlueNoise
+    let sampler_kind = SamplerKind::Independent;
+    // trace hero wavelength spectra instead of RGB, for dispersion through dielectrics
+    let spectral = false;
+    // render raymarching step counts instead of colour, saturating at this many steps
+    let debug_step_heatmap: Option<usize> = None;
+    // trace primary rays in packets of four (SIMD lanes with the `simd` feature)
+    let use_ray_packets = false;
+    // also write depth, normal, albedo, position, ID and sample count buffers
+    let write_aovs = false;
+    // run the AOV guided denoiser over the finished image
+    let denoiser: Option<DenoiseSettings> = None;
+    // keep the unfiltered image as well when denoising
+    let save_noisy = false;
+    // render this many direct light + SDF AO preview frames orbiting `lookat` and exit
+    let preview_turntable_frames: Option<usize> = None;
 
     println!("Image width: {}, Image Height: {}, Samples Per Pixel: {}, Status print every {} rows",
              image_width, image_height, samples_per_pixel, print_every_n_rows);
 
 
-    let vfov: f64 = 45.0;
-    let aspect_ratio: f64 = (image_width as f64) / (image_height as f64);
+    let vfov: Float = 45.0;
+    let aspect_ratio: Float = (image_width as Float) / (image_height as Float);
     let lookat: Vec3 = Vec3::new(-0.0, 0.0, 0.0);
     let lookfrom: Vec3 = Vec3::new(-3.3, 2.0, 1.75);
     let vup: Vec3 = Vec3::y_axis();
@@ -59,29 +88,131 @@ fn main() -> std::io::Result<()> {
     let thread_counter = Arc::new(AtomicUsize::new(0));
 
     let world = spherion_meets_fractalius_scene();
+    let ids = SceneIds::new(&world);
+
+    if let Some(num_frames) = preview_turntable_frames {
+        let lighting = PreviewLighting::new(Vec3::new(-0.5, 1.0, 0.3), Vec3::new(1.0, 0.95, 0.85));
+        let radius = (lookfrom - lookat).length();
+        for frame in 0..num_frames {
+            let frame_lookfrom = turntable_lookfrom(lookat, radius, lookfrom.y() - lookat.y(),
+                                                    frame as Float / num_frames as Float);
+            let frame_cam = Camera::new(frame_lookfrom, lookat, vup, vfov, aspect_ratio);
+            let frame_pixels: Vec<(u32, u32, Vec3)> = (0..image_width * image_height).into_par_iter().map(|index| {
+                let x = index % image_width;
+                let y = (index - x) / image_width;
+                let u = x as Float / (image_width - 1) as Float;
+                let v = ((image_height - 1) - y) as Float / (image_height - 1) as Float;
+                (x, y, preview_color(frame_cam.get_ray(u, v), &world, &lighting))
+            }).collect();
+
+            let mut img = RgbImage::new(image_width, image_height);
+            for (x, y, pixel_color) in frame_pixels {
+                img.put_pixel(x, y, to_color(pixel_color, 1));
+            }
+            img.save(format!("./output/turntable_{:03}.png", frame)).unwrap();
+            println!("Saved preview frame {} of {}", frame + 1, num_frames);
+        }
+        return Ok(());
+    }
+
+    // heatmap pixels go straight to their own pixel rather than being blended by the filter
+    let film = Film::new(image_width, image_height,
+                         if debug_step_heatmap.is_some() { Filter::Box(0.5) } else { filter });
     let now_render = SystemTime::now();
-    let result_vec: Vec<(u32, u32, Vec3)> = (0..image_width * image_height).into_par_iter().map(|index| {
-        let mut rng = rand::thread_rng();
+    let result_vec: Vec<(u32, u32, AovPixel)> = (0..image_width * image_height).into_par_iter().map(|index| {
+        let mut sampler = sampler_kind.build(samples_per_pixel, 0);
 
 
-        let x = index as u32 % image_width;
-        let y = (index as u32 - x) / image_width;
-        let i = x as f64;
-        let j = ((image_height - 1) - y) as f64;
+        let x = index % image_width;
+        let y = (index - x) / image_width;
+        let i = x as Float;
+        let j = ((image_height - 1) - y) as Float;
 
 
-        let pixel_color: Vec3 = (0..samples_per_pixel).map(|_| {
-            let u = (i + rng.gen_range(0.0, 1.0)) / (image_width - 1) as f64;
-            let v = (j + rng.gen_range(0.0, 1.0)) / (image_height - 1) as f64;
-            let ray = cam.get_ray(u, v);
-            ray_color(&mut rng, ray, &world, max_depth)
-        }).fold(Vec3::zero(), |x, y| x + y);
+        if let Some(max_steps) = debug_step_heatmap {
+            let ray = cam.get_ray(i / (image_width - 1) as Float, j / (image_height - 1) as Float);
+            film.add_sample(i + 0.5, y as Float + 0.5, step_heatmap_color(ray, &world, max_steps));
+            return (x, y, AovPixel::default());
+        }
+
+        // starts sample `s` of this pixel, returning its raster position on the film
+        // (y growing downwards) and its camera ray
+        let camera_sample = |sampler: &mut Box<dyn Sampler>, s: usize| {
+            sampler.start_pixel_sample(x, y, s);
+            let (jitter_x, jitter_y) = sampler.next_2d();
+            let (film_x, film_y) = (i + jitter_x, y as Float + jitter_y);
+            let u = film_x / (image_width - 1) as Float;
+            let v = (image_height as Float - film_y) / (image_height - 1) as Float;
+            ((film_x, film_y), cam.get_ray(u, v))
+        };
+
+        let mut aov_pixel = AovPixel::default();
+        let samples: Vec<((Float, Float), Vec3)> = if spectral {
+            (0..samples_per_pixel).map(|s| {
+                let (position, ray) = camera_sample(&mut sampler, s);
+                if write_aovs || denoiser.is_some() {
+                    aov_pixel.add(&AovSample::trace(&ray, &world, &ids));
+                }
+                let mut wavelengths = SampledWavelengths::sample_visible(sampler.next_1d());
+                let radiance = ray_color_spectral(&mut sampler, ray, &world, max_depth, &mut wavelengths);
+                (position, radiance.to_rgb(&wavelengths))
+            }).collect()
+        }
+        else if use_ray_packets {
+            // every lane keeps its own sampler so its bounces continue its sample's dimensions
+            let mut lane_samplers: Vec<Box<dyn Sampler>> = (0..RayPacket4::LANES)
+                .map(|_| sampler_kind.build(samples_per_pixel, 0)).collect();
+            (0..samples_per_pixel).collect::<Vec<usize>>().chunks(RayPacket4::LANES).flat_map(|indices| {
+                let lane_samplers = &mut lane_samplers[..indices.len()];
+                let (positions, rays): (Vec<(Float, Float)>, Vec<Ray>) = indices.iter().zip(lane_samplers.iter_mut())
+                    .map(|(s, lane_sampler)| camera_sample(lane_sampler, *s)).unzip();
+                let colors: Vec<Vec3> = if rays.len() == RayPacket4::LANES && (write_aovs || denoiser.is_some()) {
+                    ray_color_packet_aov(lane_samplers, &RayPacket4::new(&rays), &world, &ids, max_depth).into_iter()
+                        .map(|(color, aov_sample)| {
+                            aov_pixel.add(&aov_sample);
+                            color
+                        }).collect()
+                }
+                else if rays.len() == RayPacket4::LANES {
+                    ray_color_packet(lane_samplers, &RayPacket4::new(&rays), &world, max_depth)
+                }
+                else if write_aovs || denoiser.is_some() {
+                    rays.iter().zip(lane_samplers.iter_mut()).map(|(ray, lane_sampler)| {
+                        let (color, aov_sample) = ray_color_aov(lane_sampler, *ray, &world, &ids, max_depth);
+                        aov_pixel.add(&aov_sample);
+                        color
+                    }).collect()
+                }
+                else {
+                    rays.iter().zip(lane_samplers.iter_mut())
+                        .map(|(ray, lane_sampler)| ray_color(lane_sampler, *ray, &world, max_depth)).collect()
+                };
+                positions.into_iter().zip(colors).collect::<Vec<((Float, Float), Vec3)>>()
+            }).collect()
+        }
+        else if write_aovs || denoiser.is_some() {
+            (0..samples_per_pixel).map(|s| {
+                let (position, ray) = camera_sample(&mut sampler, s);
+                let (color, aov_sample) = ray_color_aov(&mut sampler, ray, &world, &ids, max_depth);
+                aov_pixel.add(&aov_sample);
+                (position, color)
+            }).collect()
+        }
+        else {
+            (0..samples_per_pixel).map(|s| {
+                let (position, ray) = camera_sample(&mut sampler, s);
+                (position, ray_color(&mut sampler, ray, &world, max_depth))
+            }).collect()
+        };
+        for ((film_x, film_y), color) in samples {
+            film.add_sample(film_x, film_y, color);
+        }
         let count = thread_counter.fetch_add(1, Ordering::SeqCst);
         if count as u32 % (print_every_n_rows * image_width) == 0 {
             let rows_remaining = image_height - ((count as u32) / image_width);
-            println!("Rows remaining: {}, Percent left to go: {}", rows_remaining, 100.0 * rows_remaining as f64 / image_height as f64);
+            println!("Rows remaining: {}, Percent left to go: {}", rows_remaining, 100.0 * rows_remaining as Float / image_height as Float);
         }
-        (x, y, pixel_color)
+        (x, y, aov_pixel)
     }).collect();
 
     match now_render.elapsed() {
@@ -98,13 +229,29 @@ fn main() -> std::io::Result<()> {
     let now_save = SystemTime::now();
 
     let mut img = RgbImage::new(image_width, image_height);
-    for (x, y, pixel_color) in result_vec {
-        img.put_pixel(x, y, to_color(pixel_color, samples_per_pixel));
+    let mut aovs = AovBuffers::new(image_width, image_height);
+    let pixel_means = film.pixels();
+    for (x, y, aov_pixel) in result_vec {
+        img.put_pixel(x, y, to_color(pixel_means[(y * image_width + x) as usize], 1));
+        aovs.set(x, y, aov_pixel);
     }
 
     let savepath =  "./output/throwaway";
+    // heatmaps write no AOVs to guide the denoiser, and their steps shouldn't be smoothed anyway
+    if let (Some(settings), None) = (&denoiser, debug_step_heatmap) {
+        if save_noisy {
+            img.save(format!("{}_noisy.{}", savepath, "png")).unwrap();
+        }
+        let denoised = denoise(&pixel_means, &aovs, settings);
+        for (index, pixel_color) in denoised.into_iter().enumerate() {
+            img.put_pixel(index as u32 % image_width, index as u32 / image_width, to_color(pixel_color, 1));
+        }
+    }
     img.save(format!("{}.{}", savepath, "png")).unwrap();
     img.save(format!("{}.{}", savepath, "jpg")).unwrap();
+    if write_aovs {
+        aovs.save(savepath)?;
+    }
 
     match now_save.elapsed() {
         Ok(elapsed) => {
@@ -4,4 +4,18 @@ pub mod raytracing;
 pub mod materials;
 pub mod utils;
 pub mod signed_distance;
-
+pub mod transform;
+pub mod simd;
+pub mod aov;
+pub mod denoise;
+pub mod film;
+pub mod sampling;
+pub mod spectrum;
+pub mod media;
+pub mod texture;
+pub mod aabb;
+pub mod primitives;
+pub mod bvh;
+pub mod voxel_grid;
+pub mod mesh;
+pub mod implicit;
@@ -0,0 +1,89 @@
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Ray};
+use crate::math::transform::{Transform};
+use crate::math::utils::{Float};
+
+/// Axis aligned bounding box.
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Box spanning two opposite corners, in any order.
+    pub fn new(a: Vec3, b: Vec3) -> Aabb {
+        Aabb { min: a.min(b), max: a.max(b) }
+    }
+
+    pub fn around_points(points: &[Vec3]) -> Aabb {
+        points.iter().skip(1).fold(Aabb::new(points[0], points[0]), |aabb, point| {
+            Aabb::new(aabb.min.min(*point), aabb.max.max(*point))
+        })
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// Grown by `delta` on every side, which gives flat shapes some thickness.
+    pub fn padded(&self, delta: Float) -> Aabb {
+        Aabb::new(self.min - Vec3::splat(delta), self.max + Vec3::splat(delta))
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> Float {
+        let e = self.extent();
+        2.0 * (e.x() * e.y() + e.y() * e.z() + e.z() * e.x())
+    }
+
+    /// Box around this one after `transform`, by transforming all eight corners.
+    pub fn transformed(&self, transform: &Transform) -> Aabb {
+        let corners: Vec<Vec3> = (0..8).map(|i| {
+            transform.transform_point(Vec3::new(
+                if i & 1 == 0 { self.min.x() } else { self.max.x() },
+                if i & 2 == 0 { self.min.y() } else { self.max.y() },
+                if i & 4 == 0 { self.min.z() } else { self.max.z() },
+            ))
+        }).collect();
+        Aabb::around_points(&corners)
+    }
+
+    /// Distance from `point` to the box, 0 inside it.  A lower bound on the distance
+    /// to anything the box contains.
+    pub fn distance(&self, point: Vec3) -> Float {
+        (self.min - point).max(point - self.max).max(Vec3::zero()).length()
+    }
+
+    /// Slab test: whether `ray` passes through the box between `t_min` and `t_max`.
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.hit_interval(ray, t_min, t_max).is_some()
+    }
+
+    /// Part of `ray` between `t_min` and `t_max` that lies inside the box, as the
+    /// entry and exit distances.
+    pub fn hit_interval(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float)> {
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for axis in 0..3 {
+            let inverse_dir = 1.0 / ray.dir[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_dir;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_dir;
+            if inverse_dir < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
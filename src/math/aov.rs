@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use image::{ImageBuffer, Luma, RgbImage};
+
+use crate::math::math3::{Vec3};
+use crate::math::materials::{Material};
+use crate::math::raytracing::{Ray, Hittable, HitRecord};
+use crate::math::utils::{Float, Real};
+
+/// Object and material IDs of a scene, numbered once when rendering starts so each
+/// sample only has to look up the pointers in its hit record.
+///
+/// Objects are the top level entries of the scene's list, with every leaf of a BVH
+/// counting as top level, numbered in tree order.  Materials are numbered depth first
+/// and compared by address, so every instance of a shared object reports the same ID.
+pub struct SceneIds {
+    objects: HashMap<usize, usize>,
+    materials: HashMap<usize, usize>,
+}
+
+impl SceneIds {
+    pub fn new(world: &Hittable) -> SceneIds {
+        let mut ids = SceneIds { objects: HashMap::new(), materials: HashMap::new() };
+        let mut next_object = 0;
+        match world {
+            Hittable::HittableList(hittables) => hittables.iter().for_each(|x| ids.number_objects(x, &mut next_object)),
+            _ => ids.number_objects(world, &mut next_object),
+        }
+        ids.number_materials(world);
+        ids
+    }
+
+    pub fn object_id(&self, hit_record: &HitRecord) -> Option<usize> {
+        hit_record.object().and_then(|object| self.objects.get(&(object as *const Hittable as usize)).copied())
+    }
+
+    pub fn material_id(&self, material: &Material) -> Option<usize> {
+        self.materials.get(&(material as *const Material as usize)).copied()
+    }
+
+    fn number_objects(&mut self, hittable: &Hittable, next_object: &mut usize) {
+        match hittable {
+            Hittable::Bvh(_, left, right) => {
+                self.number_objects(left, next_object);
+                self.number_objects(right, next_object);
+            },
+            _ => {
+                self.claim_object(hittable, *next_object);
+                *next_object += 1;
+            },
+        }
+    }
+
+    /// Gives `id` to `hittable` and everything inside it that a hit record can name;
+    /// instances name themselves, so their contents aren't visited.
+    fn claim_object(&mut self, hittable: &Hittable, id: usize) {
+        self.objects.insert(hittable as *const Hittable as usize, id);
+        match hittable {
+            Hittable::HittableList(hittables) => hittables.iter().for_each(|x| self.claim_object(x, id)),
+            Hittable::Bvh(_, left, right) => {
+                self.claim_object(left, id);
+                self.claim_object(right, id);
+            },
+            _ => {},
+        }
+    }
+
+    fn number_materials(&mut self, hittable: &Hittable) {
+        match hittable {
+            Hittable::HittableList(hittables) => hittables.iter().for_each(|x| self.number_materials(x)),
+            Hittable::Sphere(_, _, own) | Hittable::Plane(_, _, own) | Hittable::Quad(_, _, _, own)
+            | Hittable::Disc(_, _, _, own) | Hittable::Box(_, _, own) | Hittable::Cylinder(_, _, _, own)
+            | Hittable::Cone(_, _, _, own) | Hittable::Torus(_, _, _, own) | Hittable::Raymarcher(_, _, own)
+            | Hittable::Implicit(_, own) => {
+                let id = self.materials.len();
+                self.materials.entry(own as *const Material as usize).or_insert(id);
+            },
+            Hittable::Instance(object, _) => self.number_materials(object),
+            Hittable::Volume(_, _) | Hittable::Fog(_, _) => {},
+            Hittable::Bvh(_, left, right) => {
+                self.number_materials(left);
+                self.number_materials(right);
+            },
+        }
+    }
+}
+
+/// First-hit features of a single camera sample.
+#[derive(Debug, Copy, Clone)]
+pub struct AovSample {
+    /// Distance along the (unit length) camera ray, `None` for rays that hit nothing.
+    pub depth: Option<Float>,
+    pub normal: Vec3,
+    pub albedo: Vec3,
+    pub position: Vec3,
+    pub object_id: Option<usize>,
+    pub material_id: Option<usize>,
+}
+
+impl AovSample {
+    pub fn miss() -> AovSample {
+        AovSample {
+            depth: None,
+            normal: Vec3::zero(),
+            albedo: Vec3::zero(),
+            position: Vec3::zero(),
+            object_id: None,
+            material_id: None,
+        }
+    }
+
+    /// AOVs of `ray` traced on its own, for integrators that don't share their primary hit.
+    pub fn trace(ray: &Ray, hittable: &Hittable, ids: &SceneIds) -> AovSample {
+        AovSample::from_hit(hittable.hit(ray, 0.001, 100.0).as_ref(), ids)
+    }
+
+    pub fn from_hit(hit: Option<&HitRecord>, ids: &SceneIds) -> AovSample {
+        match hit {
+            Some(hit_record) => AovSample {
+                depth: Some(hit_record.t()),
+                normal: hit_record.normal,
+                albedo: hit_record.material.albedo(hit_record.point),
+                position: hit_record.point,
+                object_id: ids.object_id(hit_record),
+                material_id: ids.material_id(hit_record.material),
+            },
+            None => AovSample::miss(),
+        }
+    }
+}
+
+/// AOVs of one pixel accumulated over its samples.  Continuous features are averaged
+/// over the samples that hit something; IDs can't be averaged, so the first hit's are kept.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AovPixel {
+    depth_sum: Float,
+    normal_sum: Vec3,
+    albedo_sum: Vec3,
+    position_sum: Vec3,
+    hits: usize,
+    samples: usize,
+    object_id: Option<usize>,
+    material_id: Option<usize>,
+}
+
+impl AovPixel {
+    pub fn add(&mut self, sample: &AovSample) {
+        self.samples += 1;
+        if let Some(depth) = sample.depth {
+            self.hits += 1;
+            self.depth_sum += depth;
+            self.normal_sum += sample.normal;
+            self.albedo_sum += sample.albedo;
+            self.position_sum += sample.position;
+            if self.object_id.is_none() {
+                self.object_id = sample.object_id;
+                self.material_id = sample.material_id;
+            }
+        }
+    }
+
+    fn hit_mean<T: std::ops::Div<Float, Output = T> + Default>(&self, sum: T) -> T {
+        if self.hits == 0 { T::default() } else { sum / self.hits as Float }
+    }
+
+    /// Mean first-hit distance, 0 where every sample missed.
+    pub fn depth(&self) -> Float {
+        self.hit_mean(self.depth_sum)
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        let normal = self.normal_sum;
+        if normal.length_squared() > 0.0 { normal.unit_vector() } else { normal }
+    }
+
+    pub fn albedo(&self) -> Vec3 {
+        self.hit_mean(self.albedo_sum)
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.hit_mean(self.position_sum)
+    }
+
+    /// Fraction of samples that hit geometry.
+    pub fn coverage(&self) -> Float {
+        if self.samples == 0 { 0.0 } else { self.hits as Float / self.samples as Float }
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.samples
+    }
+
+    pub fn object_id(&self) -> Option<usize> {
+        self.object_id
+    }
+
+    pub fn material_id(&self) -> Option<usize> {
+        self.material_id
+    }
+}
+
+/// Full resolution AOV buffers, row major with row 0 at the top like `RgbImage`.
+pub struct AovBuffers {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<AovPixel>,
+}
+
+impl AovBuffers {
+    pub fn new(width: u32, height: u32) -> AovBuffers {
+        AovBuffers { width, height, pixels: vec![AovPixel::default(); (width * height) as usize] }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> &AovPixel {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, pixel: AovPixel) {
+        self.pixels[(y * self.width + x) as usize] = pixel;
+    }
+
+    /// Writes every AOV next to `path_prefix`.  The `image` crate can't write EXR, so
+    /// float data goes to PFM files and PNG previews are saved alongside; IDs and sample
+    /// counts are exact 16 bit PNGs with ID `n` stored as `n + 1` and 0 for background.
+    pub fn save(&self, path_prefix: &str) -> std::io::Result<()> {
+        let max_depth = self.pixels.iter().map(|p| p.depth()).fold(0.0, Float::max);
+
+        write_pfm(&format!("{}_depth.pfm", path_prefix), self.width, self.height,
+                  &self.pixels.iter().map(|p| Vec3::splat(p.depth())).collect::<Vec<Vec3>>(), true)?;
+        write_pfm(&format!("{}_normal.pfm", path_prefix), self.width, self.height,
+                  &self.pixels.iter().map(|p| p.normal()).collect::<Vec<Vec3>>(), false)?;
+        write_pfm(&format!("{}_albedo.pfm", path_prefix), self.width, self.height,
+                  &self.pixels.iter().map(|p| p.albedo()).collect::<Vec<Vec3>>(), false)?;
+        write_pfm(&format!("{}_position.pfm", path_prefix), self.width, self.height,
+                  &self.pixels.iter().map(|p| p.position()).collect::<Vec<Vec3>>(), false)?;
+
+        self.save_rgb(&format!("{}_depth.png", path_prefix), |p| {
+            let depth = if max_depth > 0.0 { p.depth() / max_depth } else { 0.0 };
+            Vec3::splat(if p.coverage() > 0.0 { 1.0 - depth } else { 0.0 })
+        })?;
+        self.save_rgb(&format!("{}_normal.png", path_prefix), |p| 0.5 * (p.normal() + Vec3::one()))?;
+        self.save_rgb(&format!("{}_albedo.png", path_prefix), |p| p.albedo())?;
+
+        self.save_u16(&format!("{}_object_id.png", path_prefix), |p| p.object_id().map_or(0, |id| id + 1))?;
+        self.save_u16(&format!("{}_material_id.png", path_prefix), |p| p.material_id().map_or(0, |id| id + 1))?;
+        self.save_u16(&format!("{}_samples.png", path_prefix), |p| p.sample_count())
+    }
+
+    fn save_rgb<F: Fn(&AovPixel) -> Vec3>(&self, path: &str, f: F) -> std::io::Result<()> {
+        let img = RgbImage::from_fn(self.width, self.height, |x, y| {
+            let c = f(self.get(x, y));
+            let channel = |v: Float| (255.0 * v.clamp(0.0, 1.0)).round() as u8;
+            image::Rgb([channel(c.x()), channel(c.y()), channel(c.z())])
+        });
+        img.save(path).map_err(image_error)
+    }
+
+    fn save_u16<F: Fn(&AovPixel) -> usize>(&self, path: &str, f: F) -> std::io::Result<()> {
+        let img: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            Luma([f(self.get(x, y)).min(u16::MAX as usize) as u16])
+        });
+        img.save(path).map_err(image_error)
+    }
+}
+
+fn image_error(e: image::ImageError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e)
+}
+
+/// Portable float map: little endian `f32`, rows stored bottom to top.  `grey` writes
+/// only the first channel.
+pub fn write_pfm(path: &str, width: u32, height: u32, pixels: &[Vec3], grey: bool) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "{}\n{} {}\n-1.0\n", if grey { "Pf" } else { "PF" }, width, height)?;
+    for row in pixels.chunks(width as usize).rev() {
+        for pixel in row {
+            let channels = if grey { 1 } else { 3 };
+            for c in 0..channels {
+                out.write_all(&(pixel[c].to_f64() as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::math::transform::{Transform};
+
+    #[test]
+    fn pfm_round_trip() {
+        let (width, height) = (3, 2);
+        let pixels: Vec<Vec3> = (0..width * height).map(|i| Vec3::new(i as Float, 0.5 * i as Float, -0.25)).collect();
+        let path = std::env::temp_dir().join(format!("aov_round_trip_{}.pfm", std::process::id()));
+        write_pfm(path.to_str().unwrap(), width, height, &pixels, false).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // negative scale marks little endian data
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], &header[..]);
+        let values: Vec<f32> = bytes[header.len()..].chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        assert_eq!(values.len(), 3 * pixels.len());
+        // rows run bottom to top
+        for (index, pixel) in values.chunks(3).enumerate() {
+            let (x, y) = (index as u32 % width, height - 1 - index as u32 / width);
+            let expected: Vec<f32> = (0..3).map(|c| pixels[(y * width + x) as usize][c].to_f64() as f32).collect();
+            assert_eq!(pixel, &expected[..]);
+        }
+    }
+
+    #[test]
+    fn ids_name_top_level_objects_and_shared_materials() {
+        let grey = || Material::Lambertian(Vec3::new(0.5, 0.5, 0.5));
+        let shared = Arc::new(Hittable::Sphere(Vec3::zero(), 0.5, grey()));
+        let world = Hittable::HittableList(vec![
+            Hittable::Sphere(Vec3::new(-3.0, 0.0, 0.0), 0.5, grey()),
+            Hittable::HittableList(vec![
+                Hittable::Sphere(Vec3::new(-1.0, 0.0, 0.0), 0.5, grey()),
+                Hittable::Sphere(Vec3::new(-1.0, 2.0, 0.0), 0.5, grey()),
+            ]),
+            Hittable::Instance(shared.clone(), Transform::translation(Vec3::new(1.0, 0.0, 0.0))),
+            Hittable::Instance(shared, Transform::translation(Vec3::new(3.0, 0.0, 0.0))),
+        ]);
+        let ids = SceneIds::new(&world);
+        let ids_at = |x: Float, y: Float| {
+            let sample = AovSample::trace(&Ray::new(Vec3::new(x, y, 5.0), -Vec3::z_axis()), &world, &ids);
+            (sample.object_id, sample.material_id)
+        };
+
+        assert_eq!(ids_at(-3.0, 0.0), (Some(0), Some(0)));
+        // a nested list is one object, with a material per sphere
+        assert_eq!(ids_at(-1.0, 0.0), (Some(1), Some(1)));
+        assert_eq!(ids_at(-1.0, 2.0), (Some(1), Some(2)));
+        // instances of one object are separate objects sharing its material
+        assert_eq!(ids_at(1.0, 0.0), (Some(2), Some(3)));
+        assert_eq!(ids_at(3.0, 0.0), (Some(3), Some(3)));
+        assert_eq!(ids_at(0.0, 5.0), (None, None));
+    }
+}
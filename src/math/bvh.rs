@@ -0,0 +1,119 @@
+use std::cmp::Ordering::{Equal};
+
+use crate::math::aabb::{Aabb};
+use crate::math::raytracing::{Hittable};
+use crate::math::utils::{Float};
+
+/// Bounding volume hierarchy over `hittables`, split by the surface area heuristic.
+/// Unbounded objects (planes, fog) can't go in the tree and stay in a list after it.
+pub fn build_bvh(hittables: Vec<Hittable>) -> Hittable {
+    let (bounded, mut unbounded): (Vec<Hittable>, Vec<Hittable>) =
+        hittables.into_iter().partition(|x| x.bounding_box().is_some());
+    if bounded.is_empty() {
+        return Hittable::HittableList(unbounded);
+    }
+
+    let boxed = bounded.into_iter().map(|x| (x.bounding_box().unwrap(), x)).collect();
+    let tree = build_node(boxed);
+    if unbounded.is_empty() {
+        tree
+    }
+    else {
+        unbounded.insert(0, tree);
+        Hittable::HittableList(unbounded)
+    }
+}
+
+fn build_node(mut objects: Vec<(Aabb, Hittable)>) -> Hittable {
+    if objects.len() == 1 {
+        return objects.pop().unwrap().1;
+    }
+
+    // sort along the axis the centroids spread furthest over
+    let centroids: Vec<_> = objects.iter().map(|(aabb, _)| aabb.centroid()).collect();
+    let spread = Aabb::around_points(&centroids).extent();
+    let axis = if spread.x() >= spread.y() && spread.x() >= spread.z() { 0 }
+               else if spread.y() >= spread.z() { 1 }
+               else { 2 };
+    objects.sort_by(|(a, _), (b, _)| a.centroid()[axis].partial_cmp(&b.centroid()[axis]).unwrap_or(Equal));
+
+    // splitting before object i costs about area(left) * i + area(right) * (n - i)
+    let n = objects.len();
+    let mut right_areas = vec![0.0; n];
+    let mut right = objects[n - 1].0;
+    for i in (1..n).rev() {
+        right = right.union(&objects[i].0);
+        right_areas[i] = right.surface_area();
+    }
+    let mut left = objects[0].0;
+    let mut best: (Float, usize) = (Float::INFINITY, 1);
+    for (i, right_area) in right_areas.iter().enumerate().skip(1) {
+        let cost = left.surface_area() * i as Float + right_area * (n - i) as Float;
+        if cost < best.0 {
+            best = (cost, i);
+        }
+        left = left.union(&objects[i].0);
+    }
+
+    let aabb = left;
+    let right_objects = objects.split_off(best.1);
+    Hittable::Bvh(aabb, Box::new(build_node(objects)), Box::new(build_node(right_objects)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use crate::math::materials::{Material};
+    use crate::math::math3::{Vec3};
+    use crate::math::raytracing::{Ray};
+    use crate::math::signed_distance::{SignedDistanceField, MarchSettings};
+
+    /// Random scene of every kind of object the tree takes or leaves out, built twice
+    /// from the same positions.  Each object gets its own colour to tell hits apart.
+    fn random_scenes() -> (Vec<Hittable>, Vec<Hittable>) {
+        let mut rng = rand::thread_rng();
+        let centres: Vec<(Vec3, Float)> = (0..60)
+            .map(|_| (Vec3::random_range(&mut rng, -5.0, 5.0), rng.gen_range(0.1, 0.8)))
+            .collect();
+        let build = || -> Vec<Hittable> {
+            let mut hittables: Vec<Hittable> = centres.iter().enumerate().map(|(i, &(centre, size))| {
+                let material = Material::Lambertian(Vec3::new(i as Float / 100.0, 0.5, 0.5));
+                match i % 5 {
+                    0 => Hittable::Sphere(centre, size, material),
+                    1 => Hittable::Box(centre - Vec3::splat(size), centre + Vec3::new(size, 0.5 * size, size), material),
+                    2 => Hittable::Quad(centre, Vec3::new(size, 0.0, 0.3), Vec3::new(0.0, size, -0.2), material),
+                    3 => Hittable::Disc(centre, Vec3::new(0.3, 1.0, -0.5), size, material),
+                    _ => Hittable::Cylinder(centre, 0.5 * size, size, material),
+                }
+            }).collect();
+            hittables.push(Hittable::Plane(Vec3::new(0.0, -6.0, 0.0), Vec3::y_axis(), Material::Lambertian(Vec3::new(0.7, 0.0, 0.0))));
+            hittables.push(Hittable::Plane(Vec3::new(0.0, 0.0, -7.0), Vec3::new(0.1, 0.0, 1.0), Material::Lambertian(Vec3::new(0.8, 0.0, 0.0))));
+            hittables.push(Hittable::Raymarcher(SignedDistanceField::Sphere(Vec3::new(2.0, 1.0, -1.0), 1.2),
+                                                MarchSettings::new(256, 1e-7), Material::Lambertian(Vec3::new(0.9, 0.0, 0.0))));
+            hittables
+        };
+        (build(), build())
+    }
+
+    #[test]
+    fn bvh_hits_like_the_flat_list() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..5 {
+            let (flat, tree) = random_scenes();
+            let (flat, tree) = (Hittable::HittableList(flat), build_bvh(tree));
+            for _ in 0..2000 {
+                let ray = Ray::new(Vec3::random_range(&mut rng, -8.0, 8.0), Vec3::random_range(&mut rng, -1.0, 1.0));
+                match (flat.hit(&ray, 0.001, 100.0), tree.hit(&ray, 0.001, 100.0)) {
+                    (Some(expected), Some(found)) => {
+                        assert!((expected.t() - found.t()).abs() < 1e-4, "{:?}: {} vs {}", ray, expected.t(), found.t());
+                        assert!(expected.normal.approx_eq(found.normal, 1e-4), "{:?}", ray);
+                        assert_eq!(expected.material.albedo(Vec3::zero()), found.material.albedo(Vec3::zero()), "{:?}", ray);
+                    },
+                    (None, None) => {},
+                    (expected, found) => panic!("{:?}: list hit {}, tree hit {}", ray, expected.is_some(), found.is_some()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use rayon::prelude::*;
+
+use crate::math::math3::{Vec3};
+use crate::math::aov::{AovBuffers, AovPixel};
+use crate::math::utils::{Float};
+
+/// B3 spline taps of the à-trous wavelet kernel.
+const KERNEL: [Float; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+pub struct DenoiseSettings {
+    /// Number of à-trous passes; pass `i` spaces its taps `2^i` pixels apart, so five
+    /// passes cover a 125 pixel wide footprint.
+    pub iterations: usize,
+    /// Colour edge stopping, halved after every pass as the image gets smoother.
+    pub sigma_color: Float,
+    pub sigma_normal: Float,
+    /// Relative depth difference tolerated per pixel of tap distance.
+    pub sigma_depth: Float,
+    /// Filter irradiance (colour / albedo) and multiply the albedo back afterwards, so
+    /// texture and material edges stay sharp.
+    pub demodulate_albedo: bool,
+}
+
+impl DenoiseSettings {
+    pub fn new() -> DenoiseSettings {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_color: 0.5,
+            sigma_normal: 0.1,
+            sigma_depth: 0.05,
+            demodulate_albedo: true,
+        }
+    }
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> DenoiseSettings {
+        DenoiseSettings::new()
+    }
+}
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010) guided by the normal,
+/// depth and albedo AOVs.  `color` holds per pixel means in the same row order as `aovs`.
+pub fn denoise(color: &[Vec3], aovs: &AovBuffers, settings: &DenoiseSettings) -> Vec<Vec3> {
+    let albedo_floor = 0.01;
+    let demodulate = |c: Vec3, p: &AovPixel| {
+        if settings.demodulate_albedo && p.coverage() > 0.0 { c / p.albedo().max(Vec3::splat(albedo_floor)) } else { c }
+    };
+    let remodulate = |c: Vec3, p: &AovPixel| {
+        if settings.demodulate_albedo && p.coverage() > 0.0 { c * p.albedo().max(Vec3::splat(albedo_floor)) } else { c }
+    };
+
+    let mut filtered: Vec<Vec3> = color.iter().zip(aovs.pixels.iter()).map(|(c, p)| demodulate(*c, p)).collect();
+    let mut sigma_color = settings.sigma_color;
+    for iteration in 0..settings.iterations {
+        filtered = atrous_pass(&filtered, aovs, 1 << iteration, sigma_color, settings);
+        sigma_color *= 0.5;
+    }
+    filtered.iter().zip(aovs.pixels.iter()).map(|(c, p)| remodulate(*c, p)).collect()
+}
+
+fn atrous_pass(input: &[Vec3], aovs: &AovBuffers, step: i64, sigma_color: Float,
+               settings: &DenoiseSettings) -> Vec<Vec3> {
+    let (width, height) = (aovs.width as i64, aovs.height as i64);
+    (0..width * height).into_par_iter().map(|index| {
+        let (x, y) = (index % width, index / width);
+        let center_color = input[index as usize];
+        let center = &aovs.pixels[index as usize];
+
+        let mut sum = Vec3::zero();
+        let mut weight_sum = 0.0;
+        for (j, ky) in KERNEL.iter().enumerate() {
+            for (i, kx) in KERNEL.iter().enumerate() {
+                let dx = (i as i64 - 2) * step;
+                let dy = (j as i64 - 2) * step;
+                let (qx, qy) = (x + dx, y + dy);
+                if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                    continue;
+                }
+                let q = (qy * width + qx) as usize;
+                let tap = &aovs.pixels[q];
+
+                let color_distance = (input[q] - center_color).length_squared();
+                let normal_distance = (tap.normal() - center.normal()).length_squared();
+                let tap_distance = ((dx * dx + dy * dy) as Float).sqrt();
+                let depth_distance = (tap.depth() - center.depth()).abs()
+                    / (settings.sigma_depth * center.depth().max(0.001) * tap_distance.max(1.0));
+
+                let weight = kx * ky
+                    * (-color_distance / (sigma_color * sigma_color)).exp()
+                    * (-normal_distance / (settings.sigma_normal * settings.sigma_normal)).exp()
+                    * (-depth_distance).exp();
+                sum += weight * input[q];
+                weight_sum += weight;
+            }
+        }
+        // the centre tap always has a positive weight
+        sum / weight_sum
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::aov::{AovSample};
+
+    const WIDTH: u32 = 16;
+    const HEIGHT: u32 = 12;
+
+    /// Guides with one hit per pixel, taken from `guide(x, y)` as (normal, albedo).
+    fn guides<F: Fn(u32, u32) -> (Vec3, Vec3)>(guide: F) -> AovBuffers {
+        let mut aovs = AovBuffers::new(WIDTH, HEIGHT);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let (normal, albedo) = guide(x, y);
+                let mut pixel = AovPixel::default();
+                pixel.add(&AovSample { depth: Some(2.0), normal, albedo, position: Vec3::zero(),
+                                       object_id: Some(0), material_id: Some(0) });
+                aovs.set(x, y, pixel);
+            }
+        }
+        aovs
+    }
+
+    fn image<F: Fn(u32, u32) -> Vec3>(color: F) -> Vec<Vec3> {
+        (0..WIDTH * HEIGHT).map(|i| color(i % WIDTH, i / WIDTH)).collect()
+    }
+
+    fn assert_images_match(actual: &[Vec3], expected: &[Vec3], tolerance: Float) {
+        for (index, (a, e)) in actual.iter().zip(expected).enumerate() {
+            assert!(a.approx_eq(*e, tolerance), "pixel {}: {:?} vs {:?}", index, a, e);
+        }
+    }
+
+    #[test]
+    fn constant_image_stays_constant() {
+        let aovs = guides(|_, _| (Vec3::z_axis(), Vec3::splat(0.5)));
+        let color = image(|_, _| Vec3::new(0.3, 0.6, 0.1));
+        assert_images_match(&denoise(&color, &aovs, &DenoiseSettings::new()), &color, 1e-4);
+    }
+
+    #[test]
+    fn normal_and_albedo_edges_are_kept() {
+        let settings = DenoiseSettings { demodulate_albedo: false, ..DenoiseSettings::new() };
+        // colours close enough that only the normal guide stops them blending
+        let aovs = guides(|x, _| (if x < WIDTH / 2 { Vec3::z_axis() } else { Vec3::x_axis() }, Vec3::splat(0.5)));
+        let color = image(|x, _| Vec3::splat(if x < WIDTH / 2 { 0.4 } else { 0.6 }));
+        assert_images_match(&denoise(&color, &aovs, &settings), &color, 1e-3);
+
+        // a texture edge on a flat, evenly lit surface is all albedo, so filtering the
+        // demodulated irradiance leaves it untouched
+        let albedo = |x: u32| Vec3::splat(if x < WIDTH / 2 { 0.2 } else { 0.8 });
+        let aovs = guides(|x, _| (Vec3::z_axis(), albedo(x)));
+        let color = image(|x, _| albedo(x));
+        assert_images_match(&denoise(&color, &aovs, &DenoiseSettings::new()), &color, 1e-4);
+    }
+
+    #[test]
+    fn zero_albedo_demodulation_round_trips() {
+        // black albedo (an emitter, say) still carries colour, which must come back out
+        let aovs = guides(|x, y| (Vec3::z_axis(), if (x + y) % 3 == 0 { Vec3::zero() } else { Vec3::splat(0.5) }));
+        let color = image(|_, _| Vec3::new(0.005, 0.002, 0.008));
+        let denoised = denoise(&color, &aovs, &DenoiseSettings::new());
+        assert!(denoised.iter().all(|c| c.x().is_finite() && c.y().is_finite() && c.z().is_finite()));
+
+        let aovs = guides(|_, _| (Vec3::z_axis(), Vec3::zero()));
+        assert_images_match(&denoise(&color, &aovs, &DenoiseSettings::new()), &color, 1e-6);
+    }
+}
@@ -0,0 +1,216 @@
+use std::sync::Mutex;
+
+use crate::math::math3::{Vec3};
+use crate::math::utils::{Float};
+
+/// Separable pixel reconstruction filters.  The first field is the radius in pixels.
+#[derive(Debug, Copy, Clone)]
+pub enum Filter {
+    /// Unweighted average; radius 0.5 is plain per-pixel averaging.
+    Box(Float),
+    Tent(Float),
+    /// Gaussian with falloff `alpha`, shifted down to reach zero at the radius.
+    Gaussian(Float, Float),
+    /// Mitchell-Netravali with parameters `b` and `c`; b = c = 1/3 is the usual choice.
+    Mitchell(Float, Float, Float),
+    /// Lanczos windowed sinc with as many lobes as the radius.
+    Lanczos(Float),
+}
+
+impl Filter {
+    pub fn radius(&self) -> Float {
+        match self {
+            Filter::Box(radius) | Filter::Tent(radius) | Filter::Gaussian(radius, _)
+            | Filter::Mitchell(radius, _, _) | Filter::Lanczos(radius) => *radius,
+        }
+    }
+
+    /// Filter weight for a sample `dx`, `dy` pixels away from the pixel centre.
+    pub fn evaluate(&self, dx: Float, dy: Float) -> Float {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: Float) -> Float {
+        let x = x.abs();
+        match self {
+            Filter::Box(radius) => if x <= *radius { 1.0 } else { 0.0 },
+
+            Filter::Tent(radius) => (1.0 - x / *radius).max(0.0),
+
+            Filter::Gaussian(radius, alpha) => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            },
+
+            Filter::Mitchell(radius, b, c) => {
+                let x = 2.0 * x / *radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b)) / 6.0
+                }
+                else if x < 2.0 {
+                    ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+                }
+                else { 0.0 }
+            },
+
+            Filter::Lanczos(radius) => {
+                if x >= *radius { 0.0 } else { sinc(x) * sinc(x / *radius) }
+            },
+        }
+    }
+}
+
+fn sinc(x: Float) -> Float {
+    if x < 1e-5 {
+        1.0
+    }
+    else {
+        let pi_x = std::f64::consts::PI as Float * x;
+        pi_x.sin() / pi_x
+    }
+}
+
+/// Total filter weight a pixel needs before its colour is trusted.
+const MIN_PIXEL_WEIGHT: Float = 1e-4;
+
+/// Image plane that samples are splatted onto: each sample adds its filter weighted
+/// colour to every pixel within the filter radius, and pixels are normalised by their
+/// total weight.  Rows are locked separately so render threads can splat concurrently.
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    filter: Filter,
+    rows: Vec<Mutex<Vec<(Vec3, Float)>>>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, filter: Filter) -> Film {
+        Film {
+            width,
+            height,
+            filter,
+            rows: (0..height).map(|_| Mutex::new(vec![(Vec3::zero(), 0.0); width as usize])).collect(),
+        }
+    }
+
+    /// `film_x`, `film_y` are continuous raster coordinates with the origin at the top
+    /// left corner, so pixel (x, y) is centred at (x + 0.5, y + 0.5).
+    pub fn add_sample(&self, film_x: Float, film_y: Float, color: Vec3) {
+        let radius = self.filter.radius();
+        let x0 = ((film_x - 0.5 - radius).ceil() as i64).max(0);
+        let y0 = ((film_y - 0.5 - radius).ceil() as i64).max(0);
+        let x1 = ((film_x - 0.5 + radius).floor() as i64).min(self.width as i64 - 1);
+        let y1 = ((film_y - 0.5 + radius).floor() as i64).min(self.height as i64 - 1);
+
+        for y in y0..=y1 {
+            let weight_y = self.filter.evaluate_1d(y as Float + 0.5 - film_y);
+            if weight_y == 0.0 {
+                continue;
+            }
+            let mut row = self.rows[y as usize].lock().unwrap();
+            for x in x0..=x1 {
+                let weight = weight_y * self.filter.evaluate_1d(x as Float + 0.5 - film_x);
+                let pixel = &mut row[x as usize];
+                pixel.0 += weight * color;
+                pixel.1 += weight;
+            }
+        }
+    }
+
+    /// Filtered colour of pixel (x, y), black where no sample reached it.  Filters with
+    /// negative lobes can leave a pixel with a tiny or negative total weight, which would
+    /// blow its colour up or flip it, so those are black as well.
+    pub fn pixel(&self, x: u32, y: u32) -> Vec3 {
+        let (sum, weight) = self.rows[y as usize].lock().unwrap()[x as usize];
+        if weight > MIN_PIXEL_WEIGHT { sum / weight } else { Vec3::zero() }
+    }
+
+    /// All pixels row by row from the top.
+    pub fn pixels(&self) -> Vec<Vec3> {
+        (0..self.height).flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.pixel(x, y))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters() -> [Filter; 5] {
+        [Filter::Box(0.5), Filter::Tent(1.5), Filter::Gaussian(1.5, 2.0),
+         Filter::Mitchell(2.0, 1.0 / 3.0, 1.0 / 3.0), Filter::Lanczos(3.0)]
+    }
+
+    #[test]
+    fn filters_vanish_outside_their_radius() {
+        for filter in filters().iter() {
+            let radius = filter.radius();
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{:?}", filter);
+            for offset in [1e-3, 0.1, 1.0, 5.0].iter() {
+                assert_eq!(filter.evaluate(radius + offset, 0.0), 0.0, "{:?}", filter);
+                assert_eq!(filter.evaluate(0.0, -radius - offset), 0.0, "{:?}", filter);
+                assert_eq!(filter.evaluate(radius + offset, radius + offset), 0.0, "{:?}", filter);
+            }
+        }
+    }
+
+    #[test]
+    fn filters_reproduce_a_constant_image() {
+        // however the weights are shaped, normalising by them keeps a flat image flat
+        let color = Vec3::new(0.2, 0.5, 0.9);
+        for filter in filters().iter() {
+            let film = Film::new(12, 10, *filter);
+            for i in 0..12 * 10 * 16 {
+                let (film_x, film_y) = ((i % 48) as Float * 0.25 + 0.125, (i / 48) as Float * 0.25 + 0.125);
+                film.add_sample(film_x, film_y, color);
+            }
+            for (index, pixel) in film.pixels().iter().enumerate() {
+                assert!(pixel.approx_eq(color, 1e-4), "{:?} pixel {}: {:?}", filter, index, pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn samples_splat_onto_pixels_within_the_radius() {
+        let color = Vec3::new(1.0, 0.5, 0.25);
+        // a box of radius 0.5 keeps each sample in its own pixel
+        let film = Film::new(5, 5, Filter::Box(0.5));
+        film.add_sample(2.3, 1.8, color);
+        for (index, pixel) in film.pixels().iter().enumerate() {
+            let expected = if index == 5 + 2 { color } else { Vec3::zero() };
+            assert!(pixel.approx_eq(expected, 1e-6), "pixel {}: {:?}", index, pixel);
+        }
+
+        // a tent of radius 1.5 reaches the neighbouring pixels but no further
+        let film = Film::new(5, 5, Filter::Tent(1.5));
+        film.add_sample(2.5, 2.5, color);
+        for y in 0..5 {
+            for x in 0..5 {
+                let near = (x as i32 - 2).abs() <= 1 && (y as i32 - 2).abs() <= 1;
+                let expected = if near { color } else { Vec3::zero() };
+                assert!(film.pixel(x, y).approx_eq(expected, 1e-6), "pixel ({}, {})", x, y);
+            }
+        }
+        // two samples blend by their weights: the centre pixel is weighted 1 for the
+        // sample on it and 1/3 for the one a pixel away
+        film.add_sample(3.5, 2.5, Vec3::zero());
+        assert!(film.pixel(2, 2).approx_eq(color * 0.75, 1e-4), "{:?}", film.pixel(2, 2));
+    }
+
+    #[test]
+    fn negative_or_tiny_weights_give_black() {
+        // 1.5 pixels from a Mitchell sample is inside its negative lobe
+        let film = Film::new(10, 10, Filter::Mitchell(2.0, 1.0 / 3.0, 1.0 / 3.0));
+        film.add_sample(5.0, 5.5, Vec3::one());
+        assert!(film.pixel(4, 5).x() > 0.0);
+        assert_eq!(film.pixel(6, 5), Vec3::zero());
+
+        // the very edge of a tent's support
+        let film = Film::new(10, 10, Filter::Tent(1.0));
+        film.add_sample(5.5, 6.49999, Vec3::one());
+        assert_eq!(film.pixel(5, 5), Vec3::zero());
+        assert!(film.pixel(5, 6).approx_eq(Vec3::one(), 1e-6));
+    }
+}
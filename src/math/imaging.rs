@@ -1,8 +1,12 @@
-use rand::prelude::ThreadRng;
+use std::ops::Mul;
 
-use crate::math::math3::{Vec3, cross};
-use crate::math::raytracing::{Ray, Hittable};
-use crate::math::utils::{degrees_to_radians};
+use crate::math::math3::{Vec3, cross, dot};
+use crate::math::aov::{AovSample, SceneIds};
+use crate::math::raytracing::{Ray, Hittable, HitRecord};
+use crate::math::simd::{SimdFloat, RayPacket};
+use crate::math::sampling::{Sampler};
+use crate::math::spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::math::utils::{degrees_to_radians, Float, TAU};
 
 pub struct Camera {
     origin: Vec3,
@@ -12,9 +16,9 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, vfov: f64, aspect_ratio: f64) -> Camera {
-        let theta: f64 = degrees_to_radians(vfov);
-        let h: f64 = (theta / 2.0).tan();
+    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, vfov: Float, aspect_ratio: Float) -> Camera {
+        let theta: Float = degrees_to_radians(vfov);
+        let h: Float = (theta / 2.0).tan();
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
 
@@ -34,7 +38,7 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
+    pub fn get_ray(&self, u: Float, v: Float) -> Ray {
         Ray::new(self.origin, self.lower_left_corner - self.origin
             + u * self.horizontal + v * self.vertical)
     }
@@ -42,7 +46,7 @@ impl Camera {
 
 
 pub fn to_color(pixel_color: Vec3, samples_per_pixel: i32) -> image::Rgb<u8> {
-    let pixel_color_scaled_gamma_corrected = (pixel_color / (samples_per_pixel as f64)).sqrt();
+    let pixel_color_scaled_gamma_corrected = (pixel_color / (samples_per_pixel as Float)).sqrt();
 
     let r = (256.0 * clamp(pixel_color_scaled_gamma_corrected.x(), 0.0, 0.999)).floor() as u8;
     let g = (256.0 * clamp(pixel_color_scaled_gamma_corrected.y(), 0.0, 0.999)).floor() as u8;
@@ -51,30 +55,312 @@ pub fn to_color(pixel_color: Vec3, samples_per_pixel: i32) -> image::Rgb<u8> {
     image::Rgb([r, g, b])
 }
 
-fn clamp(x: f64, min: f64, max: f64) -> f64 {
+fn clamp(x: Float, min: Float, max: Float) -> Float {
     if x < min {min} else if x > max {max} else {x}
 }
 
-pub fn ray_color(rng_source: &mut ThreadRng, ray: Ray, hittable: &Hittable, depth: i32) -> Vec3 {
-    if depth <= 0 {
+/// What a path carries from the camera, so one shading step serves the RGB and the
+/// spectral integrators: how RGB colours convert to it and how surfaces scatter it.
+trait Throughput {
+    type Radiance: Copy + Mul<Output = Self::Radiance>;
+
+    fn zero() -> Self::Radiance;
+    fn of_rgb(&self, rgb: Vec3) -> Self::Radiance;
+    fn scatter<S: Sampler + ?Sized>(&mut self, sampler: &mut S, ray: &Ray, hit_record: HitRecord)
+        -> Option<(Ray, Self::Radiance)>;
+}
+
+/// Linear RGB, as `ray_color` traces.
+struct RgbThroughput;
+
+impl Throughput for RgbThroughput {
+    type Radiance = Vec3;
+
+    fn zero() -> Vec3 {
         Vec3::zero()
     }
+
+    fn of_rgb(&self, rgb: Vec3) -> Vec3 {
+        rgb
+    }
+
+    fn scatter<S: Sampler + ?Sized>(&mut self, sampler: &mut S, ray: &Ray, hit_record: HitRecord) -> Option<(Ray, Vec3)> {
+        hit_record.material.scatter(sampler, ray, hit_record)
+    }
+}
+
+/// Radiance at a path's sampled wavelengths, which a dispersive surface may cut down
+/// to the hero wavelength.
+struct SpectralThroughput<'w> {
+    wavelengths: &'w mut SampledWavelengths,
+}
+
+impl Throughput for SpectralThroughput<'_> {
+    type Radiance = SampledSpectrum;
+
+    fn zero() -> SampledSpectrum {
+        SampledSpectrum::zero()
+    }
+
+    fn of_rgb(&self, rgb: Vec3) -> SampledSpectrum {
+        SampledSpectrum::from_rgb(rgb, self.wavelengths)
+    }
+
+    fn scatter<S: Sampler + ?Sized>(&mut self, sampler: &mut S, ray: &Ray, hit_record: HitRecord)
+        -> Option<(Ray, SampledSpectrum)> {
+        hit_record.material.scatter_spectral(sampler, ray, hit_record, self.wavelengths)
+    }
+}
+
+pub fn ray_color<S: Sampler + ?Sized>(sampler: &mut S, ray: Ray, hittable: &Hittable, depth: i32) -> Vec3 {
+    trace_path(&mut RgbThroughput, sampler, ray, hittable, depth)
+}
+
+/// `ray_color` that also returns the AOVs of the primary hit, sharing its intersection.
+pub fn ray_color_aov<S: Sampler + ?Sized>(sampler: &mut S, ray: Ray, hittable: &Hittable, ids: &SceneIds,
+                                          depth: i32) -> (Vec3, AovSample) {
+    if depth <= 0 {
+        return (Vec3::zero(), AovSample::miss());
+    }
+    let hit = hittable.hit(&ray, 0.001, 100.0);
+    let aov = AovSample::from_hit(hit.as_ref(), ids);
+    (shade_hit(&mut RgbThroughput, sampler, ray, hit, hittable, depth), aov)
+}
+
+/// Spectral `ray_color`: radiance at the sampled wavelengths, which a dispersive
+/// dielectric may cut down to the hero wavelength along the way.
+pub fn ray_color_spectral<S: Sampler + ?Sized>(sampler: &mut S, ray: Ray, hittable: &Hittable, depth: i32,
+                                              wavelengths: &mut SampledWavelengths) -> SampledSpectrum {
+    trace_path(&mut SpectralThroughput { wavelengths }, sampler, ray, hittable, depth)
+}
+
+/// `ray_color` for a packet of primary rays: the first hits are found for all lanes at
+/// once, after which each lane's bounces continue as scalar rays drawing from that
+/// lane's sampler.
+pub fn ray_color_packet<L: SimdFloat, S: Sampler>(samplers: &mut [S], packet: &RayPacket<L>,
+                                                  hittable: &Hittable, depth: i32) -> Vec<Vec3> {
+    if depth <= 0 {
+        return vec![Vec3::zero(); L::LANES];
+    }
+    hittable.hit_packet(packet, 0.001, 100.0).into_iter().enumerate()
+        .map(|(lane, hit)| shade_hit(&mut RgbThroughput, &mut samplers[lane], packet.ray(lane), hit, hittable, depth))
+        .collect()
+}
+
+/// `ray_color_packet` that also returns the AOVs of each lane's primary hit.
+pub fn ray_color_packet_aov<L: SimdFloat, S: Sampler>(samplers: &mut [S], packet: &RayPacket<L>, hittable: &Hittable,
+                                                      ids: &SceneIds, depth: i32) -> Vec<(Vec3, AovSample)> {
+    if depth <= 0 {
+        return vec![(Vec3::zero(), AovSample::miss()); L::LANES];
+    }
+    hittable.hit_packet(packet, 0.001, 100.0).into_iter().enumerate()
+        .map(|(lane, hit)| {
+            let aov = AovSample::from_hit(hit.as_ref(), ids);
+            (shade_hit(&mut RgbThroughput, &mut samplers[lane], packet.ray(lane), hit, hittable, depth), aov)
+        })
+        .collect()
+}
+
+fn trace_path<T: Throughput, S: Sampler + ?Sized>(throughput: &mut T, sampler: &mut S, ray: Ray, hittable: &Hittable,
+                                                  depth: i32) -> T::Radiance {
+    if depth <= 0 {
+        return T::zero();
+    }
+    // TODO: replace this hard coded t_max limit with a better way to terminate raymarching
+    // was before Float::INFINITY
+    let hit = hittable.hit(&ray, 0.001, 100.0);
+    shade_hit(throughput, sampler, ray, hit, hittable, depth)
+}
+
+/// Radiance arriving along `ray` given its first hit (if any): scattering in a medium
+/// on the way there, or at the surface, or the sky beyond.
+fn shade_hit<T: Throughput, S: Sampler + ?Sized>(throughput: &mut T, sampler: &mut S, ray: Ray, hit: Option<HitRecord>,
+                                                 hittable: &Hittable, depth: i32) -> T::Radiance {
+    // the ray may scatter in a medium before it gets to the surface (or the sky)
+    let t_surface = hit.as_ref().map_or(100.0, |hit_record| hit_record.t());
+    if let Some(event) = hittable.sample_medium(sampler, &ray, 0.001, t_surface) {
+        let scattered = Ray::new(event.point, event.medium.sample_phase(sampler, ray.dir));
+        return throughput.of_rgb(event.medium.albedo()) * trace_path(throughput, sampler, scattered, hittable, depth - 1);
+    }
+    if let Some(hit_record) = hit {
+        match scatter_surface(throughput, sampler, ray, hit_record, hittable) {
+            Some((scattered, weight)) => weight * trace_path(throughput, sampler, scattered, hittable, depth - 1),
+            None => T::zero(),
+        }
+    } else {
+        throughput.of_rgb(sky_color(ray.dir))
+    }
+}
+
+/// Most scattering events a subsurface random walk may take before it counts as lost.
+const MAX_WALK_STEPS: usize = 256;
+
+/// Most times the boundary of a subsurface material may reflect a walk back inside
+/// before it counts as lost.
+const MAX_INTERNAL_REFLECTIONS: usize = 64;
+
+/// Scatters a ray off the surface it hit, with the weight it carries away.  A ray
+/// inside a subsurface material first walks through the interior, and whenever the
+/// boundary reflects it back in (total internal reflection, or the reflected share
+/// of a dielectric) the walk carries on; those re-entries have their own budget
+/// rather than counting against the path depth.
+fn scatter_surface<'a, T: Throughput, S: Sampler + ?Sized>(throughput: &mut T, sampler: &mut S, mut ray: Ray,
+                                                            mut hit_record: HitRecord<'a>, hittable: &'a Hittable)
+                                                            -> Option<(Ray, T::Radiance)> {
+    let mut weight = throughput.of_rgb(Vec3::one());
+    for _ in 0..MAX_INTERNAL_REFLECTIONS {
+        let (walked, walk_hit, walk_albedo) = subsurface_walk(sampler, ray, hit_record, hittable)?;
+        let (scattered, attenuation) = throughput.scatter(sampler, &walked, walk_hit)?;
+        weight = weight * throughput.of_rgb(walk_albedo) * attenuation;
+        // the normal of a hit from inside faces inwards
+        let reflected_inside = !walk_hit.front_face() && walk_hit.material.interior_medium().is_some()
+            && dot(scattered.dir, walk_hit.normal) > 0.0;
+        if !reflected_inside {
+            return Some((scattered, weight));
+        }
+        ray = scattered;
+        hit_record = hittable.hit(&ray, 0.001, 100.0)?;
+    }
+    None
+}
+
+/// Random walk through the interior of a subsurface material, for a ray that hit the
+/// inside of its boundary.  Scatters through the interior medium until the ray gets
+/// back to the surface and returns the ray and hit there, with the product of the
+/// albedos picked up on the way; the surface then refracts it out or reflects it back
+/// in.  Any other hit comes back unchanged with a throughput of one.  The walk doesn't
+/// count against the path depth, since it can take hundreds of steps.
+fn subsurface_walk<'a, S: Sampler + ?Sized>(sampler: &mut S, mut ray: Ray, mut hit_record: HitRecord<'a>,
+                                            hittable: &'a Hittable) -> Option<(Ray, HitRecord<'a>, Vec3)> {
+    let mut throughput = Vec3::one();
+    for _ in 0..MAX_WALK_STEPS {
+        let interior = match hit_record.material.interior_medium() {
+            Some(interior) if !hit_record.front_face() => interior,
+            _ => return Some((ray, hit_record, throughput)),
+        };
+        match interior.sample_distance(sampler, &ray, 0.0, hit_record.t()) {
+            Some(t) => {
+                throughput *= interior.albedo();
+                ray = Ray::new(ray.at(t), interior.sample_phase(sampler, ray.dir));
+                hit_record = hittable.hit(&ray, 0.0001, 100.0)?;
+            },
+            None => return Some((ray, hit_record, throughput)),
+        }
+    }
+    None
+}
+
+fn sky_color(dir: Vec3) -> Vec3 {
+    let unit_ray_dir = dir.unit_vector();
+    let t = 0.5 * (unit_ray_dir.y() + 1.0);
+    (1.0 - t) * Vec3::one() + t * Vec3::new(0.5, 0.7, 1.0)
+}
+
+/// Debug colouring of the raymarching cost along a primary ray: black for no steps,
+/// through blue and green, to red at `max_steps` or more.
+pub fn step_heatmap_color(ray: Ray, hittable: &Hittable, max_steps: usize) -> Vec3 {
+    let steps = hittable.march_steps(&ray, 0.001, 100.0);
+    let heat = clamp(steps as Float / max_steps as Float, 0.0, 1.0);
+    if heat < 0.5 {
+        let s = heat * 2.0;
+        Vec3::new(0.0, s, 1.0 - s) * s.sqrt()
+    }
     else {
-        // TODO: replace this hard coded t_max limit with a better way to terminate raymarching
-        // was before f64::INFINITY
-        if let Some(hit_record) = (*hittable).hit(&ray, 0.001, 100.0) {
-            if let Some((scattered, attenuation)) =
-            (*hit_record.material).scatter(rng_source, &ray, hit_record) {
-                attenuation * ray_color(rng_source, scattered, hittable, depth - 1)
-            }
-            else {
-                Vec3::zero()
-            }
-        } else {
-            let unit_ray_dir = ray.dir.unit_vector();
-            let t = 0.5 * (unit_ray_dir.y() + 1.0);
-            (1.0 - t) * Vec3::one() + t * Vec3::new(0.5, 0.7, 1.0)
+        let s = (heat - 0.5) * 2.0;
+        Vec3::new(s, 1.0 - s, 0.0)
+    }
+}
+
+/// Lighting for the preview integrator: a single directional light with SDF soft
+/// shadows, plus sky light attenuated by SDF ambient occlusion.
+pub struct PreviewLighting {
+    pub light_dir: Vec3,
+    pub light_color: Vec3,
+    pub penumbra: Float,
+    pub ao_samples: usize,
+    pub ao_step: Float,
+    /// Density samples per medium segment when shadowing through media.
+    pub medium_steps: usize,
+}
+
+impl PreviewLighting {
+    pub fn new(light_dir: Vec3, light_color: Vec3) -> PreviewLighting {
+        PreviewLighting {
+            light_dir: light_dir.unit_vector(),
+            light_color,
+            penumbra: 16.0,
+            ao_samples: 5,
+            ao_step: 0.02,
+            medium_steps: 32,
         }
     }
 }
 
+/// Noise-free single-sample shading for quick looks at a scene before a full render.
+pub fn preview_color(ray: Ray, hittable: &Hittable, lighting: &PreviewLighting) -> Vec3 {
+    if let Some(hit_record) = hittable.hit(&ray, 0.001, 100.0) {
+        let albedo = hit_record.material.albedo(hit_record.point);
+        let origin = hit_record.point + 0.001 * hit_record.normal;
+
+        let cos_light = dot(hit_record.normal, lighting.light_dir).max(0.0);
+        let shadow = if cos_light > 0.0 {
+            // media don't show up in the SDF shadow, so their transmittance darkens it
+            // instead, by quadrature since ratio tracking would add noise
+            hittable.soft_shadow(origin, lighting.light_dir, 0.01, 100.0, lighting.penumbra)
+                * hittable.transmittance_quadrature(&Ray::new(origin, lighting.light_dir), 0.01, 100.0,
+                                                    lighting.medium_steps)
+        } else { 0.0 };
+        let occlusion = hittable.ambient_occlusion(
+            hit_record.point, hit_record.normal, lighting.ao_samples, lighting.ao_step);
+
+        albedo * (cos_light * shadow * lighting.light_color + occlusion * sky_color(hit_record.normal))
+    }
+    else {
+        sky_color(ray.dir)
+    }
+}
+
+/// Camera position circling `lookat` at the given radius and height, `turn` in [0, 1).
+pub fn turntable_lookfrom(lookat: Vec3, radius: Float, height: Float, turn: Float) -> Vec3 {
+    let angle = TAU * turn;
+    lookat + Vec3::new(radius * angle.cos(), height, radius * angle.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::materials::Material;
+
+    /// Mean weight leaving a wide white subsurface slab, for rays started at its
+    /// centre and heading down; every walk should get out with its energy.
+    fn slab_exit_weight(ior: Float) -> Float {
+        let slab = Hittable::Box(Vec3::new(-50.0, -0.5, -50.0), Vec3::new(50.0, 0.5, 50.0),
+                                 Material::Subsurface(Vec3::one(), 0.25, ior));
+        let mut sampler = rand::thread_rng();
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, -1.0, 0.0));
+        let paths = 4000;
+        let total: Float = (0..paths).map(|_| {
+            let hit_record = slab.hit(&ray, 0.001, 100.0).unwrap();
+            match scatter_surface(&mut RgbThroughput, &mut sampler, ray, hit_record, &slab) {
+                Some((scattered, weight)) => {
+                    assert!(slab.hit(&scattered, 0.001, 100.0).is_none(), "ray stayed inside");
+                    weight.y()
+                },
+                None => 0.0,
+            }
+        }).sum();
+        total / paths as Float
+    }
+
+    #[test]
+    fn index_matched_slab_conserves_energy() {
+        let weight = slab_exit_weight(1.0);
+        assert!((weight - 1.0).abs() < 0.01, "{}", weight);
+    }
+
+    #[test]
+    fn internal_reflections_do_not_lose_energy() {
+        let weight = slab_exit_weight(1.5);
+        assert!((weight - 1.0).abs() < 0.01, "{}", weight);
+    }
+}
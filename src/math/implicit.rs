@@ -0,0 +1,358 @@
+use std::ops::{Add, Mul};
+use std::sync::Arc;
+
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Ray};
+use crate::math::signed_distance::{gradient_estimate};
+
+type DVec3 = Vec3<f64>;
+
+/// Ray segments narrower than this that may still contain the surface count as a hit.
+const ROOT_TOLERANCE: f64 = 1e-6;
+/// Segments an interval search may visit, or Lipschitz steps a march may take, before
+/// giving up on a ray.
+const MAX_ROOT_ITERATIONS: usize = 4096;
+/// Steepest slope of the metaball kernel along r, at r = R/√5, for a ball of radius 1.
+const KERNEL_LIPSCHITZ: f64 = 96.0 / (25.0 * 2.236_067_977_499_79);
+
+/// Surfaces given as the zero set of a function that is negative inside, but unlike a
+/// signed distance field doesn't tell how far away the surface is.  Rays find them by
+/// interval arithmetic, or by stepping with a Lipschitz bound on the function.
+pub enum ImplicitSurface {
+    /// Blobby object: every (centre, radius, weight) ball adds weight × (1 - r²/R²)³
+    /// within its radius, and the surface is where the sum reaches the threshold.
+    /// Negative weights carve dents.
+    Metaballs(Vec<(DVec3, f64, f64)>, f64),
+    /// Algebraic surface: the sum of coefficient × x^i y^j z^k over (coefficient,
+    /// [i, j, k]) terms, inside the box between two corners.
+    Polynomial(Vec<(f64, [u32; 3])>, DVec3, DVec3),
+    /// Any function with the given Lipschitz constant (the most it changes per unit of
+    /// distance), inside the box between two corners.
+    Function(Arc<dyn Fn(DVec3) -> f64 + Send + Sync>, f64, DVec3, DVec3),
+}
+
+impl ImplicitSurface {
+    /// Quadric pᵀ A p + b · p + c = 0 inside the box between `min` and `max`.
+    pub fn quadric(a: [[f64; 3]; 3], b: DVec3, c: f64, min: DVec3, max: DVec3) -> ImplicitSurface {
+        let mut terms = vec![(c, [0, 0, 0])];
+        for i in 0..3 {
+            let mut linear = [0, 0, 0];
+            linear[i] = 1;
+            terms.push((b[i], linear));
+            for (j, coefficient) in a[i].iter().enumerate() {
+                let mut quadratic = linear;
+                quadratic[j] += 1;
+                terms.push((*coefficient, quadratic));
+            }
+        }
+        ImplicitSurface::Polynomial(terms, min, max)
+    }
+
+    pub fn value(&self, point: DVec3) -> f64 {
+        match self {
+            ImplicitSurface::Metaballs(balls, threshold) => {
+                threshold - balls.iter().map(|(center, radius, weight)| {
+                    weight * metaball_kernel((point - *center).length_squared() / (radius * radius))
+                }).sum::<f64>()
+            },
+            ImplicitSurface::Polynomial(terms, _, _) => {
+                terms.iter().map(|(coefficient, [i, j, k])| {
+                    coefficient * point.x().powi(*i as i32) * point.y().powi(*j as i32) * point.z().powi(*k as i32)
+                }).sum()
+            },
+            ImplicitSurface::Function(function, _, _, _) => function(point),
+        }
+    }
+
+    /// Range the function may take over the box between `min` and `max`.  Always
+    /// contains the true range, though it can be wider.
+    pub fn value_interval(&self, min: DVec3, max: DVec3) -> Interval {
+        match self {
+            ImplicitSurface::Metaballs(balls, threshold) => {
+                balls.iter().fold(Interval::point(*threshold), |total, (center, radius, weight)| {
+                    let offset = |axis: usize| Interval::new(min[axis] - center[axis], max[axis] - center[axis]).powi(2);
+                    let s = (offset(0) + offset(1) + offset(2)) * (1.0 / (radius * radius));
+                    // the kernel falls as s grows
+                    let kernel = Interval::new(metaball_kernel(s.hi), metaball_kernel(s.lo));
+                    total + kernel * -*weight
+                })
+            },
+            ImplicitSurface::Polynomial(terms, _, _) => polynomial_interval(terms, min, max),
+            ImplicitSurface::Function(function, lipschitz, _, _) => {
+                // nothing in the box is further than half its diagonal from the centre
+                let center = 0.5 * (min + max);
+                let reach = lipschitz * 0.5 * (max - min).length();
+                let value = function(center);
+                Interval::new(value - reach, value + reach)
+            },
+        }
+    }
+
+    /// Gradient of the function, which points out of the surface.
+    pub fn gradient(&self, point: DVec3) -> DVec3 {
+        match self {
+            ImplicitSurface::Metaballs(balls, _) => {
+                balls.iter().fold(DVec3::zero(), |total, (center, radius, weight)| {
+                    let offset = point - *center;
+                    let s = offset.length_squared() / (radius * radius);
+                    if s >= 1.0 {
+                        return total;
+                    }
+                    total + (6.0 * weight * (1.0 - s) * (1.0 - s) / (radius * radius)) * offset
+                })
+            },
+            ImplicitSurface::Polynomial(terms, _, _) => {
+                let partial = |axis: usize| {
+                    terms.iter().filter(|(_, powers)| powers[axis] > 0).map(|(coefficient, powers)| {
+                        let mut derivative = *coefficient * powers[axis] as f64;
+                        for (other, power) in powers.iter().enumerate() {
+                            let power = if other == axis { *power - 1 } else { *power };
+                            derivative *= point[other].powi(power as i32);
+                        }
+                        derivative
+                    }).sum::<f64>()
+                };
+                Vec3::new(partial(0), partial(1), partial(2))
+            },
+            ImplicitSurface::Function(_, _, min, max) => {
+                let h = 1e-4 * (*max - *min).max_element();
+                // the tetrahedral differences sum to 4h times the gradient
+                gradient_estimate(|p| self.value(p), point, h) / (4.0 * h)
+            },
+        }
+    }
+
+    /// Corners of a box the surface lies within, `None` for metaballs with no positive
+    /// weights, which have no surface at all.
+    pub fn bounds(&self) -> Option<(DVec3, DVec3)> {
+        match self {
+            ImplicitSurface::Metaballs(balls, _) => {
+                // only balls adding to the field can push the surface out
+                let mut extents = balls.iter().filter(|(_, _, weight)| *weight > 0.0)
+                    .map(|(center, radius, _)| (*center - DVec3::splat(*radius), *center + DVec3::splat(*radius)));
+                let first = extents.next()?;
+                Some(extents.fold(first, |(min, max), (ball_min, ball_max)| (min.min(ball_min), max.max(ball_max))))
+            },
+            ImplicitSurface::Polynomial(_, min, max) | ImplicitSurface::Function(_, _, min, max) => Some((*min, *max)),
+        }
+    }
+
+    /// Largest change of the function per unit of distance within the bounds.
+    pub fn lipschitz(&self) -> f64 {
+        match self {
+            ImplicitSurface::Metaballs(balls, _) => {
+                balls.iter().map(|(_, radius, weight)| weight.abs() * KERNEL_LIPSCHITZ / radius).sum()
+            },
+            ImplicitSurface::Polynomial(terms, min, max) => {
+                let slope = |axis: usize| {
+                    let derivative_terms: Vec<(f64, [u32; 3])> = terms.iter()
+                        .filter(|(_, powers)| powers[axis] > 0)
+                        .map(|(coefficient, powers)| {
+                            let mut lowered = *powers;
+                            lowered[axis] -= 1;
+                            (*coefficient * powers[axis] as f64, lowered)
+                        }).collect();
+                    polynomial_interval(&derivative_terms, *min, *max).magnitude()
+                };
+                Vec3::new(slope(0), slope(1), slope(2)).length()
+            },
+            ImplicitSurface::Function(_, lipschitz, _, _) => *lipschitz,
+        }
+    }
+
+    /// Lower bound on the distance from `point` to the surface: the distance to the
+    /// bounds outside them, the function over its Lipschitz constant inside.
+    pub fn distance_estimate(&self, point: DVec3) -> f64 {
+        let (min, max) = match self.bounds() {
+            Some(bounds) => bounds,
+            None => return f64::INFINITY,
+        };
+        let outside = (min - point).max(point - max).max(DVec3::zero()).length();
+        if outside > 0.0 {
+            outside
+        }
+        else {
+            self.value(point).abs() / self.lipschitz()
+        }
+    }
+
+    /// Distance along `ray` to the first crossing of the surface between `t_min` and
+    /// `t_max`.  Functions step by their value over the Lipschitz constant, which can't
+    /// pass the surface; the rest bisect the ray, dropping every segment whose interval
+    /// of values doesn't contain zero.
+    pub fn find_root(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let origin: DVec3 = ray.origin.cast();
+        let dir: DVec3 = ray.dir.cast();
+        match self {
+            ImplicitSurface::Function(function, lipschitz, _, _) => {
+                let mut t = t_min;
+                for _ in 0..MAX_ROOT_ITERATIONS {
+                    let step = function(origin + t * dir).abs() / lipschitz;
+                    if step < ROOT_TOLERANCE {
+                        return Some(t);
+                    }
+                    t += step;
+                    if t > t_max {
+                        return None;
+                    }
+                }
+                None
+            },
+
+            _ => {
+                // depth first with the nearer half on top, so the first root found is the closest
+                let mut segments = vec![(t_min, t_max)];
+                for _ in 0..MAX_ROOT_ITERATIONS {
+                    let (a, b) = segments.pop()?;
+                    let (start, end) = (origin + a * dir, origin + b * dir);
+                    if !self.value_interval(start.min(end), start.max(end)).contains_zero() {
+                        continue;
+                    }
+                    let mid = 0.5 * (a + b);
+                    if b - a < ROOT_TOLERANCE {
+                        return Some(mid);
+                    }
+                    segments.push((mid, b));
+                    segments.push((a, mid));
+                }
+                None
+            },
+        }
+    }
+}
+
+/// Wyvill's soft object kernel over s = r²/R², falling smoothly from 1 at the centre
+/// to 0 with zero slope at the radius.
+fn metaball_kernel(s: f64) -> f64 {
+    if s >= 1.0 { 0.0 } else { (1.0 - s) * (1.0 - s) * (1.0 - s) }
+}
+
+fn polynomial_interval(terms: &[(f64, [u32; 3])], min: DVec3, max: DVec3) -> Interval {
+    let axis = |axis: usize, power: u32| Interval::new(min[axis], max[axis]).powi(power);
+    terms.iter().fold(Interval::point(0.0), |total, (coefficient, [i, j, k])| {
+        total + axis(0, *i) * axis(1, *j) * axis(2, *k) * *coefficient
+    })
+}
+
+/// Closed range of reals for interval arithmetic.
+#[derive(Debug, Copy, Clone)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Interval {
+    pub fn new(lo: f64, hi: f64) -> Interval {
+        Interval { lo: lo.min(hi), hi: lo.max(hi) }
+    }
+
+    pub fn point(x: f64) -> Interval {
+        Interval { lo: x, hi: x }
+    }
+
+    pub fn contains_zero(&self) -> bool {
+        self.lo <= 0.0 && self.hi >= 0.0
+    }
+
+    /// Largest absolute value in the interval.
+    pub fn magnitude(&self) -> f64 {
+        self.lo.abs().max(self.hi.abs())
+    }
+
+    /// Tighter than repeated multiplication, which forgets that every factor is the same.
+    pub fn powi(&self, n: u32) -> Interval {
+        let (lo, hi) = (self.lo.powi(n as i32), self.hi.powi(n as i32));
+        if n == 0 {
+            Interval::point(1.0)
+        }
+        else if n % 2 == 1 || self.lo >= 0.0 {
+            Interval::new(lo, hi)
+        }
+        else if self.hi <= 0.0 {
+            Interval::new(hi, lo)
+        }
+        else {
+            Interval::new(0.0, lo.max(hi))
+        }
+    }
+}
+
+impl Add for Interval {
+    type Output = Interval;
+    fn add(self, other: Interval) -> Interval {
+        Interval { lo: self.lo + other.lo, hi: self.hi + other.hi }
+    }
+}
+
+impl Mul for Interval {
+    type Output = Interval;
+    fn mul(self, other: Interval) -> Interval {
+        let products = [self.lo * other.lo, self.lo * other.hi, self.hi * other.lo, self.hi * other.hi];
+        Interval {
+            lo: products.iter().cloned().fold(f64::INFINITY, f64::min),
+            hi: products.iter().cloned().fold(-f64::INFINITY, f64::max),
+        }
+    }
+}
+
+impl Mul<f64> for Interval {
+    type Output = Interval;
+    fn mul(self, scale: f64) -> Interval {
+        Interval::new(self.lo * scale, self.hi * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::materials::{Material};
+    use crate::math::raytracing::{Hittable};
+
+    fn ray_down_z(x: f64, y: f64) -> Ray {
+        Ray::new(Vec3::new(x, y, 0.0).cast(), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn find_root_hits_single_metaball_at_analytic_distance() {
+        // (1 - r²/R²)³ falls to the threshold of 1/2 at r = R √(1 - ∛(1/2))
+        let (radius, threshold) = (2.0, 0.5);
+        let metaball = ImplicitSurface::Metaballs(vec![(Vec3::new(0.0, 0.0, -5.0), radius, 1.0)], threshold);
+        let surface_radius = radius * (1.0 - threshold.cbrt()).sqrt();
+        let expected = 5.0 - surface_radius;
+        let t = metaball.find_root(&ray_down_z(0.0, 0.0), 0.0, 100.0).unwrap();
+        assert!((t - expected).abs() < 1e-5, "{} vs {}", t, expected);
+    }
+
+    #[test]
+    fn find_root_hits_sphere_quadric_at_analytic_distance() {
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let sphere = ImplicitSurface::quadric(identity, DVec3::zero(), -1.0, DVec3::splat(-1.5), DVec3::splat(1.5));
+        let ray = Ray::new(Vec3::new(0.0, 0.3, 5.0).cast(), Vec3::new(0.0, 0.0, -1.0));
+        let expected = 5.0 - (1.0 - 0.09_f64).sqrt();
+        let t = sphere.find_root(&ray, 3.5, 6.5).unwrap();
+        assert!((t - expected).abs() < 1e-5, "{} vs {}", t, expected);
+        assert!(sphere.find_root(&ray_down_z(1.2, 0.0), 0.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn metaball_kernel_slope_stays_within_lipschitz_bound() {
+        let h = 1e-5;
+        let steepest = (0..=120_000).map(|i| {
+            let r = i as f64 * h;
+            (metaball_kernel((r + h) * (r + h)) - metaball_kernel(r * r)).abs() / h
+        }).fold(0.0, f64::max);
+        assert!(steepest <= KERNEL_LIPSCHITZ * (1.0 + 1e-6), "{} > {}", steepest, KERNEL_LIPSCHITZ);
+        // and the bound is tight, so marching doesn't crawl
+        assert!(steepest >= KERNEL_LIPSCHITZ * 0.999, "{} < {}", steepest, KERNEL_LIPSCHITZ);
+    }
+
+    #[test]
+    fn metaballs_without_positive_weights_are_empty() {
+        let dents = ImplicitSurface::Metaballs(vec![(DVec3::zero(), 1.0, -1.0), (DVec3::splat(0.5), 1.0, -0.5)], 0.5);
+        assert!(dents.bounds().is_none());
+        assert_eq!(dents.distance_estimate(DVec3::zero()), f64::INFINITY);
+        let hittable = Hittable::Implicit(dents, Material::Lambertian(Vec3::new(0.5, 0.5, 0.5)));
+        assert!(hittable.bounding_box().is_none());
+        assert!(hittable.hit(&Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), 0.001, 100.0).is_none());
+    }
+}
@@ -1,21 +1,64 @@
-use rand::prelude::ThreadRng;
+use std::sync::Arc;
 
-use crate::math::math3::{Vec3, random_unit_vector, reflect, dot, random_vec_in_unit_sphere};
+use crate::math::math3::{Vec3, Onb, random_unit_vector, reflect, refract, cross, dot, random_vec_in_unit_sphere};
 use crate::math::raytracing::{Ray, HitRecord};
+use crate::math::media::{Medium};
+use crate::math::sampling::{Sampler};
+use crate::math::spectrum::{ComplexIor, Ior, SampledSpectrum, SampledWavelengths, RGB_WAVELENGTH};
+use crate::math::texture::{Texture, NormalMap};
+use crate::math::utils::{Float, TAU};
 
 
 pub enum Material {
     Lambertian(Vec3),
-    Metal(Vec3, f64),
+    /// Rough diffuse (Oren-Nayar) for clay and cloth, with the roughness `sigma` in
+    /// radians; 0 is `Lambertian`.
+    OrenNayar(Vec3, Float),
+    /// Adds a sheen lobe (Estevez and Kulla's "Charlie" sheen) with the given colour and
+    /// roughness on top of the base material, for the rim glow of velvet and cloth.
+    Sheen(Box<Material>, Vec3, Float),
+    /// Thin translucent sheet like a leaf or paper: diffuse reflectance on the lit side
+    /// and diffuse transmittance through to the other.
+    DiffuseTransmission(Vec3, Vec3),
+    Metal(Vec3, Float),
+    /// Brushed metal: anisotropic GGX with the colour as normal incidence reflectance,
+    /// roughnesses `alpha_x` along the surface tangent and `alpha_y` across it, and the
+    /// tangent rotated by the last parameter (radians) around the normal.
+    AnisotropicMetal(Vec3, Float, Float, Float),
+    /// Metal from its measured complex index of refraction (see the `ComplexIor`
+    /// presets) with GGX roughness `alpha`, so its colour and the way it shifts
+    /// towards grazing angles come out of the Fresnel equations.
+    Conductor(ComplexIor, Float),
+    /// Clear glass-like material; dispersive indices split white light in spectral mode.
+    Dielectric(Ior),
+    /// Translucent material for skin, wax and marble: a smooth dielectric boundary
+    /// with the given index of refraction around a scattering interior that rays
+    /// random walk through.  The colour is what the surface looks like once light has
+    /// scattered around inside (the multiple scattering albedo), and the mean free path
+    /// is the average distance between scattering events, in scene units.
+    Subsurface(Vec3, Float, Float),
+    /// Blend of two materials: the second is picked with probability given by the
+    /// texture at the hit point, the first otherwise.
+    Mix(Box<Material>, Box<Material>, Texture),
+    /// Base material under a clear coat with the given index of refraction and
+    /// roughness (fuzz, as for `Metal`), for car paint and lacquer.  The coat reflects
+    /// by its Fresnel reflectance and lets the base scatter the rest.
+    Coated(Box<Material>, Float, Float),
+    /// Base material with its shading normal tilted by the slope of a height texture,
+    /// scaled by the last parameter.
+    Bump(Box<Material>, Texture, Float),
+    /// Base material with its shading normal read from a tangent space normal map,
+    /// whose tilt is scaled by the last parameter.
+    NormalMapped(Box<Material>, Arc<NormalMap>, Float),
     Absorb,
 }
 
 impl Material {
-    pub fn scatter(&self, rng_source: &mut ThreadRng, ray: &Ray, hit_record: HitRecord) -> Option<(Ray, Vec3)> {
+    pub fn scatter<S: Sampler + ?Sized>(&self, sampler: &mut S, ray: &Ray, hit_record: HitRecord) -> Option<(Ray, Vec3)> {
         match self {
             Material::Metal(albedo, fuzz) => {
                 let reflected = reflect(ray.dir.unit_vector(), hit_record.normal);
-                let scattered = Ray::new(hit_record.point, reflected + (*fuzz) * random_vec_in_unit_sphere(rng_source));
+                let scattered = Ray::new(hit_record.point, reflected + (*fuzz) * random_vec_in_unit_sphere(sampler));
                 if dot(scattered.dir, hit_record.normal) > 0.0 {
                     Some((scattered, (*albedo)))
                 }
@@ -25,13 +68,746 @@ impl Material {
             },
 
             Material::Lambertian(albedo) => {
-                let scatter_direction: Vec3 = hit_record.normal + random_unit_vector(rng_source);
+                let scatter_direction: Vec3 = hit_record.normal + random_unit_vector(sampler);
                 Some((Ray::new(hit_record.point, scatter_direction), (*albedo)))
             },
 
+            Material::OrenNayar(_, _) | Material::DiffuseTransmission(_, _) | Material::AnisotropicMetal(_, _, _, _)
+            | Material::Conductor(_, _) => {
+                let wo = -ray.dir.unit_vector();
+                let wi = self.sample(sampler, &hit_record, wo)?;
+                let pdf = self.pdf(&hit_record, wo, wi);
+                if pdf > 0.0 {
+                    Some((Ray::new(hit_record.point, wi), self.eval(&hit_record, wo, wi) / pdf))
+                } else {
+                    None
+                }
+            },
+
+            Material::Sheen(base, color, roughness) => {
+                // one lobe per scatter, weighed by how often it is picked
+                if sampler.next_1d() < SHEEN_SAMPLE_PROBABILITY {
+                    let wo = -ray.dir.unit_vector();
+                    let wi = cosine_direction(sampler, hit_record.normal);
+                    let pdf = SHEEN_SAMPLE_PROBABILITY * cosine_pdf(hit_record.normal, wi);
+                    if pdf > 0.0 {
+                        Some((Ray::new(hit_record.point, wi), sheen_eval(*color, *roughness, hit_record.normal, wo, wi) / pdf))
+                    } else {
+                        None
+                    }
+                } else {
+                    base.scatter(sampler, ray, hit_record)
+                        .map(|(scattered, attenuation)| (scattered, attenuation / (1.0 - SHEEN_SAMPLE_PROBABILITY)))
+                }
+            },
+
+            Material::Dielectric(ior) => {
+                Some((dielectric_scatter(sampler, ray, &hit_record, ior.at(RGB_WAVELENGTH)), Vec3::one()))
+            },
+
+            Material::Subsurface(_, _, ior) => {
+                Some((dielectric_scatter(sampler, ray, &hit_record, *ior), Vec3::one()))
+            },
+
+            Material::Mix(first, second, weight) => {
+                if sampler.next_1d() < weight.value(hit_record.point) {
+                    second.scatter(sampler, ray, hit_record)
+                } else {
+                    first.scatter(sampler, ray, hit_record)
+                }
+            },
+
+            Material::Coated(base, ior, roughness) => {
+                if sampler.next_1d() < coat_reflectance(ray, &hit_record, *ior) {
+                    coat_scatter(sampler, ray, &hit_record, *roughness).map(|scattered| (scattered, Vec3::one()))
+                } else {
+                    base.scatter(sampler, ray, hit_record)
+                }
+            },
+
+            Material::Bump(base, _, _) | Material::NormalMapped(base, _, _) => {
+                let geometric_normal = hit_record.normal;
+                let shaded = self.shading_record(&hit_record, -ray.dir.unit_vector());
+                let shading_normal = shaded.normal;
+                base.scatter(sampler, ray, shaded).map(|(scattered, attenuation)| {
+                    (keep_geometric_side(scattered, shading_normal, geometric_normal), attenuation)
+                })
+            },
+
             Material::Absorb => {
                 None
             }
         }
     }
+
+    /// Hit record with the shading normal of a `Bump` or `NormalMapped` material,
+    /// bent towards the viewer where it would face away from it.
+    fn shading_record<'a>(&self, hit_record: &HitRecord<'a>, wo: Vec3) -> HitRecord<'a> {
+        let perturbed = match self {
+            Material::Bump(_, height, strength) => bump_normal(height, *strength, hit_record),
+            Material::NormalMapped(_, normal_map, strength) => {
+                let tangent_space = normal_map.sample(hit_record.uv);
+                hit_record.shading_frame()
+                    .local(Vec3::new(*strength * tangent_space.x(), *strength * tangent_space.y(), tangent_space.z()))
+                    .unit_vector()
+            },
+            _ => return *hit_record,
+        };
+
+        let mut shaded = *hit_record;
+        shaded.normal = keep_visible(perturbed, wo);
+        shaded.with_tangent(hit_record.tangent)
+    }
+
+    /// `scatter` for spectral rendering, with the attenuation at the sampled wavelengths.
+    pub fn scatter_spectral<S: Sampler + ?Sized>(&self, sampler: &mut S, ray: &Ray, hit_record: HitRecord,
+                                                 wavelengths: &mut SampledWavelengths) -> Option<(Ray, SampledSpectrum)> {
+        match self {
+            Material::Dielectric(ior) => {
+                if ior.is_dispersive() {
+                    // each wavelength refracts in its own direction, so only the hero
+                    // wavelength can follow this one
+                    wavelengths.terminate_secondary();
+                }
+                let scattered = dielectric_scatter(sampler, ray, &hit_record, ior.at(wavelengths.hero()));
+                Some((scattered, SampledSpectrum::splat(1.0)))
+            },
+            Material::Mix(first, second, weight) => {
+                if sampler.next_1d() < weight.value(hit_record.point) {
+                    second.scatter_spectral(sampler, ray, hit_record, wavelengths)
+                } else {
+                    first.scatter_spectral(sampler, ray, hit_record, wavelengths)
+                }
+            },
+            Material::Coated(base, ior, roughness) => {
+                if sampler.next_1d() < coat_reflectance(ray, &hit_record, *ior) {
+                    coat_scatter(sampler, ray, &hit_record, *roughness)
+                        .map(|scattered| (scattered, SampledSpectrum::splat(1.0)))
+                } else {
+                    base.scatter_spectral(sampler, ray, hit_record, wavelengths)
+                }
+            },
+            Material::Bump(base, _, _) | Material::NormalMapped(base, _, _) => {
+                let geometric_normal = hit_record.normal;
+                let shaded = self.shading_record(&hit_record, -ray.dir.unit_vector());
+                let shading_normal = shaded.normal;
+                base.scatter_spectral(sampler, ray, shaded, wavelengths).map(|(scattered, attenuation)| {
+                    (keep_geometric_side(scattered, shading_normal, geometric_normal), attenuation)
+                })
+            },
+            _ => self.scatter(sampler, ray, hit_record)
+                .map(|(scattered, attenuation)| (scattered, SampledSpectrum::from_rgb(attenuation, wavelengths))),
+        }
+    }
+
+    /// Base colour at `point`, used by the preview integrator and feature buffers.
+    pub fn albedo(&self, point: Vec3) -> Vec3 {
+        match self {
+            Material::Lambertian(albedo) | Material::OrenNayar(albedo, _) | Material::Metal(albedo, _)
+            | Material::AnisotropicMetal(albedo, _, _, _) | Material::Subsurface(albedo, _, _) => *albedo,
+            Material::Sheen(base, color, _) => (base.albedo(point) + *color).min(Vec3::one()),
+            Material::DiffuseTransmission(reflectance, transmittance) => *reflectance + *transmittance,
+            Material::Conductor(ior, _) => ior.normal_reflectance(),
+            Material::Dielectric(_) => Vec3::one(),
+            Material::Mix(first, second, weight) => {
+                let w = weight.value(point);
+                (1.0 - w) * first.albedo(point) + w * second.albedo(point)
+            },
+            Material::Coated(base, _, _) | Material::Bump(base, _, _) | Material::NormalMapped(base, _, _) => {
+                base.albedo(point)
+            },
+            Material::Absorb => Vec3::zero(),
+        }
+    }
+
+    /// BSDF times the cosine term for light arriving along `wi` and leaving along `wo`,
+    /// both unit vectors pointing away from the surface.  Zero for materials that only
+    /// `scatter`: the specular and random walk ones, and `Coated`.
+    pub fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        let normal = hit_record.normal;
+        match self {
+            Material::Lambertian(albedo) => dot(normal, wi).max(0.0) / (TAU / 2.0) * *albedo,
+            Material::OrenNayar(albedo, sigma) => oren_nayar_eval(*albedo, *sigma, normal, wo, wi),
+            Material::AnisotropicMetal(albedo, alpha_x, alpha_y, rotation) => {
+                let frame = rotated_frame(hit_record, *rotation);
+                let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+                ggx_eval(wo, wi, *alpha_x, *alpha_y).map_or(Vec3::zero(), |(value, half)| {
+                    value * schlick_fresnel(*albedo, dot(wo, half))
+                })
+            },
+            Material::Conductor(ior, alpha) => {
+                let frame = hit_record.shading_frame();
+                let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+                ggx_eval(wo, wi, *alpha, *alpha).map_or(Vec3::zero(), |(value, half)| {
+                    value * ior.reflectance(dot(wo, half))
+                })
+            },
+            Material::Sheen(base, color, roughness) => {
+                base.eval(hit_record, wo, wi) + sheen_eval(*color, *roughness, normal, wo, wi)
+            },
+            Material::DiffuseTransmission(reflectance, transmittance) => {
+                let cos_i = dot(normal, wi);
+                let side = if cos_i > 0.0 { *reflectance } else { *transmittance };
+                cos_i.abs() / (TAU / 2.0) * side
+            },
+            Material::Mix(first, second, weight) => {
+                let w = weight.value(hit_record.point);
+                (1.0 - w) * first.eval(hit_record, wo, wi) + w * second.eval(hit_record, wo, wi)
+            },
+            Material::Bump(base, _, _) | Material::NormalMapped(base, _, _) => {
+                base.eval(&self.shading_record(hit_record, wo), wo, wi)
+            },
+            _ => Vec3::zero(),
+        }
+    }
+
+    /// Density over solid angle with which `sample` picks `wi`.
+    pub fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Float {
+        let normal = hit_record.normal;
+        match self {
+            Material::Lambertian(_) | Material::OrenNayar(_, _) => cosine_pdf(normal, wi),
+            Material::AnisotropicMetal(_, alpha_x, alpha_y, rotation) => {
+                let frame = rotated_frame(hit_record, *rotation);
+                ggx_pdf(frame.to_local(wo), frame.to_local(wi), *alpha_x, *alpha_y)
+            },
+            Material::Conductor(_, alpha) => {
+                let frame = hit_record.shading_frame();
+                ggx_pdf(frame.to_local(wo), frame.to_local(wi), *alpha, *alpha)
+            },
+            Material::Sheen(base, _, _) => {
+                SHEEN_SAMPLE_PROBABILITY * cosine_pdf(normal, wi)
+                    + (1.0 - SHEEN_SAMPLE_PROBABILITY) * base.pdf(hit_record, wo, wi)
+            },
+            Material::DiffuseTransmission(reflectance, transmittance) => {
+                let p_reflect = reflect_probability(*reflectance, *transmittance);
+                if dot(normal, wi) > 0.0 {
+                    p_reflect * cosine_pdf(normal, wi)
+                } else {
+                    (1.0 - p_reflect) * cosine_pdf(-normal, wi)
+                }
+            },
+            Material::Mix(first, second, weight) => {
+                let w = weight.value(hit_record.point);
+                (1.0 - w) * first.pdf(hit_record, wo, wi) + w * second.pdf(hit_record, wo, wi)
+            },
+            Material::Bump(base, _, _) | Material::NormalMapped(base, _, _) => {
+                base.pdf(&self.shading_record(hit_record, wo), wo, wi)
+            },
+            _ => 0.0,
+        }
+    }
+
+    /// Direction `wi` drawn with density `pdf`, or `None` for materials without `eval`.
+    pub fn sample<S: Sampler + ?Sized>(&self, sampler: &mut S, hit_record: &HitRecord, wo: Vec3) -> Option<Vec3> {
+        let normal = hit_record.normal;
+        match self {
+            Material::Lambertian(_) | Material::OrenNayar(_, _) => Some(cosine_direction(sampler, normal)),
+            Material::AnisotropicMetal(_, alpha_x, alpha_y, rotation) => {
+                let frame = rotated_frame(hit_record, *rotation);
+                let wi = ggx_sample(sampler, frame.to_local(wo), *alpha_x, *alpha_y);
+                if wi.z() > 0.0 { Some(frame.local(wi)) } else { None }
+            },
+            Material::Conductor(_, alpha) => {
+                let frame = hit_record.shading_frame();
+                let wi = ggx_sample(sampler, frame.to_local(wo), *alpha, *alpha);
+                if wi.z() > 0.0 { Some(frame.local(wi)) } else { None }
+            },
+            Material::Sheen(base, _, _) => {
+                if sampler.next_1d() < SHEEN_SAMPLE_PROBABILITY {
+                    Some(cosine_direction(sampler, normal))
+                } else {
+                    base.sample(sampler, hit_record, wo)
+                }
+            },
+            Material::DiffuseTransmission(reflectance, transmittance) => {
+                if sampler.next_1d() < reflect_probability(*reflectance, *transmittance) {
+                    Some(cosine_direction(sampler, normal))
+                } else {
+                    Some(cosine_direction(sampler, -normal))
+                }
+            },
+            Material::Mix(first, second, weight) => {
+                if sampler.next_1d() < weight.value(hit_record.point) {
+                    second.sample(sampler, hit_record, wo)
+                } else {
+                    first.sample(sampler, hit_record, wo)
+                }
+            },
+            Material::Bump(base, _, _) | Material::NormalMapped(base, _, _) => {
+                let shaded = self.shading_record(hit_record, wo);
+                base.sample(sampler, &shaded, wo).map(|wi| {
+                    keep_geometric_side(Ray::new(hit_record.point, wi), shaded.normal, hit_record.normal).dir
+                })
+            },
+            _ => None,
+        }
+    }
+
+    /// Medium filling objects made of this material, which rays that got inside
+    /// random walk through.
+    pub fn interior_medium(&self) -> Option<Medium> {
+        match self {
+            Material::Subsurface(color, mean_free_path, _) => {
+                let albedo = Vec3::new(single_scattering_albedo(color.x()), single_scattering_albedo(color.y()),
+                                       single_scattering_albedo(color.z()));
+                Some(Medium::Homogeneous(1.0 / *mean_free_path, albedo, 0.0))
+            },
+            Material::Coated(base, _, _) | Material::Bump(base, _, _) | Material::NormalMapped(base, _, _) => {
+                base.interior_medium()
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Single scattering albedo whose random walk comes out with the multiple scattering
+/// albedo `color`, by the fit of Chiang et al. 2016 ("Practical and Controllable
+/// Subsurface Scattering for Production Path Tracing").
+fn single_scattering_albedo(color: Float) -> Float {
+    let color = color.clamp(0.0, 1.0);
+    1.0 - (4.09712 + 4.20863 * color - (9.59217 + 41.6808 * color + 17.7126 * color * color).sqrt()).powi(2)
+}
+
+/// How often `Sheen` samples its sheen lobe rather than the base material.
+const SHEEN_SAMPLE_PROBABILITY: Float = 0.25;
+
+/// Cosine weighted direction in the hemisphere around `normal`.
+fn cosine_direction<S: Sampler + ?Sized>(sampler: &mut S, normal: Vec3) -> Vec3 {
+    let direction = normal + random_unit_vector(sampler);
+    if direction.length_squared() < 1e-12 { normal } else { direction.unit_vector() }
+}
+
+fn cosine_pdf(normal: Vec3, wi: Vec3) -> Float {
+    dot(normal, wi).max(0.0) / (TAU / 2.0)
+}
+
+/// Chance of `DiffuseTransmission` sampling reflection, by the brightness of each side.
+fn reflect_probability(reflectance: Vec3, transmittance: Vec3) -> Float {
+    let (r, t) = (reflectance.max_element(), transmittance.max_element());
+    if r + t > 0.0 { r / (r + t) } else { 0.5 }
+}
+
+/// Oren-Nayar's qualitative model (their simplified form), times the cosine term.
+fn oren_nayar_eval(albedo: Vec3, sigma: Float, normal: Vec3, wo: Vec3, wi: Vec3) -> Vec3 {
+    let (cos_i, cos_o) = (dot(normal, wi), dot(normal, wo));
+    if cos_i <= 0.0 || cos_o <= 0.0 {
+        return Vec3::zero();
+    }
+    let sigma2 = sigma * sigma;
+    let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+    let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+    let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+    let sin_o = (1.0 - cos_o * cos_o).max(0.0).sqrt();
+    // cosine of the azimuth between the two directions, from their tangential parts
+    let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+        (dot(wi - cos_i * normal, wo - cos_o * normal) / (sin_i * sin_o)).max(0.0)
+    } else { 0.0 };
+    let (sin_alpha, tan_beta) = if cos_i > cos_o { (sin_o, sin_i / cos_i) } else { (sin_i, sin_o / cos_o) };
+    (a + b * cos_phi * sin_alpha * tan_beta) * cos_i / (TAU / 2.0) * albedo
+}
+
+/// "Charlie" sheen distribution with Ashikhmin's visibility term, times the cosine term.
+fn sheen_eval(color: Vec3, roughness: Float, normal: Vec3, wo: Vec3, wi: Vec3) -> Vec3 {
+    let (cos_i, cos_o) = (dot(normal, wi), dot(normal, wo));
+    if cos_i <= 0.0 || cos_o <= 0.0 {
+        return Vec3::zero();
+    }
+    let half = (wo + wi).unit_vector();
+    let cos_h = dot(normal, half);
+    let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+    let inv_roughness = 1.0 / roughness.max(1e-3);
+    let distribution = (2.0 + inv_roughness) * sin_h.powf(inv_roughness) / TAU;
+    let visibility = 1.0 / (4.0 * (cos_i + cos_o - cos_i * cos_o));
+    distribution * visibility * cos_i * color
+}
+
+/// Shading normal tilted against the gradient of the height texture in the tangent
+/// plane, by central differences.
+fn bump_normal(height: &Texture, strength: Float, hit_record: &HitRecord) -> Vec3 {
+    const EPSILON: Float = 1e-3;
+    let point = hit_record.point;
+    let slope = |axis: Vec3| (height.value(point + EPSILON * axis) - height.value(point - EPSILON * axis)) / (2.0 * EPSILON);
+    let gradient = Vec3::new(slope(Vec3::x_axis()), slope(Vec3::y_axis()), slope(Vec3::z_axis()));
+    let normal = hit_record.normal;
+    (normal - strength * (gradient - dot(gradient, normal) * normal)).unit_vector()
+}
+
+/// Bends a shading normal towards `wo` until `wo` is (just) in front of it, since
+/// shading from behind the normal goes black or leaks light.
+fn keep_visible(shading_normal: Vec3, wo: Vec3) -> Vec3 {
+    const MIN_COS: Float = 0.01;
+    let cos_o = dot(shading_normal, wo);
+    if cos_o >= MIN_COS { shading_normal } else { (shading_normal + (MIN_COS - cos_o) * wo).unit_vector() }
+}
+
+/// A direction on one side of the shading normal but the other side of the geometric
+/// one would go through the surface it was meant to reflect off (or the wrong way
+/// through one it was meant to cross), so mirror it across the geometric surface.
+fn keep_geometric_side(scattered: Ray, shading_normal: Vec3, geometric_normal: Vec3) -> Ray {
+    let cos_geometric = dot(scattered.dir, geometric_normal);
+    if (dot(scattered.dir, shading_normal) > 0.0) == (cos_geometric > 0.0) {
+        scattered
+    } else {
+        Ray::new(scattered.origin, scattered.dir - 2.0 * cos_geometric * geometric_normal)
+    }
+}
+
+/// Shading frame of the hit with its tangent turned by `rotation` around the normal.
+fn rotated_frame(hit_record: &HitRecord, rotation: Float) -> Onb {
+    let frame = hit_record.shading_frame();
+    Onb::from_wu(frame.w, rotation.cos() * frame.u + rotation.sin() * frame.v)
+}
+
+fn schlick_fresnel(f0: Vec3, cos_theta: Float) -> Vec3 {
+    f0 + (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5) * (Vec3::one() - f0)
+}
+
+/// Anisotropic GGX normal distribution for a local space half vector.
+fn ggx_d(half: Vec3, alpha_x: Float, alpha_y: Float) -> Float {
+    let e = (half.x() / alpha_x).powi(2) + (half.y() / alpha_y).powi(2) + half.z() * half.z();
+    1.0 / (TAU / 2.0 * alpha_x * alpha_y * e * e)
+}
+
+/// Smith's Λ for GGX, giving the masking term `1 / (1 + Λ)`.
+fn ggx_lambda(w: Vec3, alpha_x: Float, alpha_y: Float) -> Float {
+    let tan2 = ((alpha_x * w.x()).powi(2) + (alpha_y * w.y()).powi(2)) / (w.z() * w.z());
+    (-1.0 + (1.0 + tan2).sqrt()) / 2.0
+}
+
+/// GGX microfacet BRDF times the cosine term, without the Fresnel factor, and the half
+/// vector it was evaluated at; local space directions with the normal along z.
+fn ggx_eval(wo: Vec3, wi: Vec3, alpha_x: Float, alpha_y: Float) -> Option<(Float, Vec3)> {
+    let (alpha_x, alpha_y) = (alpha_x.max(1e-3), alpha_y.max(1e-3));
+    if wo.z() <= 0.0 || wi.z() <= 0.0 {
+        return None;
+    }
+    let half = (wo + wi).unit_vector();
+    let shadowing = 1.0 / (1.0 + ggx_lambda(wo, alpha_x, alpha_y) + ggx_lambda(wi, alpha_x, alpha_y));
+    Some((ggx_d(half, alpha_x, alpha_y) * shadowing / (4.0 * wo.z()), half))
+}
+
+/// Density of `ggx_sample` picking `wi`: the distribution of visible normals.
+fn ggx_pdf(wo: Vec3, wi: Vec3, alpha_x: Float, alpha_y: Float) -> Float {
+    let (alpha_x, alpha_y) = (alpha_x.max(1e-3), alpha_y.max(1e-3));
+    if wo.z() <= 0.0 || wi.z() <= 0.0 {
+        return 0.0;
+    }
+    let half = (wo + wi).unit_vector();
+    let masking = 1.0 / (1.0 + ggx_lambda(wo, alpha_x, alpha_y));
+    masking * ggx_d(half, alpha_x, alpha_y) / (4.0 * wo.z())
+}
+
+/// Reflects `wo` off a normal drawn from the GGX distribution of visible normals
+/// (Heitz 2018, "Sampling the GGX Distribution of Visible Normals").
+fn ggx_sample<S: Sampler + ?Sized>(sampler: &mut S, wo: Vec3, alpha_x: Float, alpha_y: Float) -> Vec3 {
+    let (alpha_x, alpha_y) = (alpha_x.max(1e-3), alpha_y.max(1e-3));
+    let (u, v) = sampler.next_2d();
+    // stretch the view direction into the configuration where the roughness is 1
+    let view = Vec3::new(alpha_x * wo.x(), alpha_y * wo.y(), wo.z()).unit_vector();
+    let length2 = view.x() * view.x() + view.y() * view.y();
+    let t1 = if length2 > 0.0 { Vec3::new(-view.y(), view.x(), 0.0) / length2.sqrt() } else { Vec3::x_axis() };
+    let t2 = cross(view, t1);
+
+    // sample the projected area of the visible hemisphere
+    let r = u.sqrt();
+    let phi = TAU * v;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + view.z());
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+    let normal = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * view;
+
+    // and unstretch the normal back
+    let half = Vec3::new(alpha_x * normal.x(), alpha_y * normal.y(), normal.z().max(1e-6)).unit_vector();
+    2.0 * dot(wo, half) * half - wo
+}
+
+/// Schlick's approximation of the Fresnel reflectance of a clear coat.
+fn coat_reflectance(ray: &Ray, hit_record: &HitRecord, ior: Float) -> Float {
+    let cos_theta = dot(-ray.dir.unit_vector(), hit_record.normal).clamp(0.0, 1.0);
+    let r0 = ((1.0 - ior) / (1.0 + ior)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5)
+}
+
+/// Mirror reflection off a clear coat, fuzzed like `Metal`; `None` if the fuzz sent
+/// it into the surface.
+fn coat_scatter<S: Sampler + ?Sized>(sampler: &mut S, ray: &Ray, hit_record: &HitRecord, roughness: Float) -> Option<Ray> {
+    let reflected = reflect(ray.dir.unit_vector(), hit_record.normal);
+    let scattered = Ray::new(hit_record.point, reflected + roughness * random_vec_in_unit_sphere(sampler));
+    if dot(scattered.dir, hit_record.normal) > 0.0 { Some(scattered) } else { None }
+}
+
+/// Reflects or refracts off a smooth interface, picking between the two by Schlick's
+/// approximation of the Fresnel reflectance.
+fn dielectric_scatter<S: Sampler + ?Sized>(sampler: &mut S, ray: &Ray, hit_record: &HitRecord, ior: Float) -> Ray {
+    let eta_ratio = if hit_record.front_face() { 1.0 / ior } else { ior };
+    let unit_dir = ray.dir.unit_vector();
+    let cos_theta = dot(-unit_dir, hit_record.normal).min(1.0);
+    let r0 = ((1.0 - eta_ratio) / (1.0 + eta_ratio)).powi(2);
+    let reflectance = r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5);
+
+    let u = sampler.next_1d();
+    let direction = match refract(unit_dir, hit_record.normal, eta_ratio) {
+        Some(refracted) if u >= reflectance => refracted,
+        _ => reflect(unit_dir, hit_record.normal),
+    };
+    Ray::new(hit_record.point, direction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::texture::{Texture};
+
+    fn hit_on(material: &Material, normal: Vec3) -> HitRecord<'_> {
+        HitRecord::new(Vec3::zero(), normal, material, 1.0, true)
+    }
+
+    /// Ray arriving at the origin from direction `wo`.
+    fn arriving_from(wo: Vec3) -> Ray {
+        Ray::new(wo, -wo)
+    }
+
+    #[test]
+    fn mix_at_either_end_is_that_material() {
+        let normal = Vec3::new(0.2, 0.9, -0.1).unit_vector();
+        let (wo, wi) = (Vec3::new(0.5, 0.7, 0.3).unit_vector(), Vec3::new(-0.4, 0.8, 0.2).unit_vector());
+        let first = || Box::new(Material::OrenNayar(Vec3::new(0.8, 0.4, 0.2), 0.5));
+        let second = || Box::new(Material::Lambertian(Vec3::new(0.1, 0.6, 0.9)));
+        for (weight, expected) in [(0.0, *first()), (1.0, *second())] {
+            let mix = Material::Mix(first(), second(), Texture::Constant(weight));
+            let (hit, expected_hit) = (hit_on(&mix, normal), hit_on(&expected, normal));
+            assert!(mix.eval(&hit, wo, wi).approx_eq(expected.eval(&expected_hit, wo, wi), 1e-12));
+            assert_eq!(mix.pdf(&hit, wo, wi), expected.pdf(&expected_hit, wo, wi));
+            assert!(mix.albedo(Vec3::zero()).approx_eq(expected.albedo(Vec3::zero()), 1e-12));
+        }
+
+        // and only ever scatters off that one
+        let mut sampler = rand::thread_rng();
+        for (weight, scatters) in [(0.0, true), (1.0, false)] {
+            let mix = Material::Mix(first(), Box::new(Material::Absorb), Texture::Constant(weight));
+            for _ in 0..1000 {
+                let scattered = mix.scatter(&mut sampler, &arriving_from(wo), hit_on(&mix, normal));
+                assert_eq!(scattered.is_some(), scatters, "weight {}", weight);
+            }
+        }
+    }
+
+    /// Mean attenuation of rays arriving along the normal, and the share of them
+    /// reflected straight back.
+    fn normal_incidence_reflection(material: &Material, samples: usize) -> (Vec3, Float) {
+        let mut sampler = rand::thread_rng();
+        let normal = Vec3::y_axis();
+        let mut reflected = Vec3::zero();
+        let mut mirrored = 0;
+        for _ in 0..samples {
+            if let Some((scattered, attenuation)) = material.scatter(&mut sampler, &arriving_from(normal),
+                                                                      hit_on(material, normal)) {
+                reflected += attenuation;
+                if scattered.dir.unit_vector().approx_eq(normal, 1e-9) {
+                    mirrored += 1;
+                }
+            }
+        }
+        (reflected / samples as Float, mirrored as Float / samples as Float)
+    }
+
+    #[test]
+    fn coat_does_not_add_energy_at_normal_incidence() {
+        for base in [Material::Lambertian(Vec3::one()), Material::Metal(Vec3::one(), 0.0)] {
+            let (reflected, _) = normal_incidence_reflection(&Material::Coated(Box::new(base), 1.5, 0.0), 20000);
+            assert!(reflected.max_element() <= 1.0 + 1e-9, "{:?}", reflected);
+        }
+        // the coat's own share is its Fresnel reflectance, 4% for an index of 1.5
+        let coated = Material::Coated(Box::new(Material::Lambertian(Vec3::new(0.5, 0.5, 0.5))), 1.5, 0.0);
+        let (_, coat_share) = normal_incidence_reflection(&coated, 20000);
+        assert!((coat_share - 0.04).abs() < 0.006, "{}", coat_share);
+    }
+
+    /// Integral of `f` over the whole sphere of directions, by the midpoint rule in the
+    /// cosine and azimuth around `normal`.
+    fn sphere_integral(normal: Vec3, f: impl Fn(Vec3) -> Vec3) -> Vec3 {
+        let frame = Onb::from_w(normal);
+        let (cos_steps, phi_steps) = (400, 200);
+        let cell = 2.0 / cos_steps as Float * TAU / phi_steps as Float;
+        let mut total = Vec3::zero();
+        for i in 0..cos_steps {
+            let cos_theta = -1.0 + 2.0 * (i as Float + 0.5) / cos_steps as Float;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            for j in 0..phi_steps {
+                let phi = TAU * (j as Float + 0.5) / phi_steps as Float;
+                total += cell * f(frame.local(Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)));
+            }
+        }
+        total
+    }
+
+    fn diffuse_lobes() -> Vec<Material> {
+        vec![
+            Material::OrenNayar(Vec3::new(0.9, 0.6, 0.3), 0.6),
+            Material::Sheen(Box::new(Material::Lambertian(Vec3::new(0.5, 0.4, 0.3))), Vec3::new(0.4, 0.4, 0.5), 0.4),
+            Material::DiffuseTransmission(Vec3::new(0.5, 0.3, 0.2), Vec3::new(0.3, 0.5, 0.2)),
+        ]
+    }
+
+    /// Viewing directions from near the normal to near grazing.
+    fn views(normal: Vec3) -> Vec<Vec3> {
+        let frame = Onb::from_w(normal);
+        [0.1, 0.8, 1.3].iter().map(|&theta: &Float| frame.local(Vec3::new(theta.sin(), 0.0, theta.cos()))).collect()
+    }
+
+    #[test]
+    fn lobe_pdfs_integrate_to_one() {
+        let normal = Vec3::new(0.3, 0.8, -0.2).unit_vector();
+        for material in diffuse_lobes() {
+            let hit = hit_on(&material, normal);
+            for wo in views(normal) {
+                let total = sphere_integral(normal, |wi| Vec3::splat(material.pdf(&hit, wo, wi))).x();
+                assert!((total - 1.0).abs() < 1e-3, "{}", total);
+            }
+        }
+    }
+
+    #[test]
+    fn lobe_scatter_weights_average_to_the_integrated_bsdf() {
+        let mut sampler = rand::thread_rng();
+        let normal = Vec3::new(0.3, 0.8, -0.2).unit_vector();
+        let samples = 100000;
+        for material in diffuse_lobes() {
+            let hit = hit_on(&material, normal);
+            for wo in views(normal) {
+                let expected = sphere_integral(normal, |wi| material.eval(&hit, wo, wi));
+                let mean = (0..samples).filter_map(|_| material.scatter(&mut sampler, &arriving_from(wo), hit))
+                    .map(|(_, attenuation)| attenuation)
+                    .fold(Vec3::zero(), |sum, attenuation| sum + attenuation) / samples as Float;
+                assert!(mean.approx_eq(expected, 0.01), "{:?} vs {:?}", mean, expected);
+                assert!(expected.max_element() <= 1.0, "{:?}", expected);
+            }
+        }
+    }
+
+    #[test]
+    fn lobes_are_reciprocal() {
+        let mut sampler = rand::thread_rng();
+        let normal = Vec3::new(0.3, 0.8, -0.2).unit_vector();
+        for material in diffuse_lobes() {
+            let (hit, flipped) = (hit_on(&material, normal), hit_on(&material, -normal));
+            for _ in 0..1000 {
+                let wo = cosine_direction(&mut sampler, normal);
+                let wi = cosine_direction(&mut sampler, normal);
+                let forward = material.eval(&hit, wo, wi) / dot(normal, wi);
+                let backward = material.eval(&hit, wi, wo) / dot(normal, wo);
+                assert!(forward.relative_eq(backward, 1e-4), "{:?} vs {:?}", forward, backward);
+                // through the surface, seen from the other side on the way back
+                let wi = -wi;
+                let forward = material.eval(&hit, wo, wi) / dot(normal, wi).abs();
+                let backward = material.eval(&flipped, wi, wo) / dot(normal, wo);
+                assert!(forward.relative_eq(backward, 1e-4), "{:?} vs {:?}", forward, backward);
+            }
+        }
+    }
+
+    #[test]
+    fn sheen_albedo_stays_below_one() {
+        let sheen = Material::Sheen(Box::new(Material::Lambertian(Vec3::new(0.9, 0.5, 0.2))), Vec3::new(0.3, 0.3, 0.3), 0.5);
+        assert!(sheen.albedo(Vec3::zero()).approx_eq(Vec3::new(1.0, 0.8, 0.5), 1e-6));
+    }
+
+    /// Local space view directions and anisotropic roughnesses for the GGX tests.
+    fn ggx_cases() -> Vec<(Vec3, Float, Float)> {
+        let view = |theta: Float, phi: Float| Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+        vec![
+            (view(0.2, 0.3), 0.3, 0.6),
+            (view(0.9, 1.1), 0.5, 0.5),
+            (view(1.2, 2.5), 0.8, 0.25),
+        ]
+    }
+
+    #[test]
+    fn ggx_samples_follow_its_pdf() {
+        let mut sampler = rand::thread_rng();
+        let (samples, cos_bins, phi_bins) = (200000, 6, 6);
+        for (wo, alpha_x, alpha_y) in ggx_cases() {
+            let mut histogram = vec![0; cos_bins * phi_bins];
+            for _ in 0..samples {
+                let wi = ggx_sample(&mut sampler, wo, alpha_x, alpha_y);
+                if wi.z() > 0.0 {
+                    let phi = wi.y().atan2(wi.x()).rem_euclid(TAU);
+                    let cos_bin = ((wi.z() * cos_bins as Float) as usize).min(cos_bins - 1);
+                    let phi_bin = ((phi / TAU * phi_bins as Float) as usize).min(phi_bins - 1);
+                    histogram[cos_bin * phi_bins + phi_bin] += 1;
+                }
+            }
+            // probability of each bin by integrating the pdf over it
+            let steps = 40;
+            let (cos_width, phi_width) = (1.0 / cos_bins as Float, TAU / phi_bins as Float);
+            for (bin, count) in histogram.iter().enumerate() {
+                let (cos_bin, phi_bin) = ((bin / phi_bins) as Float, (bin % phi_bins) as Float);
+                let mut expected = 0.0;
+                for i in 0..steps {
+                    let cos_theta = cos_width * (cos_bin + (i as Float + 0.5) / steps as Float);
+                    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                    for j in 0..steps {
+                        let phi = phi_width * (phi_bin + (j as Float + 0.5) / steps as Float);
+                        let wi = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                        expected += ggx_pdf(wo, wi, alpha_x, alpha_y) * cos_width * phi_width / (steps * steps) as Float;
+                    }
+                }
+                let measured = *count as Float / samples as Float;
+                assert!((measured - expected).abs() < 0.005, "bin {}: {} vs {}", bin, measured, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn ggx_white_furnace() {
+        let mut sampler = rand::thread_rng();
+        let samples = 50000;
+        for (wo, alpha_x, alpha_y) in ggx_cases() {
+            let mut total = 0.0;
+            for _ in 0..samples {
+                let wi = ggx_sample(&mut sampler, wo, alpha_x, alpha_y);
+                if let Some((value, _)) = ggx_eval(wo, wi, alpha_x, alpha_y) {
+                    let weight = value / ggx_pdf(wo, wi, alpha_x, alpha_y);
+                    assert!(weight <= 1.0 + 1e-4, "{}", weight);
+                    total += weight;
+                }
+            }
+            // single scattering loses a little energy, more the rougher it gets
+            let reflected = total / samples as Float;
+            assert!(reflected <= 1.0 && reflected > 0.6, "{}", reflected);
+        }
+    }
+
+    #[test]
+    fn isotropic_ggx_matches_the_closed_form() {
+        let mut sampler = rand::thread_rng();
+        let alpha: Float = 0.4;
+        let pi = TAU / 2.0;
+        let smith = |w: Vec3| {
+            let tan2 = (1.0 - w.z() * w.z()) / (w.z() * w.z());
+            2.0 / (1.0 + (1.0 + alpha * alpha * tan2).sqrt())
+        };
+        for _ in 0..1000 {
+            let (wo, wi) = (cosine_direction(&mut sampler, Vec3::z_axis()), cosine_direction(&mut sampler, Vec3::z_axis()));
+            let half = (wo + wi).unit_vector();
+            let d = alpha * alpha / (pi * (half.z() * half.z() * (alpha * alpha - 1.0) + 1.0).powi(2));
+            // height correlated masking and shadowing, from the two Smith terms
+            let g2 = 1.0 / (1.0 / smith(wo) + 1.0 / smith(wi) - 1.0);
+            let expected = d * g2 / (4.0 * wo.z());
+            let (value, _) = ggx_eval(wo, wi, alpha, alpha).unwrap();
+            assert!((value - expected).abs() <= 1e-4 * expected.max(1.0), "{} vs {}", value, expected);
+        }
+
+        // so turning an isotropic brushed metal changes nothing
+        let normal = Vec3::new(0.1, 0.9, 0.3).unit_vector();
+        let turned = [0.0, 0.7, 2.0].map(|rotation| Material::AnisotropicMetal(Vec3::new(0.9, 0.6, 0.3), alpha, alpha, rotation));
+        for _ in 0..100 {
+            let (wo, wi) = (cosine_direction(&mut sampler, normal), cosine_direction(&mut sampler, normal));
+            let reference = turned[0].eval(&hit_on(&turned[0], normal), wo, wi);
+            for material in &turned[1..] {
+                assert!(material.eval(&hit_on(material, normal), wo, wi).relative_eq(reference, 1e-4));
+            }
+        }
+    }
 }
@@ -1,157 +1,304 @@
-
-use std::ops::{Neg, Add, Sub, Mul, Div};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Neg, Add, Sub, Mul, Div, AddAssign, SubAssign, MulAssign, DivAssign, Index, IndexMut};
 use rand::Rng;
 use rand::prelude::ThreadRng;
-
-#[derive(Debug, Copy, Clone)]
-pub struct Vec3 {
-    e: [f64; 3]
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use crate::math::sampling::{Sampler};
+use crate::math::utils::{Float, Real, TAU};
+
+/// Three component vector generic over the float type; plain `Vec3` is `Vec3<Float>`.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "[F; 3]", into = "[F; 3]"))]
+pub struct Vec3<F: Real = Float> {
+    e: [F; 3]
 }
 
 #[allow(dead_code)]
-impl Vec3 {
-    pub fn new(e0: f64, e1: f64, e2: f64) -> Vec3 {
+impl<F: Real> Vec3<F> {
+    pub fn new(e0: F, e1: F, e2: F) -> Vec3<F> {
         Vec3 { e: [e0, e1, e2] }
     }
 
-    pub fn zero() -> Vec3 {
-        Vec3::new(0.0, 0.0, 0.0)
+    pub fn splat(value: F) -> Vec3<F> {
+        Vec3::new(value, value, value)
     }
 
-    pub fn one() -> Vec3 {
-        Vec3::new(1.0, 1.0, 1.0)
+    pub fn zero() -> Vec3<F> {
+        Vec3::splat(F::ZERO)
     }
 
-    pub fn x_axis() -> Vec3 {
-        Vec3::new(1.0, 0.0, 0.0)
+    pub fn one() -> Vec3<F> {
+        Vec3::splat(F::ONE)
     }
 
-    pub fn y_axis() -> Vec3 {
-        Vec3::new(0.0, 1.0, 0.0)
+    pub fn x_axis() -> Vec3<F> {
+        Vec3::new(F::ONE, F::ZERO, F::ZERO)
     }
 
-    pub fn z_axis() -> Vec3 {
-        Vec3::new(0.0, 0.0, 1.0)
+    pub fn y_axis() -> Vec3<F> {
+        Vec3::new(F::ZERO, F::ONE, F::ZERO)
     }
 
-    pub fn x(&self) -> f64 {
+    pub fn z_axis() -> Vec3<F> {
+        Vec3::new(F::ZERO, F::ZERO, F::ONE)
+    }
+
+    pub fn x(&self) -> F {
         self.e[0]
     }
 
-    pub fn y(&self) -> f64 {
+    pub fn y(&self) -> F {
         self.e[1]
     }
 
-    pub fn z(&self) -> f64 {
+    pub fn z(&self) -> F {
         self.e[2]
     }
 
-    pub fn length(&self) -> f64 {
+    pub fn length(&self) -> F {
         self.length_squared().sqrt()
     }
 
-    pub fn length_squared(&self) -> f64 {
+    pub fn length_squared(&self) -> F {
         dot(*self, *self)
     }
 
-    pub fn sqrt(&self) -> Vec3 {
+    pub fn sqrt(&self) -> Vec3<F> {
         Vec3::new(self.e[0].sqrt(), self.e[1].sqrt(), self.e[2].sqrt())
     }
 
-    pub fn unit_vector(&self) -> Vec3 {
+    pub fn unit_vector(&self) -> Vec3<F> {
         *self / self.length()
     }
 
+    /// Converts to another precision, e.g. `f64` for raymarching.
+    pub fn cast<G: Real>(&self) -> Vec3<G> {
+        Vec3::new(G::from_f64(self.e[0].to_f64()), G::from_f64(self.e[1].to_f64()), G::from_f64(self.e[2].to_f64()))
+    }
+
+    pub fn print_string(&self) {
+        println!("{}", self);
+    }
+
+    pub fn min(&self, other: Vec3<F>) -> Vec3<F> {
+        Vec3::new(self.e[0].min(other.e[0]), self.e[1].min(other.e[1]), self.e[2].min(other.e[2]))
+    }
+
+    pub fn max(&self, other: Vec3<F>) -> Vec3<F> {
+        Vec3::new(self.e[0].max(other.e[0]), self.e[1].max(other.e[1]), self.e[2].max(other.e[2]))
+    }
+
+    pub fn abs(&self) -> Vec3<F> {
+        Vec3::new(self.e[0].abs(), self.e[1].abs(), self.e[2].abs())
+    }
+
+    pub fn min_element(&self) -> F {
+        self.e[0].min(self.e[1]).min(self.e[2])
+    }
+
+    pub fn max_element(&self) -> F {
+        self.e[0].max(self.e[1]).max(self.e[2])
+    }
+
+    /// Linear interpolation, `self` at `t = 0` and `other` at `t = 1`.
+    pub fn lerp(&self, other: Vec3<F>, t: F) -> Vec3<F> {
+        *self * (F::ONE - t) + other * t
+    }
+
+    /// Component-wise comparison with an absolute tolerance.
+    pub fn approx_eq(&self, other: Vec3<F>, epsilon: F) -> bool {
+        (*self - other).abs().max_element() <= epsilon
+    }
+
+    /// Component-wise comparison with a tolerance relative to the larger magnitude,
+    /// falling back to `epsilon` as an absolute tolerance near zero.
+    pub fn relative_eq(&self, other: Vec3<F>, epsilon: F) -> bool {
+        (0..3).all(|i| {
+            let scale = self.e[i].abs().max(other.e[i].abs()).max(F::ONE);
+            (self.e[i] - other.e[i]).abs() <= epsilon * scale
+        })
+    }
+}
+
+impl Vec3 {
     pub fn random(rng_source: &mut ThreadRng) -> Vec3 {
         Vec3::new((*rng_source).gen_range(0.0, 1.0),
                   (*rng_source).gen_range(0.0, 1.0),
                   (*rng_source).gen_range(0.0, 1.0))
     }
 
-    pub fn random_range(rng_source: &mut ThreadRng, min: f64, max: f64) -> Vec3 {
+    pub fn random_range(rng_source: &mut ThreadRng, min: Float, max: Float) -> Vec3 {
         Vec3::new((*rng_source).gen_range(min, max),
                   (*rng_source).gen_range(min, max),
                   (*rng_source).gen_range(min, max))
     }
+}
 
-    pub fn to_string(&self) -> String {
-        format!("{} {} {}", self.e[0], self.e[1], self.e[2])
+impl<F: Real> fmt::Display for Vec3<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.e[0], self.e[1], self.e[2])
     }
+}
 
-    pub fn print_string(&self) -> () {
-        println!("{}", self.to_string());
+impl<F: Real> From<[F; 3]> for Vec3<F> {
+    fn from(e: [F; 3]) -> Vec3<F> {
+        Vec3 { e }
     }
 }
 
-impl Neg for Vec3 {
-    type Output = Vec3;
+impl<F: Real> From<Vec3<F>> for [F; 3] {
+    fn from(v: Vec3<F>) -> [F; 3] {
+        v.e
+    }
+}
+
+impl<F: Real> Index<usize> for Vec3<F> {
+    type Output = F;
 
-    fn neg(self) -> Vec3 {
+    fn index(&self, i: usize) -> &F {
+        &self.e[i]
+    }
+}
+
+impl<F: Real> IndexMut<usize> for Vec3<F> {
+    fn index_mut(&mut self, i: usize) -> &mut F {
+        &mut self.e[i]
+    }
+}
+
+impl<F: Real> Neg for Vec3<F> {
+    type Output = Vec3<F>;
+
+    fn neg(self) -> Vec3<F> {
         Vec3 {
             e: [-self.e[0], -self.e[1], -self.e[2]]
         }
     }
 }
 
-impl Add for Vec3 {
-    type Output = Vec3;
+impl<F: Real> Add for Vec3<F> {
+    type Output = Vec3<F>;
 
-    fn add(self, other: Vec3) -> Vec3 {
+    fn add(self, other: Vec3<F>) -> Vec3<F> {
         Vec3 {
             e: [self.e[0] + other.e[0], self.e[1] + other.e[1], self.e[2] + other.e[2]]
         }
     }
 }
 
-impl Sub for Vec3 {
-    type Output = Vec3;
+impl<F: Real> Sub for Vec3<F> {
+    type Output = Vec3<F>;
 
-    fn sub(self, other: Vec3) -> Vec3 {
+    fn sub(self, other: Vec3<F>) -> Vec3<F> {
         self + (-other)
     }
 }
 
-impl Mul for Vec3 {
-    type Output = Vec3;
+impl<F: Real> Mul for Vec3<F> {
+    type Output = Vec3<F>;
 
-    fn mul(self, other: Vec3) -> Vec3 {
+    fn mul(self, other: Vec3<F>) -> Vec3<F> {
         Vec3 {
             e: [self.e[0] * other.e[0], self.e[1] * other.e[1], self.e[2] * other.e[2]]
         }
     }
 }
 
-impl Mul<f64> for Vec3 {
-    type Output = Vec3;
+impl<F: Real> Mul<F> for Vec3<F> {
+    type Output = Vec3<F>;
 
-    fn mul(self, other: f64) -> Vec3 {
+    fn mul(self, other: F) -> Vec3<F> {
         Vec3 {
             e: [self.e[0] * other, self.e[1] * other, self.e[2] * other]
         }
     }
 }
 
-impl Mul<Vec3> for f64 {
-    type Output = Vec3;
+impl Mul<Vec3<f64>> for f64 {
+    type Output = Vec3<f64>;
 
-    fn mul(self, other: Vec3) -> Vec3 {
+    fn mul(self, other: Vec3<f64>) -> Vec3<f64> {
         other * self
     }
 }
 
-impl Div<f64> for Vec3 {
-    type Output = Vec3;
+impl Mul<Vec3<f32>> for f32 {
+    type Output = Vec3<f32>;
+
+    fn mul(self, other: Vec3<f32>) -> Vec3<f32> {
+        other * self
+    }
+}
+
+impl<F: Real> Div<F> for Vec3<F> {
+    type Output = Vec3<F>;
 
-    fn div(self, other: f64) -> Vec3 {
-        self * (1.0 / other)
+    fn div(self, other: F) -> Vec3<F> {
+        self * (F::ONE / other)
     }
 }
 
-pub fn dot(v1: Vec3, v2: Vec3) -> f64 {
+impl<F: Real> Div for Vec3<F> {
+    type Output = Vec3<F>;
+
+    fn div(self, other: Vec3<F>) -> Vec3<F> {
+        Vec3 {
+            e: [self.e[0] / other.e[0], self.e[1] / other.e[1], self.e[2] / other.e[2]]
+        }
+    }
+}
+
+impl<F: Real> AddAssign for Vec3<F> {
+    fn add_assign(&mut self, other: Vec3<F>) {
+        *self = *self + other;
+    }
+}
+
+impl<F: Real> SubAssign for Vec3<F> {
+    fn sub_assign(&mut self, other: Vec3<F>) {
+        *self = *self - other;
+    }
+}
+
+impl<F: Real> MulAssign for Vec3<F> {
+    fn mul_assign(&mut self, other: Vec3<F>) {
+        *self = *self * other;
+    }
+}
+
+impl<F: Real> MulAssign<F> for Vec3<F> {
+    fn mul_assign(&mut self, other: F) {
+        *self = *self * other;
+    }
+}
+
+impl<F: Real> DivAssign<F> for Vec3<F> {
+    fn div_assign(&mut self, other: F) {
+        *self = *self / other;
+    }
+}
+
+impl<F: Real> Sum for Vec3<F> {
+    fn sum<I: Iterator<Item = Vec3<F>>>(iter: I) -> Vec3<F> {
+        iter.fold(Vec3::zero(), |x, y| x + y)
+    }
+}
+
+impl<'a, F: Real> Sum<&'a Vec3<F>> for Vec3<F> {
+    fn sum<I: Iterator<Item = &'a Vec3<F>>>(iter: I) -> Vec3<F> {
+        iter.fold(Vec3::zero(), |x, y| x + *y)
+    }
+}
+
+pub fn dot<F: Real>(v1: Vec3<F>, v2: Vec3<F>) -> F {
     v1.e[0] * v2.e[0] + v1.e[1] * v2.e[1] + v1.e[2] * v2.e[2]
 }
 
-pub fn cross(v1: Vec3, v2: Vec3) -> Vec3 {
+pub fn cross<F: Real>(v1: Vec3<F>, v2: Vec3<F>) -> Vec3<F> {
     Vec3::new(
         v1.e[1] * v2.e[2] - v1.e[2] * v2.e[1],
         v1.e[2] * v2.e[0] - v1.e[0] * v2.e[2],
@@ -159,31 +306,420 @@ pub fn cross(v1: Vec3, v2: Vec3) -> Vec3 {
     )
 }
 
-pub fn reflect(vec: Vec3, normal: Vec3) -> Vec3 {
-    vec - 2.0 * dot(vec, normal) * normal
+pub fn reflect<F: Real>(vec: Vec3<F>, normal: Vec3<F>) -> Vec3<F> {
+    vec - normal * (F::from_f64(2.0) * dot(vec, normal))
+}
+
+/// Snell refraction of the unit vector `vec` through a surface with unit `normal` facing
+/// against it, `eta_ratio` being incident over transmitted index.  `None` on total
+/// internal reflection.
+pub fn refract<F: Real>(vec: Vec3<F>, normal: Vec3<F>, eta_ratio: F) -> Option<Vec3<F>> {
+    let cos_theta = (-dot(vec, normal)).min(F::ONE);
+    let sin2_theta_t = eta_ratio * eta_ratio * (F::ONE - cos_theta * cos_theta);
+    if sin2_theta_t > F::ONE {
+        return None;
+    }
+    let perpendicular = (vec + normal * cos_theta) * eta_ratio;
+    let parallel = normal * -(F::ONE - sin2_theta_t).sqrt();
+    Some(perpendicular + parallel)
+}
+
+
+/// Uniform point in the unit ball: a uniform direction scaled by the cube root of a
+/// uniform variable, which unlike rejection sampling always uses three dimensions.
+pub fn random_vec_in_unit_sphere<S: Sampler + ?Sized>(sampler: &mut S) -> Vec3 {
+    let direction = random_unit_vector(sampler);
+    direction * sampler.next_1d().cbrt()
+}
+
+pub fn random_unit_vector<S: Sampler + ?Sized>(sampler: &mut S) -> Vec3 {
+    let (u, v) = sampler.next_2d();
+    let angle: Float = TAU * u;
+    let height: Float = 1.0 - 2.0 * v;
+    let radius: Float = (1.0 - height * height).max(0.0).sqrt();
+
+    Vec3::new(radius * angle.cos(), radius * angle.sin(), height)
+}
+
+/// Position in space; unlike a direction it is affected by translation.
+#[derive(Debug, Copy, Clone)]
+pub struct Point3(pub Vec3);
+
+/// Surface normal; transforms by the inverse transpose rather than the matrix itself.
+#[derive(Debug, Copy, Clone)]
+pub struct Normal3(pub Vec3);
+
+impl Sub for Point3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Point3) -> Vec3 {
+        self.0 - other.0
+    }
+}
+
+impl Add<Vec3> for Point3 {
+    type Output = Point3;
+
+    fn add(self, other: Vec3) -> Point3 {
+        Point3(self.0 + other)
+    }
+}
+
+impl Sub<Vec3> for Point3 {
+    type Output = Point3;
+
+    fn sub(self, other: Vec3) -> Point3 {
+        Point3(self.0 - other)
+    }
 }
 
+/// Relative size below which a determinant or pivot counts as zero when inverting.
+const SINGULAR_TOLERANCE: Float = 64.0 * Float::EPSILON;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Mat3 {
+    rows: [Vec3; 3]
+}
+
+impl Mat3 {
+    pub fn from_rows(r0: Vec3, r1: Vec3, r2: Vec3) -> Mat3 {
+        Mat3 { rows: [r0, r1, r2] }
+    }
+
+    pub fn from_columns(c0: Vec3, c1: Vec3, c2: Vec3) -> Mat3 {
+        Mat3::from_rows(c0, c1, c2).transpose()
+    }
+
+    pub fn identity() -> Mat3 {
+        Mat3::from_rows(Vec3::x_axis(), Vec3::y_axis(), Vec3::z_axis())
+    }
 
-#[allow(unused_assignments)]
-pub fn random_vec_in_unit_sphere(rng_source: &mut ThreadRng) -> Vec3 {
-    let mut random_vec: Vec3 = Vec3::zero();
+    pub fn diagonal(d: Vec3) -> Mat3 {
+        Mat3::from_rows(d.x() * Vec3::x_axis(), d.y() * Vec3::y_axis(), d.z() * Vec3::z_axis())
+    }
+
+    /// Right handed rotation by `angle` radians about `axis`.
+    pub fn rotation(axis: Vec3, angle: Float) -> Mat3 {
+        Quat::from_axis_angle(axis, angle).to_mat3()
+    }
+
+    pub fn row(&self, i: usize) -> Vec3 {
+        self.rows[i]
+    }
+
+    pub fn column(&self, i: usize) -> Vec3 {
+        Vec3::new(self.rows[0].e[i], self.rows[1].e[i], self.rows[2].e[i])
+    }
 
-    loop {
-        random_vec = Vec3::random_range(rng_source, -1.0, 1.0);
-        if random_vec.length_squared() <= 1.0 {
-            break;
+    pub fn transpose(&self) -> Mat3 {
+        Mat3::from_rows(self.column(0), self.column(1), self.column(2))
+    }
+
+    pub fn determinant(&self) -> Float {
+        dot(self.rows[0], cross(self.rows[1], self.rows[2]))
+    }
+
+    /// Inverse from the cross products of the columns, `None` if singular.  Singularity
+    /// is judged relative to the product of the column lengths, the largest determinant
+    /// columns that long can have, so it doesn't depend on the matrix's overall scale.
+    pub fn inverse(&self) -> Option<Mat3> {
+        let det = self.determinant();
+        let (c0, c1, c2) = (self.column(0), self.column(1), self.column(2));
+        if det.abs() <= SINGULAR_TOLERANCE * c0.length() * c1.length() * c2.length() {
+            return None;
         }
+        Some(Mat3::from_rows(cross(c1, c2) / det, cross(c2, c0) / det, cross(c0, c1) / det))
     }
-    random_vec
 }
 
-pub fn random_unit_vector(rng_source: &mut ThreadRng) -> Vec3 {
-    let tau: f64 = 6.28318530717958647692528676655900577f64;
-    let angle: f64 = rng_source.gen_range(0.0, tau);
-    let height: f64 = rng_source.gen_range(-1.0, 1.0);
-    let radius: f64 = (1.0 - height * height).sqrt();
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
 
-    Vec3::new(radius * angle.cos(), radius * angle.sin(), height)
+    fn mul(self, other: Vec3) -> Vec3 {
+        Vec3::new(dot(self.rows[0], other), dot(self.rows[1], other), dot(self.rows[2], other))
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, other: Mat3) -> Mat3 {
+        let columns = other.transpose();
+        Mat3::from_columns(self * columns.rows[0], self * columns.rows[1], self * columns.rows[2])
+    }
+}
+
+impl Mul<Float> for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, other: Float) -> Mat3 {
+        Mat3::from_rows(self.rows[0] * other, self.rows[1] * other, self.rows[2] * other)
+    }
+}
+
+/// Row major 4x4 matrix for affine and projective transforms.
+#[derive(Debug, Copy, Clone)]
+pub struct Mat4 {
+    e: [[Float; 4]; 4]
+}
+
+impl Mat4 {
+    pub fn from_rows(e: [[Float; 4]; 4]) -> Mat4 {
+        Mat4 { e }
+    }
+
+    pub fn identity() -> Mat4 {
+        Mat4::from_affine(Mat3::identity(), Vec3::zero())
+    }
+
+    /// The affine transform `x -> linear * x + translation`.
+    pub fn from_affine(linear: Mat3, translation: Vec3) -> Mat4 {
+        let r = linear.rows;
+        Mat4::from_rows([
+            [r[0].x(), r[0].y(), r[0].z(), translation.x()],
+            [r[1].x(), r[1].y(), r[1].z(), translation.y()],
+            [r[2].x(), r[2].y(), r[2].z(), translation.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        Mat4::from_affine(Mat3::identity(), offset)
+    }
+
+    pub fn scale(factors: Vec3) -> Mat4 {
+        Mat4::from_affine(Mat3::diagonal(factors), Vec3::zero())
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> Float {
+        self.e[row][column]
+    }
+
+    /// Upper left 3x3 block.
+    pub fn linear(&self) -> Mat3 {
+        Mat3::from_rows(Vec3::new(self.e[0][0], self.e[0][1], self.e[0][2]),
+                        Vec3::new(self.e[1][0], self.e[1][1], self.e[1][2]),
+                        Vec3::new(self.e[2][0], self.e[2][1], self.e[2][2]))
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut e = [[0.0; 4]; 4];
+        for (i, row) in e.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.e[j][i];
+            }
+        }
+        Mat4::from_rows(e)
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting, `None` if singular.  Each pivot is
+    /// judged relative to the largest entry of its column, which scales along with it,
+    /// so a small linear part next to a large translation still inverts.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let column_scale = |col: usize| (0..4).fold(0.0, |largest: Float, row| largest.max(self.e[row][col].abs()));
+        let mut a = self.e;
+        let mut inv = Mat4::identity().e;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())
+                .unwrap();
+            if a[pivot][col].abs() <= SINGULAR_TOLERANCE * column_scale(col) {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4::from_rows(inv))
+    }
+
+    pub fn transform_point(&self, point: Point3) -> Point3 {
+        let p = point.0;
+        let e = &self.e;
+        let x = e[0][0] * p.x() + e[0][1] * p.y() + e[0][2] * p.z() + e[0][3];
+        let y = e[1][0] * p.x() + e[1][1] * p.y() + e[1][2] * p.z() + e[1][3];
+        let z = e[2][0] * p.x() + e[2][1] * p.y() + e[2][2] * p.z() + e[2][3];
+        let w = e[3][0] * p.x() + e[3][1] * p.y() + e[3][2] * p.z() + e[3][3];
+        if w == 1.0 { Point3(Vec3::new(x, y, z)) } else { Point3(Vec3::new(x, y, z) / w) }
+    }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        self.linear() * vector
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut e = [[0.0; 4]; 4];
+        for (i, row) in e.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.e[i][k] * other.e[k][j]).sum();
+            }
+        }
+        Mat4::from_rows(e)
+    }
+}
+
+impl Mul<Point3> for Mat4 {
+    type Output = Point3;
+
+    fn mul(self, other: Point3) -> Point3 {
+        self.transform_point(other)
+    }
+}
+
+impl Mul<Vec3> for Mat4 {
+    type Output = Vec3;
+
+    fn mul(self, other: Vec3) -> Vec3 {
+        self.transform_vector(other)
+    }
+}
+
+/// Rotation quaternion `w + (x, y, z)`.
+#[derive(Debug, Copy, Clone)]
+pub struct Quat {
+    pub w: Float,
+    pub v: Vec3
+}
+
+impl Quat {
+    pub fn new(w: Float, v: Vec3) -> Quat {
+        Quat { w, v }
+    }
+
+    pub fn identity() -> Quat {
+        Quat::new(1.0, Vec3::zero())
+    }
+
+    pub fn from_axis_angle(axis: Vec3, angle: Float) -> Quat {
+        let (sin, cos) = (0.5 * angle).sin_cos();
+        Quat::new(cos, sin * axis.unit_vector())
+    }
+
+    /// Axis and angle in radians; the axis is arbitrary for the identity rotation.
+    pub fn to_axis_angle(&self) -> (Vec3, Float) {
+        let q = self.normalize();
+        let sin = q.v.length();
+        if sin < 1e-12 {
+            (Vec3::x_axis(), 0.0)
+        }
+        else {
+            (q.v / sin, 2.0 * sin.atan2(q.w))
+        }
+    }
+
+    pub fn dot(&self, other: Quat) -> Float {
+        self.w * other.w + dot(self.v, other.v)
+    }
+
+    pub fn length(&self) -> Float {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Quat {
+        let length = self.length();
+        Quat::new(self.w / length, self.v / length)
+    }
+
+    pub fn conjugate(&self) -> Quat {
+        Quat::new(self.w, -self.v)
+    }
+
+    pub fn rotate(&self, vector: Vec3) -> Vec3 {
+        // v' = v + 2w(q x v) + 2q x (q x v) for unit q
+        let t = 2.0 * cross(self.v, vector);
+        vector + self.w * t + cross(self.v, t)
+    }
+
+    pub fn to_mat3(&self) -> Mat3 {
+        Mat3::from_columns(self.rotate(Vec3::x_axis()),
+                           self.rotate(Vec3::y_axis()),
+                           self.rotate(Vec3::z_axis()))
+    }
+
+    /// Spherical linear interpolation along the shorter arc.
+    pub fn slerp(&self, other: Quat, t: Float) -> Quat {
+        let mut cos_theta = self.dot(other);
+        let mut end = other;
+        if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            end = Quat::new(-other.w, -other.v);
+        }
+        if cos_theta > 0.9995 {
+            // nearly parallel: fall back to normalized lerp to avoid dividing by ~0
+            return Quat::new(self.w + t * (end.w - self.w), self.v + t * (end.v - self.v)).normalize();
+        }
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        let a = ((1.0 - t) * theta).sin() / sin_theta;
+        let b = (t * theta).sin() / sin_theta;
+        Quat::new(a * self.w + b * end.w, a * self.v + b * end.v)
+    }
+}
+
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, other: Quat) -> Quat {
+        Quat::new(self.w * other.w - dot(self.v, other.v),
+                  self.w * other.v + other.w * self.v + cross(self.v, other.v))
+    }
+}
+
+/// Orthonormal basis with `w` as the "up" axis, e.g. a shading normal.
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3
+}
+
+impl Onb {
+    /// Builds a basis around `n` without branching on a helper axis
+    /// (Duff et al. 2017, "Building an Orthonormal Basis, Revisited").
+    pub fn from_w(n: Vec3) -> Onb {
+        let w = n.unit_vector();
+        let sign = (1.0 as Float).copysign(w.z());
+        let a = -1.0 / (sign + w.z());
+        let b = w.x() * w.y() * a;
+        Onb {
+            u: Vec3::new(1.0 + sign * w.x() * w.x() * a, sign * b, -sign * w.x()),
+            v: Vec3::new(b, sign + w.y() * w.y() * a, -w.y()),
+            w
+        }
+    }
+
+    /// Basis from a normal and an approximate tangent, Gram-Schmidt orthogonalized.
+    pub fn from_wu(n: Vec3, tangent: Vec3) -> Onb {
+        let w = n.unit_vector();
+        let u = (tangent - dot(tangent, w) * w).unit_vector();
+        Onb { u, v: cross(w, u), w }
+    }
+
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(dot(a, self.u), dot(a, self.v), dot(a, self.w))
+    }
 }
 
 /* old verification stuff
@@ -209,3 +745,207 @@ let x_unit = x_vec.unit_vector();
 x_unit.print_string();
 println!("{}", x_unit.length());
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: Float = 1e-4;
+
+    fn mat3_approx_eq(a: Mat3, b: Mat3) -> bool {
+        (0..3).all(|i| a.row(i).approx_eq(b.row(i), EPSILON))
+    }
+
+    fn mat4_approx_eq(a: Mat4, b: Mat4) -> bool {
+        (0..4).all(|i| (0..4).all(|j| (a.get(i, j) - b.get(i, j)).abs() <= EPSILON))
+    }
+
+    fn quat_approx_eq(a: Quat, b: Quat) -> bool {
+        // q and -q are the same rotation
+        let same = (a.w - b.w).abs() <= EPSILON && a.v.approx_eq(b.v, EPSILON);
+        let opposite = (a.w + b.w).abs() <= EPSILON && a.v.approx_eq(-b.v, EPSILON);
+        same || opposite
+    }
+
+    fn sample_mat3() -> Mat3 {
+        Mat3::from_rows(Vec3::new(2.0, -1.0, 0.5), Vec3::new(0.3, 1.5, -2.0), Vec3::new(1.0, 0.25, 3.0))
+    }
+
+    fn sample_mat4() -> Mat4 {
+        Mat4::from_rows([
+            [2.0, -1.0, 0.5, 3.0],
+            [0.3, 1.5, -2.0, -1.0],
+            [1.0, 0.25, 3.0, 0.5],
+            [0.1, 0.0, -0.2, 1.0],
+        ])
+    }
+
+    #[test]
+    fn mat3_inverse_gives_identity() {
+        let m = sample_mat3();
+        let inverse = m.inverse().unwrap();
+        assert!(mat3_approx_eq(m * inverse, Mat3::identity()));
+        assert!(mat3_approx_eq(inverse * m, Mat3::identity()));
+    }
+
+    #[test]
+    fn mat3_singular_has_no_inverse() {
+        let singular = Mat3::from_rows(Vec3::new(1.0, 2.0, 3.0), Vec3::new(2.0, 4.0, 6.0), Vec3::new(0.0, 1.0, 1.0));
+        assert!(singular.inverse().is_none());
+    }
+
+    #[test]
+    fn mat4_inverse_gives_identity() {
+        let m = sample_mat4();
+        let inverse = m.inverse().unwrap();
+        assert!(mat4_approx_eq(m * inverse, Mat4::identity()));
+        assert!(mat4_approx_eq(inverse * m, Mat4::identity()));
+    }
+
+    #[test]
+    fn mat4_singular_has_no_inverse() {
+        assert!(Mat4::scale(Vec3::new(1.0, 0.0, 2.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn singularity_is_relative_to_scale() {
+        // singular, but rounding leaves a tiny nonzero determinant and pivot
+        let rounded = Mat3::from_rows(Vec3::new(0.1, 0.2, 0.3), Vec3::new(0.4, 0.5, 0.6), Vec3::new(0.7, 0.8, 0.9));
+        assert!(rounded.inverse().is_none());
+        assert!(Mat4::from_affine(rounded, Vec3::new(1.0, 2.0, 3.0)).inverse().is_none());
+
+        // tiny, but perfectly well conditioned
+        let tiny = Mat3::diagonal(Vec3::new(1e-12, 2e-12, 3e-12));
+        assert!(mat3_approx_eq(tiny * tiny.inverse().unwrap(), Mat3::identity()));
+        let tiny = Mat4::from_affine(sample_mat3() * 1e-12, Vec3::new(1.0, 2.0, 3.0));
+        assert!(mat4_approx_eq(tiny * tiny.inverse().unwrap(), Mat4::identity()));
+    }
+
+    #[test]
+    fn transpose_twice_is_identity_operation() {
+        assert!(mat3_approx_eq(sample_mat3().transpose().transpose(), sample_mat3()));
+        assert!(mat4_approx_eq(sample_mat4().transpose().transpose(), sample_mat4()));
+        assert!(sample_mat3().transpose().row(0).approx_eq(sample_mat3().column(0), EPSILON));
+    }
+
+    #[test]
+    fn quat_axis_angle_round_trip() {
+        let axis = Vec3::new(1.0, 2.0, -0.5).unit_vector();
+        let angle = 1.2;
+        let (round_axis, round_angle) = Quat::from_axis_angle(axis, angle).to_axis_angle();
+        assert!(round_axis.approx_eq(axis, EPSILON));
+        assert!((round_angle - angle).abs() <= EPSILON);
+    }
+
+    #[test]
+    fn quat_rotate_matches_matrix() {
+        let q = Quat::from_axis_angle(Vec3::new(-0.3, 1.0, 0.7), 2.1);
+        let m = q.to_mat3();
+        for v in [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.2, -3.0, 1.5), Vec3::new(-1.0, 1.0, 1.0)].iter() {
+            assert!(q.rotate(*v).approx_eq(m * *v, EPSILON));
+        }
+        // a quarter turn about z takes x to y
+        let quarter = Quat::from_axis_angle(Vec3::z_axis(), TAU / 4.0);
+        assert!(quarter.rotate(Vec3::x_axis()).approx_eq(Vec3::y_axis(), EPSILON));
+    }
+
+    #[test]
+    fn slerp_endpoints_and_midpoint() {
+        let axis = Vec3::new(0.0, 1.0, 1.0);
+        let a = Quat::from_axis_angle(axis, 0.2);
+        let b = Quat::from_axis_angle(axis, 1.8);
+        assert!(quat_approx_eq(a.slerp(b, 0.0), a));
+        assert!(quat_approx_eq(a.slerp(b, 1.0), b));
+        assert!(quat_approx_eq(a.slerp(b, 0.5), Quat::from_axis_angle(axis, 1.0)));
+        assert!((a.slerp(b, 0.5).length() - 1.0).abs() <= EPSILON);
+    }
+
+    #[test]
+    fn onb_from_w_is_orthonormal() {
+        for n in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 2.0, 3.0),
+                  Vec3::new(-0.3, 0.1, -2.0), Vec3::new(1.0, 0.0, 0.0)].iter() {
+            let onb = Onb::from_w(*n);
+            for axis in [onb.u, onb.v, onb.w].iter() {
+                assert!((axis.length() - 1.0).abs() <= EPSILON);
+            }
+            assert!(dot(onb.u, onb.v).abs() <= EPSILON);
+            assert!(dot(onb.v, onb.w).abs() <= EPSILON);
+            assert!(dot(onb.w, onb.u).abs() <= EPSILON);
+            assert!(onb.w.approx_eq(n.unit_vector(), EPSILON));
+            // right handed
+            assert!(cross(onb.u, onb.v).approx_eq(onb.w, EPSILON));
+        }
+    }
+
+    #[test]
+    fn assign_operators() {
+        let mut v = Vec3::new(1.0, 2.0, 3.0);
+        v += Vec3::new(0.5, -1.0, 2.0);
+        assert_eq!(v, Vec3::new(1.5, 1.0, 5.0));
+        v *= Vec3::new(2.0, 3.0, 0.5);
+        assert_eq!(v, Vec3::new(3.0, 3.0, 2.5));
+        v *= 2.0;
+        assert_eq!(v, Vec3::new(6.0, 6.0, 5.0));
+        v /= 4.0;
+        assert_eq!(v, Vec3::new(1.5, 1.5, 1.25));
+    }
+
+    #[test]
+    fn index_and_index_mut() {
+        let mut v = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!((v[0], v[1], v[2]), (1.0, 2.0, 3.0));
+        v[1] = -4.0;
+        assert_eq!(v, Vec3::new(1.0, -4.0, 3.0));
+    }
+
+    #[test]
+    fn sum_of_vectors() {
+        let vectors = vec![Vec3::new(1.0, 2.0, 3.0), Vec3::new(-1.0, 0.5, 2.0), Vec3::new(0.5, 0.5, 0.5)];
+        let expected = Vec3::new(0.5, 3.0, 5.5);
+        assert_eq!(vectors.iter().sum::<Vec3>(), expected);
+        assert_eq!(vectors.into_iter().sum::<Vec3>(), expected);
+        assert_eq!(Vec::<Vec3>::new().into_iter().sum::<Vec3>(), Vec3::zero());
+    }
+
+    #[test]
+    fn componentwise_division() {
+        assert_eq!(Vec3::new(1.0, 6.0, -3.0) / Vec3::new(2.0, 3.0, 4.0), Vec3::new(0.5, 2.0, -0.75));
+    }
+
+    #[test]
+    fn min_max_abs_lerp() {
+        let a = Vec3::new(1.0, -2.0, 3.0);
+        let b = Vec3::new(-1.0, 4.0, 3.5);
+        assert_eq!(a.min(b), Vec3::new(-1.0, -2.0, 3.0));
+        assert_eq!(a.max(b), Vec3::new(1.0, 4.0, 3.5));
+        assert_eq!(a.abs(), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 1.0), b);
+        assert!(a.lerp(b, 0.25).approx_eq(Vec3::new(0.5, -0.5, 3.125), EPSILON));
+    }
+
+    #[test]
+    fn approx_and_relative_eq() {
+        let a = Vec3::new(1000.0, 0.0, -1.0);
+        assert!(a.approx_eq(a + Vec3::splat(0.5e-3), 1e-3));
+        assert!(!a.approx_eq(a + Vec3::new(0.0, 2e-3, 0.0), 1e-3));
+        // relative: 0.05 off at 1000 is fine at 1e-4, but not at 1
+        assert!(a.relative_eq(a + Vec3::new(0.05, 0.0, 0.0), 1e-4));
+        assert!(!a.relative_eq(a + Vec3::new(0.0, 0.0, 0.05), 1e-4));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(format!("{}", Vec3::new(1.0, -2.5, 0.0)), "1 -2.5 0");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let v = Vec3::new(1.5, -2.0, 0.25);
+        let json = serde_json::to_string(&v).unwrap();
+        assert_eq!(json, "[1.5,-2.0,0.25]");
+        let back: Vec3 = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, v);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::math::math3::{Vec3, Onb};
+use crate::math::raytracing::{Ray};
+use crate::math::sampling::{Sampler};
+use crate::math::signed_distance::{SignedDistanceField};
+use crate::math::utils::{Float, Real, TAU};
+
+/// Participating medium filling a `Hittable::Volume` boundary or, as `Hittable::Fog`,
+/// the whole scene.
+pub enum Medium {
+    /// Constant density (extinction per unit length), single scattering albedo and
+    /// Henyey-Greenstein anisotropy `g`: 0 scatters evenly, towards 1 mostly forwards.
+    Homogeneous(Float, Vec3, Float),
+    /// Density `max_density * field(p)`, then albedo and anisotropy as above.  The
+    /// maximum is the majorant that delta and ratio tracking step through the field with.
+    Heterogeneous(DensityField, Float, Vec3, Float),
+}
+
+/// Spatially varying density in [0, 1], in the medium's (object) space.
+pub enum DensityField {
+    /// `clamp(-distance / depth, 0, 1)`: empty outside the SDF, thickening to full
+    /// density `depth` below its surface.
+    Sdf(SignedDistanceField, Float),
+    /// Fractal value noise with the given base frequency and number of octaves.
+    Noise(Float, usize),
+    /// Product of two fields, e.g. an SDF shape eaten away by noise for clouds.
+    Product(Box<DensityField>, Box<DensityField>),
+}
+
+impl DensityField {
+    pub fn density(&self, point: Vec3) -> Float {
+        match self {
+            DensityField::Sdf(distance_field, depth) => {
+                let distance = Float::from_f64(distance_field.distance_estimate(point.cast()));
+                (-distance / *depth).clamp(0.0, 1.0)
+            },
+            DensityField::Noise(frequency, octaves) => fractal_noise(*frequency * point, *octaves),
+            DensityField::Product(a, b) => {
+                let density = a.density(point);
+                if density > 0.0 { density * b.density(point) } else { 0.0 }
+            },
+        }
+    }
+}
+
+/// Where a ray scattered inside a medium.
+pub struct MediumEvent<'a> {
+    pub point: Vec3,
+    pub t: Float,
+    pub medium: &'a Medium,
+}
+
+impl Medium {
+    pub fn albedo(&self) -> Vec3 {
+        match self {
+            Medium::Homogeneous(_, albedo, _) | Medium::Heterogeneous(_, _, albedo, _) => *albedo,
+        }
+    }
+
+    pub fn anisotropy(&self) -> Float {
+        match self {
+            Medium::Homogeneous(_, _, g) | Medium::Heterogeneous(_, _, _, g) => *g,
+        }
+    }
+
+    /// Samples the distance to the next scattering event along `ray` between
+    /// `t_enter` and `t_exit`, or `None` if the ray makes it through.  Scattering events
+    /// then weigh the path by the albedo, so no transmittance needs to be tracked.
+    pub fn sample_distance<S: Sampler + ?Sized>(&self, sampler: &mut S, ray: &Ray, t_enter: Float,
+                                                t_exit: Float) -> Option<Float> {
+        match self {
+            Medium::Homogeneous(density, _, _) => {
+                if *density <= 0.0 {
+                    return None;
+                }
+                let t = t_enter - (1.0 - sampler.next_1d()).ln() / *density;
+                if t < t_exit { Some(t) } else { None }
+            },
+
+            // delta tracking (Woodcock): step with the majorant and accept each
+            // tentative collision with probability density / majorant
+            Medium::Heterogeneous(field, max_density, _, _) => {
+                if *max_density <= 0.0 {
+                    return None;
+                }
+                let mut t = t_enter;
+                loop {
+                    t -= (1.0 - sampler.next_1d()).ln() / *max_density;
+                    if t >= t_exit {
+                        return None;
+                    }
+                    if sampler.next_1d() < field.density(ray.at(t)) {
+                        return Some(t);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Fraction of light getting through along `ray` between `t_enter` and `t_exit`,
+    /// for shadow rays through the medium.
+    pub fn transmittance<S: Sampler + ?Sized>(&self, sampler: &mut S, ray: &Ray, t_enter: Float,
+                                              t_exit: Float) -> Float {
+        match self {
+            Medium::Homogeneous(density, _, _) => (-*density * (t_exit - t_enter)).exp(),
+
+            // ratio tracking (Novák et al. 2014): the same steps as delta tracking, but
+            // every tentative collision scales the estimate instead of ending it
+            Medium::Heterogeneous(field, max_density, _, _) => {
+                if *max_density <= 0.0 {
+                    return 1.0;
+                }
+                let mut transmittance = 1.0;
+                let mut t = t_enter;
+                loop {
+                    t -= (1.0 - sampler.next_1d()).ln() / *max_density;
+                    if t >= t_exit {
+                        return transmittance;
+                    }
+                    transmittance *= 1.0 - field.density(ray.at(t));
+                }
+            },
+        }
+    }
+
+    /// Transmittance like `transmittance`, but integrating the density at `steps`
+    /// evenly spaced midpoints instead of ratio tracking, so it's the same every time.
+    pub fn transmittance_quadrature(&self, ray: &Ray, t_enter: Float, t_exit: Float, steps: usize) -> Float {
+        match self {
+            Medium::Homogeneous(density, _, _) => (-*density * (t_exit - t_enter)).exp(),
+            Medium::Heterogeneous(field, max_density, _, _) => {
+                let dt = (t_exit - t_enter) / steps.max(1) as Float;
+                let density_sum: Float = (0..steps.max(1))
+                    .map(|i| field.density(ray.at(t_enter + (i as Float + 0.5) * dt)))
+                    .sum();
+                (-*max_density * density_sum * dt).exp()
+            },
+        }
+    }
+
+    /// New direction for a ray travelling along `dir` that scatters in the medium.
+    pub fn sample_phase<S: Sampler + ?Sized>(&self, sampler: &mut S, dir: Vec3) -> Vec3 {
+        sample_henyey_greenstein(sampler, dir, self.anisotropy())
+    }
+}
+
+/// Henyey-Greenstein phase function for the angle between the incoming and outgoing
+/// propagation directions.
+pub fn henyey_greenstein(cos_theta: Float, g: Float) -> Float {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (2.0 * TAU * denominator * denominator.sqrt())
+}
+
+/// Direction distributed exactly by the Henyey-Greenstein phase function around `dir`.
+pub fn sample_henyey_greenstein<S: Sampler + ?Sized>(sampler: &mut S, dir: Vec3, g: Float) -> Vec3 {
+    let (u, v) = sampler.next_2d();
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u
+    }
+    else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = TAU * v;
+    Onb::from_w(dir.unit_vector()).local(Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+}
+
+/// Sum of `octaves` layers of value noise, each at twice the frequency and half the
+/// amplitude of the last, normalised to [0, 1].
+pub fn fractal_noise(point: Vec3, octaves: usize) -> Float {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut total_amplitude = 0.0;
+    let mut p = point;
+    for _ in 0..octaves.max(1) {
+        sum += amplitude * value_noise(p);
+        total_amplitude += amplitude;
+        amplitude *= 0.5;
+        p = 2.0 * p;
+    }
+    sum / total_amplitude
+}
+
+/// Smoothly interpolated random values in [0, 1] on the integer lattice.
+pub fn value_noise(point: Vec3) -> Float {
+    let cell = Vec3::new(point.x().floor(), point.y().floor(), point.z().floor());
+    let f = point - cell;
+    let smooth = |x: Float| x * x * (3.0 - 2.0 * x);
+    let (sx, sy, sz) = (smooth(f.x()), smooth(f.y()), smooth(f.z()));
+    let (ix, iy, iz) = (cell.x() as i64, cell.y() as i64, cell.z() as i64);
+    let corner = |dx: i64, dy: i64, dz: i64| lattice_value(ix + dx, iy + dy, iz + dz);
+    let lerp = |a: Float, b: Float, t: Float| a + t * (b - a);
+    lerp(
+        lerp(lerp(corner(0, 0, 0), corner(1, 0, 0), sx), lerp(corner(0, 1, 0), corner(1, 1, 0), sx), sy),
+        lerp(lerp(corner(0, 0, 1), corner(1, 0, 1), sx), lerp(corner(0, 1, 1), corner(1, 1, 1), sx), sy),
+        sz,
+    )
+}
+
+fn lattice_value(x: i64, y: i64, z: i64) -> Float {
+    let mut h = (x as u64).wrapping_mul(0x9e3779b97f4a7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2ae3d27d4eb4f)
+        ^ (z as u64).wrapping_mul(0x165667b19e3779f9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    (h >> 11) as Float / (1u64 << 53) as Float
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::math3::{dot};
+
+    #[test]
+    fn henyey_greenstein_integrates_to_one() {
+        let steps = 20000;
+        for g in [-0.7, 0.0, 0.3, 0.9] {
+            // over the sphere, by the cosine of the scattering angle
+            let total: Float = (0..steps).map(|i| {
+                let cos_theta = -1.0 + 2.0 * (i as Float + 0.5) / steps as Float;
+                TAU * henyey_greenstein(cos_theta, g) * 2.0 / steps as Float
+            }).sum();
+            assert!((total - 1.0).abs() < 1e-3, "g = {}: {}", g, total);
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_samples_follow_the_phase_function() {
+        let mut sampler = rand::thread_rng();
+        let dir = Vec3::new(0.3, -0.5, 0.8).unit_vector();
+        let (samples, bins) = (200000, 10);
+        for g in [-0.5, 0.0, 0.6] {
+            let mut histogram = vec![0; bins];
+            for _ in 0..samples {
+                let cos_theta = dot(sample_henyey_greenstein(&mut sampler, dir, g), dir).clamp(-1.0, 0.999_999);
+                histogram[((cos_theta + 1.0) / 2.0 * bins as Float) as usize] += 1;
+            }
+            for (bin, count) in histogram.iter().enumerate() {
+                // probability of the bin from the phase function, by the midpoint rule
+                let width = 2.0 / bins as Float;
+                let expected: Float = (0..100).map(|i| {
+                    let cos_theta = -1.0 + width * (bin as Float + (i as Float + 0.5) / 100.0);
+                    TAU * henyey_greenstein(cos_theta, g) * width / 100.0
+                }).sum();
+                let measured = *count as Float / samples as Float;
+                assert!((measured - expected).abs() < 0.005, "g = {}, bin {}: {} vs {}", g, bin, measured, expected);
+            }
+        }
+    }
+
+    /// Density 0.5 around the origin, halfway down a much bigger sphere's falloff.
+    fn half_density(max_density: Float) -> Medium {
+        let field = DensityField::Sdf(SignedDistanceField::Sphere(Vec3::zero(), 1000.0), 2000.0);
+        Medium::Heterogeneous(field, max_density, Vec3::one(), 0.0)
+    }
+
+    #[test]
+    fn tracking_transmittance_matches_beer_lambert() {
+        let mut sampler = rand::thread_rng();
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.6, 0.8));
+        let expected = (-1.5 as Float).exp();
+        let samples = 100000;
+        // both have an extinction of 1, so light gets through 1.5 units with e^-1.5
+        for medium in [Medium::Homogeneous(1.0, Vec3::one(), 0.0), half_density(2.0)] {
+            let escaped = (0..samples).filter(|_| medium.sample_distance(&mut sampler, &ray, 0.5, 2.0).is_none()).count();
+            let delta_tracking = escaped as Float / samples as Float;
+            assert!((delta_tracking - expected).abs() < 0.005, "delta tracking {} vs {}", delta_tracking, expected);
+
+            let ratio_tracking = (0..samples).map(|_| medium.transmittance(&mut sampler, &ray, 0.5, 2.0)).sum::<Float>()
+                / samples as Float;
+            assert!((ratio_tracking - expected).abs() < 0.005, "ratio tracking {} vs {}", ratio_tracking, expected);
+
+            let quadrature = medium.transmittance_quadrature(&ray, 0.5, 2.0, 16);
+            assert!((quadrature - expected).abs() < 1e-3, "quadrature {} vs {}", quadrature, expected);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::math::math3::{Vec3, cross};
+use crate::math::signed_distance::{SignedDistanceField};
+
+type DVec3 = Vec3<f64>;
+
+/// Indexed triangle mesh with a normal per vertex.  Triangles wind counter-clockwise
+/// seen from outside the surface.
+pub struct Mesh {
+    pub vertices: Vec<DVec3>,
+    pub normals: Vec<DVec3>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+/// Zero isosurface of `field` inside the box from `min` to `max` (usually
+/// `field.bounds()`), by dual contouring on a grid with `resolution` cells along the
+/// box's longest side.  Every cell the surface passes through gets one vertex, the mass
+/// point of its edge crossings pulled onto the surface along the normal, and every
+/// grid edge the surface crosses gets a quad joining the four cells around it.
+pub fn extract_mesh(field: &SignedDistanceField, min: DVec3, max: DVec3, resolution: usize) -> Mesh {
+    let cell_size = (max - min).max_element() / resolution.max(1) as f64;
+    // a cell of margin on every side so surfaces touching the box still close
+    let origin = min - DVec3::splat(cell_size);
+    let cells = [0, 1, 2].map(|axis| ((max[axis] - min[axis]) / cell_size).ceil() as usize + 2);
+    let samples = cells.map(|n| n + 1);
+    let sample_point = |[x, y, z]: [usize; 3]| origin + cell_size * Vec3::new(x as f64, y as f64, z as f64);
+    let sample_index = |[x, y, z]: [usize; 3]| x + samples[0] * (y + samples[1] * z);
+    let cell_index = |[x, y, z]: [usize; 3]| x + cells[0] * (y + cells[1] * z);
+    let unflatten = |index: usize, dims: [usize; 3]| [index % dims[0], index / dims[0] % dims[1], index / (dims[0] * dims[1])];
+
+    let distances: Vec<f64> = (0..samples.iter().product()).into_par_iter()
+        .map(|index| field.distance_estimate(sample_point(unflatten(index, samples))))
+        .collect();
+    let distance = |sample: [usize; 3]| distances[sample_index(sample)];
+
+    // the eight corners of a cell, corner i offset by bit 0, 1 and 2 along x, y and z
+    let corners = |[x, y, z]: [usize; 3]| {
+        (0..8).map(move |i| [x + (i & 1), y + ((i >> 1) & 1), z + ((i >> 2) & 1)])
+    };
+
+    let surface_cells: Vec<[usize; 3]> = (0..cells.iter().product()).into_par_iter()
+        .map(|index| unflatten(index, cells))
+        .filter(|&cell| {
+            let inside = corners(cell).filter(|&corner| distance(corner) < 0.0).count();
+            inside != 0 && inside != 8
+        })
+        .collect();
+
+    let vertices: Vec<DVec3> = surface_cells.par_iter().map(|&cell| {
+        let mut crossings = DVec3::zero();
+        let mut num_crossings = 0.0;
+        for start in corners(cell) {
+            for axis in 0..3 {
+                if start[axis] != cell[axis] {
+                    continue;
+                }
+                let mut end = start;
+                end[axis] += 1;
+                let (d0, d1) = (distance(start), distance(end));
+                if (d0 < 0.0) != (d1 < 0.0) {
+                    crossings += sample_point(start).lerp(sample_point(end), d0 / (d0 - d1));
+                    num_crossings += 1.0;
+                }
+            }
+        }
+
+        // the mass point is only as good as linear interpolation, so step it onto the
+        // surface without letting it wander out of (half a cell around) its cell
+        let lower = sample_point(cell) - DVec3::splat(0.5 * cell_size);
+        let upper = lower + DVec3::splat(2.0 * cell_size);
+        let mut vertex = crossings / num_crossings;
+        for _ in 0..4 {
+            vertex = vertex - field.distance_estimate(vertex) * field.normal_estimate(vertex);
+            vertex = vertex.max(lower).min(upper);
+        }
+        vertex
+    }).collect();
+    let normals = vertices.par_iter().map(|&vertex| field.normal_estimate(vertex)).collect();
+
+    let vertex_of: HashMap<usize, usize> = surface_cells.iter().enumerate()
+        .map(|(vertex, &cell)| (cell_index(cell), vertex))
+        .collect();
+    let mut triangles = Vec::new();
+    for (axis, u, v) in [(0, 1, 2), (1, 2, 0), (2, 0, 1)] {
+        for index in 0..samples.iter().product() {
+            let start = unflatten(index, samples);
+            if start[axis] + 1 >= samples[axis] || start[u] == 0 || start[v] == 0
+                || start[u] >= cells[u] || start[v] >= cells[v] {
+                continue;
+            }
+            let mut end = start;
+            end[axis] += 1;
+            let inside = distance(start) < 0.0;
+            if inside == (distance(end) < 0.0) {
+                continue;
+            }
+
+            // the cells around the edge, counter-clockwise seen from +axis
+            let quad = [(1, 1), (0, 1), (0, 0), (1, 0)].map(|(du, dv)| {
+                let mut cell = start;
+                cell[u] -= du;
+                cell[v] -= dv;
+                vertex_of[&cell_index(cell)]
+            });
+            // u cross v is +axis, so that winding faces +axis: right when the inside is behind
+            if inside {
+                triangles.push([quad[0], quad[1], quad[2]]);
+                triangles.push([quad[0], quad[2], quad[3]]);
+            }
+            else {
+                triangles.push([quad[0], quad[2], quad[1]]);
+                triangles.push([quad[0], quad[3], quad[2]]);
+            }
+        }
+    }
+
+    Mesh { vertices, normals, triangles }
+}
+
+impl Mesh {
+    /// Writes the mesh in the format given by the extension of `path`: `obj`, `ply` or `stl`.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+            Some("obj") => self.write_obj(path),
+            Some("ply") => self.write_ply(path),
+            Some("stl") => self.write_stl(path),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown mesh format: {}", path))),
+        }
+    }
+
+    /// Wavefront OBJ with vertex normals.
+    pub fn write_obj(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "# {} vertices, {} triangles", self.vertices.len(), self.triangles.len())?;
+        for vertex in &self.vertices {
+            writeln!(writer, "v {} {} {}", vertex.x(), vertex.y(), vertex.z())?;
+        }
+        for normal in &self.normals {
+            writeln!(writer, "vn {} {} {}", normal.x(), normal.y(), normal.z())?;
+        }
+        for [a, b, c] in &self.triangles {
+            writeln!(writer, "f {0}//{0} {1}//{1} {2}//{2}", a + 1, b + 1, c + 1)?;
+        }
+        writer.flush()
+    }
+
+    /// Binary little endian PLY with vertex normals.
+    pub fn write_ply(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "ply\nformat binary_little_endian 1.0\nelement vertex {}\n", self.vertices.len())?;
+        for property in ["x", "y", "z", "nx", "ny", "nz"] {
+            writeln!(writer, "property float {}", property)?;
+        }
+        write!(writer, "element face {}\nproperty list uchar int vertex_indices\nend_header\n", self.triangles.len())?;
+        for (vertex, normal) in self.vertices.iter().zip(&self.normals) {
+            write_f32s(&mut writer, *vertex)?;
+            write_f32s(&mut writer, *normal)?;
+        }
+        for triangle in &self.triangles {
+            writer.write_all(&[3])?;
+            for index in triangle {
+                writer.write_all(&(*index as i32).to_le_bytes())?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// Binary STL, which has face normals only.
+    pub fn write_stl(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&[0; 80])?;
+        writer.write_all(&(self.triangles.len() as u32).to_le_bytes())?;
+        for [a, b, c] in &self.triangles {
+            let (p0, p1, p2) = (self.vertices[*a], self.vertices[*b], self.vertices[*c]);
+            let normal = cross(p1 - p0, p2 - p0);
+            let length = normal.length();
+            write_f32s(&mut writer, if length > 0.0 { normal / length } else { normal })?;
+            for point in [p0, p1, p2] {
+                write_f32s(&mut writer, point)?;
+            }
+            writer.write_all(&[0, 0])?;
+        }
+        writer.flush()
+    }
+}
+
+fn write_f32s<W: Write>(writer: &mut W, v: DVec3) -> io::Result<()> {
+    for component in [v.x(), v.y(), v.z()] {
+        writer.write_all(&(component as f32).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::math3::{dot};
+
+    #[test]
+    fn sphere_extracts_to_a_closed_outward_mesh() {
+        let (center, radius) = (Vec3::new(0.3, -0.2, 0.1), 0.8);
+        let field = SignedDistanceField::Sphere(center, radius);
+        let (min, max) = field.bounds();
+        let mesh = extract_mesh(&field, min, max, 24);
+        assert!(!mesh.triangles.is_empty());
+
+        // closed and consistently wound: every edge is used once in each direction
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for triangle in &mesh.triangles {
+            for i in 0..3 {
+                *edges.entry((triangle[i], triangle[(i + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "edge {} -> {}", a, b);
+            assert_eq!(edges.get(&(b, a)), Some(&1), "edge {} -> {} has no twin", a, b);
+        }
+
+        for vertex in &mesh.vertices {
+            assert!(((*vertex - center).length() - radius).abs() < 1e-3, "{:?}", vertex);
+        }
+        for &[a, b, c] in &mesh.triangles {
+            let (a, b, c) = (mesh.vertices[a], mesh.vertices[b], mesh.vertices[c]);
+            let facing = cross(b - a, c - a);
+            let outward = (a + b + c) / 3.0 - center;
+            assert!(dot(facing, outward) > 0.0, "triangle at {:?} faces inwards", a);
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use crate::math::math3::{Vec3, Onb, cross, dot};
+use crate::math::raytracing::{Ray};
+use crate::math::utils::{Float, Real, TAU};
+
+/// Intersection with an analytic primitive, before it becomes a `HitRecord`.
+pub struct PrimitiveHit {
+    pub t: Float,
+    pub outward_normal: Vec3,
+    pub uv: (Float, Float),
+    pub tangent: Vec3,
+}
+
+/// Azimuth around the y axis in [0, 1), starting from -x like `sphere_uv`.
+fn azimuth(x: Float, z: Float) -> Float {
+    ((-z).atan2(x) + TAU / 2.0) / TAU
+}
+
+fn axis(index: usize) -> Vec3 {
+    let mut unit = Vec3::zero();
+    unit[index] = 1.0;
+    unit
+}
+
+fn in_range(t: Float, t_min: Float, t_max: Float) -> bool {
+    t > t_min && t < t_max
+}
+
+/// Distance along `ray` to the plane through `point` with normal `normal`.
+fn plane_t(ray: &Ray, point: Vec3, normal: Vec3, t_min: Float, t_max: Float) -> Option<Float> {
+    let denominator = dot(normal, ray.dir);
+    if denominator.abs() < 1e-9 {
+        return None;
+    }
+    let t = dot(point - ray.origin, normal) / denominator;
+    if in_range(t, t_min, t_max) { Some(t) } else { None }
+}
+
+/// UVs are the plane coordinates along `Onb::from_w(normal)`, so they repeat every unit.
+pub fn hit_plane(ray: &Ray, point: Vec3, normal: Vec3, t_min: Float, t_max: Float) -> Option<PrimitiveHit> {
+    let frame = Onb::from_w(normal);
+    let t = plane_t(ray, point, frame.w, t_min, t_max)?;
+    let local = ray.at(t) - point;
+    Some(PrimitiveHit { t, outward_normal: frame.w, uv: (dot(local, frame.u), dot(local, frame.v)), tangent: frame.u })
+}
+
+/// Parallelogram `corner + a * u + b * v` for `a`, `b` in [0, 1], which are its UVs.
+pub fn hit_quad(ray: &Ray, corner: Vec3, u: Vec3, v: Vec3, t_min: Float, t_max: Float) -> Option<PrimitiveHit> {
+    let n = cross(u, v);
+    let normal = n.unit_vector();
+    let t = plane_t(ray, corner, normal, t_min, t_max)?;
+    let planar = ray.at(t) - corner;
+    let w = n / dot(n, n);
+    let (a, b) = (dot(w, cross(planar, v)), dot(w, cross(u, planar)));
+    if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+        return None;
+    }
+    Some(PrimitiveHit { t, outward_normal: normal, uv: (a, b), tangent: u })
+}
+
+/// UVs are the angle around the centre and the distance from it over the radius.
+pub fn hit_disc(ray: &Ray, center: Vec3, normal: Vec3, radius: Float, t_min: Float, t_max: Float) -> Option<PrimitiveHit> {
+    let frame = Onb::from_w(normal);
+    let t = plane_t(ray, center, frame.w, t_min, t_max)?;
+    let offset = ray.at(t) - center;
+    if offset.length_squared() > radius * radius {
+        return None;
+    }
+    let (x, y) = (dot(offset, frame.u), dot(offset, frame.v));
+    let angle = (y.atan2(x) / TAU).rem_euclid(1.0);
+    Some(PrimitiveHit { t, outward_normal: frame.w, uv: (angle, offset.length() / radius), tangent: frame.u })
+}
+
+/// Axis aligned box; each face is mapped to the unit square of UVs.
+pub fn hit_box(ray: &Ray, min: Vec3, max: Vec3, t_min: Float, t_max: Float) -> Option<PrimitiveHit> {
+    let (mut t_enter, mut enter_axis) = (-Float::INFINITY, 0);
+    let (mut t_exit, mut exit_axis) = (Float::INFINITY, 0);
+    for axis in 0..3 {
+        let inverse_dir = 1.0 / ray.dir[axis];
+        let mut t0 = (min[axis] - ray.origin[axis]) * inverse_dir;
+        let mut t1 = (max[axis] - ray.origin[axis]) * inverse_dir;
+        if inverse_dir < 0.0 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        if t0 > t_enter {
+            t_enter = t0;
+            enter_axis = axis;
+        }
+        if t1 < t_exit {
+            t_exit = t1;
+            exit_axis = axis;
+        }
+    }
+    if t_enter > t_exit {
+        return None;
+    }
+    let (t, face_axis) = if in_range(t_enter, t_min, t_max) {
+        (t_enter, enter_axis)
+    } else if in_range(t_exit, t_min, t_max) {
+        (t_exit, exit_axis)
+    } else {
+        return None;
+    };
+
+    let point = ray.at(t);
+    let side = if point[face_axis] > 0.5 * (min[face_axis] + max[face_axis]) { 1.0 } else { -1.0 };
+    let (u_axis, v_axis) = ((face_axis + 1) % 3, (face_axis + 2) % 3);
+    let uv = ((point[u_axis] - min[u_axis]) / (max[u_axis] - min[u_axis]),
+              (point[v_axis] - min[v_axis]) / (max[v_axis] - min[v_axis]));
+    Some(PrimitiveHit { t, outward_normal: side * axis(face_axis), uv, tangent: axis(u_axis) })
+}
+
+/// Closest of the candidate distances in range, with the index of the part it belongs to.
+fn nearest(candidates: &[(Float, usize)], t_min: Float, t_max: Float) -> Option<(Float, usize)> {
+    candidates.iter().copied()
+        .filter(|(t, _)| in_range(*t, t_min, t_max))
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+}
+
+/// Roots of `a t² + b t + c`, falling back to the linear case.
+fn quadratic_roots(a: Float, b: Float, c: Float) -> Vec<Float> {
+    if a.abs() < 1e-12 {
+        return if b.abs() < 1e-12 { vec![] } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+    let root = discriminant.sqrt();
+    vec![(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+}
+
+/// Cap of a y axis aligned shape at height `cap_y` in local coordinates, or `None`.
+fn cap_t(origin: Vec3, dir: Vec3, cap_y: Float, radius: Float) -> Option<Float> {
+    if dir.y().abs() < 1e-12 {
+        return None;
+    }
+    let t = (cap_y - origin.y()) / dir.y();
+    let (x, z) = (origin.x() + t * dir.x(), origin.z() + t * dir.z());
+    if x * x + z * z <= radius * radius { Some(t) } else { None }
+}
+
+/// Hit on the flat cap of a y axis aligned shape, UVs mapped across its square.
+fn cap_hit(t: Float, local: Vec3, radius: Float, up: bool) -> PrimitiveHit {
+    PrimitiveHit {
+        t,
+        outward_normal: if up { Vec3::y_axis() } else { -Vec3::y_axis() },
+        uv: (0.5 * (local.x() / radius + 1.0), 0.5 * (local.z() / radius + 1.0)),
+        tangent: Vec3::x_axis(),
+    }
+}
+
+/// Capped cylinder standing on `base` along +y.  The side's UVs are the azimuth and
+/// the height over `height`.
+pub fn hit_cylinder(ray: &Ray, base: Vec3, radius: Float, height: Float, t_min: Float, t_max: Float) -> Option<PrimitiveHit> {
+    let (o, d) = (ray.origin - base, ray.dir);
+    let mut candidates: Vec<(Float, usize)> = quadratic_roots(
+        d.x() * d.x() + d.z() * d.z(),
+        2.0 * (o.x() * d.x() + o.z() * d.z()),
+        o.x() * o.x() + o.z() * o.z() - radius * radius,
+    ).into_iter().filter(|t| (0.0..=height).contains(&(o.y() + t * d.y()))).map(|t| (t, 0)).collect();
+    candidates.extend(cap_t(o, d, 0.0, radius).map(|t| (t, 1)));
+    candidates.extend(cap_t(o, d, height, radius).map(|t| (t, 2)));
+
+    let (t, part) = nearest(&candidates, t_min, t_max)?;
+    let local = o + t * d;
+    Some(match part {
+        0 => {
+            let normal = Vec3::new(local.x(), 0.0, local.z()) / radius;
+            PrimitiveHit { t, outward_normal: normal, uv: (azimuth(local.x(), local.z()), local.y() / height),
+                           tangent: cross(Vec3::y_axis(), normal) }
+        },
+        part => cap_hit(t, local, radius, part == 2),
+    })
+}
+
+/// Cone standing on its base at `base` with its apex `height` above.  The side's UVs
+/// are the azimuth and the height over `height`.
+pub fn hit_cone(ray: &Ray, base: Vec3, radius: Float, height: Float, t_min: Float, t_max: Float) -> Option<PrimitiveHit> {
+    let (o, d) = (ray.origin - base, ray.dir);
+    // x² + z² = k² (height - y)²
+    let k2 = (radius / height).powi(2);
+    let below_apex = height - o.y();
+    let mut candidates: Vec<(Float, usize)> = quadratic_roots(
+        d.x() * d.x() + d.z() * d.z() - k2 * d.y() * d.y(),
+        2.0 * (o.x() * d.x() + o.z() * d.z() + k2 * below_apex * d.y()),
+        o.x() * o.x() + o.z() * o.z() - k2 * below_apex * below_apex,
+    ).into_iter().filter(|t| (0.0..=height).contains(&(o.y() + t * d.y()))).map(|t| (t, 0)).collect();
+    candidates.extend(cap_t(o, d, 0.0, radius).map(|t| (t, 1)));
+
+    let (t, part) = nearest(&candidates, t_min, t_max)?;
+    let local = o + t * d;
+    Some(match part {
+        0 => {
+            let normal = Vec3::new(local.x(), k2 * (height - local.y()), local.z()).unit_vector();
+            PrimitiveHit { t, outward_normal: normal, uv: (azimuth(local.x(), local.z()), local.y() / height),
+                           tangent: cross(Vec3::y_axis(), normal) }
+        },
+        _ => cap_hit(t, local, radius, false),
+    })
+}
+
+/// Torus around the y axis through `center`, solving its quartic in `f64`.  UVs are
+/// the angles around the y axis and around the tube.
+pub fn hit_torus(ray: &Ray, center: Vec3, major_radius: Float, minor_radius: Float,
+                 t_min: Float, t_max: Float) -> Option<PrimitiveHit> {
+    let o: Vec3<f64> = (ray.origin - center).cast();
+    let d: Vec3<f64> = ray.dir.unit_vector().cast();
+    let (r_major, r_minor) = (major_radius.to_f64(), minor_radius.to_f64());
+    let r2 = r_major * r_major;
+
+    // skip the quartic for rays missing the bounding sphere
+    let bound = r_major + r_minor;
+    let (half_b, c) = (dot(o, d), o.length_squared() - bound * bound);
+    if c > 0.0 && (half_b > 0.0 || half_b * half_b < c) {
+        return None;
+    }
+
+    // (|p|² + R² - r²)² = 4 R² (x² + z²) along p = o + t d with |d| = 1
+    let f = dot(o, d);
+    let g = o.length_squared() + r2 - r_minor * r_minor;
+    let d_xz = d.x() * d.x() + d.z() * d.z();
+    let od_xz = o.x() * d.x() + o.z() * d.z();
+    let o_xz = o.x() * o.x() + o.z() * o.z();
+    let roots = polynomial_roots(&[
+        g * g - 4.0 * r2 * o_xz,
+        4.0 * f * g - 8.0 * r2 * od_xz,
+        4.0 * f * f + 2.0 * g - 4.0 * r2 * d_xz,
+        4.0 * f,
+        1.0,
+    ]);
+    let t = roots.into_iter().map(Float::from_f64).find(|t| in_range(*t, t_min, t_max))?;
+
+    let local = ray.origin - center + t * ray.dir.unit_vector();
+    let ring_distance = (local.x() * local.x() + local.z() * local.z()).sqrt();
+    let ring_point = if ring_distance > 0.0 {
+        Vec3::new(local.x(), 0.0, local.z()) * (major_radius / ring_distance)
+    } else { Vec3::zero() };
+    let normal = ((local - ring_point) / minor_radius).unit_vector();
+    let tube_angle = ((local.y().atan2(ring_distance - major_radius) + TAU / 2.0) / TAU).rem_euclid(1.0);
+    Some(PrimitiveHit { t, outward_normal: normal, uv: (azimuth(local.x(), local.z()), tube_angle),
+                        tangent: cross(Vec3::y_axis(), local) })
+}
+
+/// Real roots, in increasing order, of the polynomial with coefficients
+/// `c[0] + c[1] x + c[2] x² + ...`.  Between consecutive roots of the derivative the
+/// polynomial is monotonic, so each such interval (within the Cauchy bound) holds at
+/// most one root, found by bisection.  Double roots, where it only touches zero, are
+/// missed; for ray tracing those are grazing hits.
+pub fn polynomial_roots(coefficients: &[f64]) -> Vec<f64> {
+    let degree = match coefficients.iter().rposition(|c| *c != 0.0) {
+        Some(degree) => degree,
+        None => return vec![],
+    };
+    let c = &coefficients[..=degree];
+    match degree {
+        0 => vec![],
+        1 => vec![-c[0] / c[1]],
+        _ => {
+            let bound = 1.0 + c[..degree].iter().map(|x| (x / c[degree]).abs()).fold(0.0, f64::max);
+            let derivative: Vec<f64> = (1..=degree).map(|i| i as f64 * c[i]).collect();
+            let mut points = vec![-bound];
+            points.extend(polynomial_roots(&derivative).into_iter().filter(|x| x.abs() < bound));
+            points.push(bound);
+            points.windows(2).filter_map(|interval| bisect(c, interval[0], interval[1])).collect()
+        },
+    }
+}
+
+fn evaluate_polynomial(c: &[f64], x: f64) -> f64 {
+    c.iter().rev().fold(0.0, |sum, coefficient| sum * x + coefficient)
+}
+
+fn bisect(c: &[f64], mut low: f64, mut high: f64) -> Option<f64> {
+    let mut f_low = evaluate_polynomial(c, low);
+    let f_high = evaluate_polynomial(c, high);
+    if f_low == 0.0 {
+        return Some(low);
+    }
+    if f_low.signum() == f_high.signum() {
+        return None;
+    }
+    for _ in 0..100 {
+        let middle = 0.5 * (low + high);
+        if middle <= low || middle >= high {
+            break;
+        }
+        let f_middle = evaluate_polynomial(c, middle);
+        if f_middle == 0.0 {
+            return Some(middle);
+        }
+        if f_middle.signum() == f_low.signum() {
+            low = middle;
+            f_low = f_middle;
+        } else {
+            high = middle;
+        }
+    }
+    Some(0.5 * (low + high))
+}
+
+/// Signed: negative below the plane.
+pub fn plane_distance(point: Vec3, plane_point: Vec3, normal: Vec3) -> Float {
+    dot(point - plane_point, normal.unit_vector())
+}
+
+/// Exact for rectangles; for skewed parallelograms the clamped point is only close to
+/// the nearest one.
+pub fn quad_distance(point: Vec3, corner: Vec3, u: Vec3, v: Vec3) -> Float {
+    let local = point - corner;
+    let a = (dot(local, u) / dot(u, u)).clamp(0.0, 1.0);
+    let b = (dot(local - a * u, v) / dot(v, v)).clamp(0.0, 1.0);
+    (local - a * u - b * v).length()
+}
+
+pub fn disc_distance(point: Vec3, center: Vec3, normal: Vec3, radius: Float) -> Float {
+    let normal = normal.unit_vector();
+    let offset = point - center;
+    let height = dot(offset, normal);
+    let radial = (offset - height * normal).length();
+    (height * height + (radial - radius).max(0.0).powi(2)).sqrt()
+}
+
+pub fn box_distance(point: Vec3, min: Vec3, max: Vec3) -> Float {
+    let q = (point - 0.5 * (min + max)).abs() - 0.5 * (max - min);
+    q.max(Vec3::zero()).length() + q.max_element().min(0.0)
+}
+
+pub fn cylinder_distance(point: Vec3, base: Vec3, radius: Float, height: Float) -> Float {
+    let local = point - base;
+    let dx = (local.x() * local.x() + local.z() * local.z()).sqrt() - radius;
+    let dy = (local.y() - 0.5 * height).abs() - 0.5 * height;
+    (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt() + dx.max(dy).min(0.0)
+}
+
+/// Inigo Quilez's exact capped cone distance, with a top radius of zero.
+pub fn cone_distance(point: Vec3, base: Vec3, radius: Float, height: Float) -> Float {
+    let local = point - base;
+    let half = 0.5 * height;
+    let (qx, qy) = ((local.x() * local.x() + local.z() * local.z()).sqrt(), local.y() - half);
+    let (k1x, k1y) = (0.0, half);
+    let (k2x, k2y) = (-radius, height);
+    let ca = (qx - qx.min(if qy < 0.0 { radius } else { 0.0 }), qy.abs() - half);
+    let s = (((k1x - qx) * k2x + (k1y - qy) * k2y) / (k2x * k2x + k2y * k2y)).clamp(0.0, 1.0);
+    let cb = (qx - k1x + k2x * s, qy - k1y + k2y * s);
+    let sign = if cb.0 < 0.0 && ca.1 < 0.0 { -1.0 } else { 1.0 };
+    sign * (ca.0 * ca.0 + ca.1 * ca.1).min(cb.0 * cb.0 + cb.1 * cb.1).sqrt()
+}
+
+pub fn torus_distance(point: Vec3, center: Vec3, major_radius: Float, minor_radius: Float) -> Float {
+    let local = point - center;
+    let ring = (local.x() * local.x() + local.z() * local.z()).sqrt() - major_radius;
+    (ring * ring + local.y() * local.y()).sqrt() - minor_radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::materials::{Material};
+    use crate::math::raytracing::{Hittable};
+
+    const EPSILON: Float = 1e-4;
+
+    fn assert_roots(coefficients: &[f64], expected: &[f64]) {
+        let roots = polynomial_roots(coefficients);
+        assert_eq!(roots.len(), expected.len(), "{:?}: {:?}", coefficients, roots);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-9, "{:?}: {:?}", coefficients, roots);
+        }
+    }
+
+    #[test]
+    fn polynomial_roots_of_known_polynomials() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(&[24.0, -50.0, 35.0, -10.0, 1.0], &[1.0, 2.0, 3.0, 4.0]);
+        // 2 (x + 1.5)(x - 0.5)(x - 3), with a vanishing leading coefficient on top
+        assert_roots(&[4.5, -7.5, -4.0, 2.0, 0.0], &[-1.5, 0.5, 3.0]);
+        assert_roots(&[-2.0, 0.0, 1.0], &[-(2.0_f64.sqrt()), 2.0_f64.sqrt()]);
+        assert_roots(&[3.0, -2.0], &[1.5]);
+        // x² + 1 and x⁴ + 1 have no real roots, constants none at all
+        assert_roots(&[1.0, 0.0, 1.0], &[]);
+        assert_roots(&[1.0, 0.0, 0.0, 0.0, 1.0], &[]);
+        assert_roots(&[5.0], &[]);
+        assert_roots(&[0.0, 0.0], &[]);
+    }
+
+    fn ray(origin: (Float, Float, Float), dir: (Float, Float, Float)) -> Ray {
+        Ray::new(Vec3::new(origin.0, origin.1, origin.2), Vec3::new(dir.0, dir.1, dir.2))
+    }
+
+    /// Checks `hit` against the analytic distance and normal, that the normal is unit
+    /// length and, unless the primitive's UVs repeat, that they're in [0, 1].
+    fn assert_hit(name: &str, hit: Option<PrimitiveHit>, t: Float, normal: Vec3, uv_in_unit_square: bool) {
+        let hit = hit.unwrap_or_else(|| panic!("{} missed", name));
+        assert!((hit.t - t).abs() < EPSILON, "{}: t {} vs {}", name, hit.t, t);
+        assert!((hit.outward_normal.length() - 1.0).abs() < EPSILON, "{}: {}", name, hit.outward_normal);
+        assert!(hit.outward_normal.approx_eq(normal.unit_vector(), EPSILON), "{}: {} vs {}", name, hit.outward_normal, normal);
+        if uv_in_unit_square {
+            let (u, v) = hit.uv;
+            assert!((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v), "{}: uv {:?}", name, hit.uv);
+        }
+    }
+
+    #[test]
+    fn primitives_hit_at_analytic_distances() {
+        let (t_min, t_max) = (0.001, 100.0);
+        let down = (0.0, -1.0, 0.0);
+        let forward = (0.0, 0.0, -1.0);
+
+        assert_hit("plane", hit_plane(&ray((0.3, 2.0, 0.1), down), Vec3::zero(), Vec3::y_axis(), t_min, t_max),
+                   2.0, Vec3::y_axis(), false);
+
+        let quad = |r: &Ray| hit_quad(r, Vec3::new(-1.0, -1.0, -2.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), t_min, t_max);
+        assert_hit("quad", quad(&ray((0.0, 0.0, 0.0), (0.5, 0.5, -2.0))),
+                   Vec3::new(0.5, 0.5, -2.0).length(), Vec3::z_axis(), true);
+        assert!(quad(&ray((0.0, 0.0, 0.0), (1.5, 0.0, -2.0))).is_none());
+
+        let disc = |r: &Ray| hit_disc(r, Vec3::new(0.0, 0.0, -3.0), Vec3::z_axis(), 1.0, t_min, t_max);
+        assert_hit("disc", disc(&ray((0.2, 0.3, 0.0), forward)), 3.0, Vec3::z_axis(), true);
+        assert_hit("disc", disc(&ray((-0.6, -0.7, 0.0), forward)), 3.0, Vec3::z_axis(), true);
+        assert!(disc(&ray((0.8, 0.8, 0.0), forward)).is_none());
+
+        let cube = |r: &Ray| hit_box(r, Vec3::new(-1.0, -1.0, -4.0), Vec3::new(1.0, 1.0, -2.0), t_min, t_max);
+        assert_hit("box front", cube(&ray((0.2, 0.1, 0.0), forward)), 2.0, Vec3::z_axis(), true);
+        assert_hit("box top", cube(&ray((0.2, 3.0, -3.5), down)), 2.0, Vec3::y_axis(), true);
+        assert_hit("box from inside", cube(&ray((0.0, 0.0, -3.0), (-1.0, 0.0, 0.0))), 1.0, -Vec3::x_axis(), true);
+
+        let cylinder = |r: &Ray| hit_cylinder(r, Vec3::new(0.0, -1.0, -5.0), 1.0, 2.0, t_min, t_max);
+        assert_hit("cylinder side", cylinder(&ray((0.0, 0.0, 0.0), forward)), 4.0, Vec3::z_axis(), true);
+        assert_hit("cylinder side", cylinder(&ray((-3.0, 0.5, -5.0), (1.0, 0.0, 0.0))), 2.0, -Vec3::x_axis(), true);
+        assert_hit("cylinder cap", cylinder(&ray((0.3, 5.0, -5.2), down)), 4.0, Vec3::y_axis(), true);
+
+        // at y = 1 the cone's radius is halved, and its side slopes at 2 up per 1 in
+        let cone = |r: &Ray| hit_cone(r, Vec3::new(0.0, 0.0, -5.0), 1.0, 2.0, t_min, t_max);
+        assert_hit("cone side", cone(&ray((0.0, 1.0, 0.0), forward)), 4.5, Vec3::new(0.0, 0.5, 1.0), true);
+        assert_hit("cone base", cone(&ray((0.2, -3.0, -5.0), (0.0, 1.0, 0.0))), 3.0, -Vec3::y_axis(), true);
+        assert!(cone(&ray((0.0, 1.9, 0.0), (0.0, 0.0, -1.0))).is_some());
+        assert!(cone(&ray((0.0, 2.1, 0.0), (0.0, 0.0, -1.0))).is_none());
+
+        let torus = |r: &Ray| hit_torus(r, Vec3::new(0.0, 0.0, -5.0), 1.0, 0.25, t_min, t_max);
+        assert_hit("torus outside", torus(&ray((0.0, 0.0, 0.0), forward)), 3.75, Vec3::z_axis(), true);
+        assert_hit("torus top", torus(&ray((1.0, 3.0, -5.0), down)), 2.75, Vec3::y_axis(), true);
+        assert_hit("torus hole", torus(&ray((0.0, 0.0, -5.0), (1.0, 0.0, 0.0))), 0.75, -Vec3::x_axis(), true);
+        assert!(torus(&ray((0.0, 3.0, -5.0), down)).is_none());
+    }
+
+    #[test]
+    fn bounding_boxes_contain_their_surfaces() {
+        let material = || Material::Lambertian(Vec3::new(0.5, 0.5, 0.5));
+        let hittables = vec![
+            Hittable::Quad(Vec3::new(0.5, -1.0, 0.2), Vec3::new(1.5, 0.5, 0.0), Vec3::new(-0.3, 0.4, 1.2), material()),
+            Hittable::Disc(Vec3::new(0.1, 0.2, -0.3), Vec3::new(1.0, 2.0, 0.5), 0.8, material()),
+            Hittable::Box(Vec3::new(-1.0, -0.5, 0.0), Vec3::new(0.5, 1.0, 2.0), material()),
+            Hittable::Cylinder(Vec3::new(0.3, -1.0, 0.0), 0.6, 1.7, material()),
+            Hittable::Cone(Vec3::new(-0.2, -0.7, 0.4), 0.9, 1.4, material()),
+            Hittable::Torus(Vec3::new(0.0, 0.5, -0.5), 1.2, 0.3, material()),
+        ];
+        for hittable in &hittables {
+            let aabb = hittable.bounding_box().unwrap();
+            let center = aabb.centroid();
+            let mut hits = 0;
+            // rays from all around, aimed at points spread over the box
+            for i in 0..2000 {
+                let (a, b) = (i as Float * 0.618_034 % 1.0, i as Float * 0.754_878 % 1.0);
+                let (z, phi) = (2.0 * a - 1.0, TAU * b);
+                let ring = (1.0 - z * z).sqrt();
+                let origin = center + 10.0 * Vec3::new(ring * phi.cos(), z, ring * phi.sin());
+                let target = center + (Vec3::new(a, b, i as Float * 0.569_840 % 1.0) - Vec3::splat(0.5)) * aabb.extent();
+                if let Some(hit_record) = hittable.hit(&Ray::new(origin, target - origin), 0.001, 100.0) {
+                    hits += 1;
+                    assert!(aabb.distance(hit_record.point) < EPSILON, "{:?} misses {}", aabb, hit_record.point);
+                }
+            }
+            assert!(hits > 100, "{:?}: only {} hits", aabb, hits);
+        }
+    }
+}
@@ -1,9 +1,21 @@
 use std::cmp::Ordering::{Equal, Less, Greater};
+use std::sync::Arc;
 
-use crate::math::math3::{Vec3, dot};
+use crate::math::math3::{Vec3, Onb, cross, dot};
+use crate::math::aabb::{Aabb};
+use crate::math::implicit::{ImplicitSurface};
 use crate::math::materials::{Material};
-use crate::math::signed_distance::SignedDistanceField;
+use crate::math::primitives::{PrimitiveHit, hit_plane, hit_quad, hit_disc, hit_box, hit_cylinder, hit_cone, hit_torus,
+                              plane_distance, quad_distance, disc_distance, box_distance, cylinder_distance,
+                              cone_distance, torus_distance};
+use crate::math::media::{Medium, MediumEvent};
+use crate::math::transform::Transform;
+use crate::math::sampling::{Sampler};
+use crate::math::simd::{SimdFloat, RayPacket, MAX_LANES, hit_sphere_packet, hit_aabb_packet};
+use crate::math::signed_distance::{SignedDistanceField, MarchSettings, MarchResult, ambient_occlusion, soft_shadow};
+use crate::math::utils::{Float, Real, TAU};
 
+#[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Vec3,
     pub dir: Vec3
@@ -17,30 +29,82 @@ impl Ray {
         }
     }
 
-    pub fn at(&self, t: f64) -> Vec3 {
+    pub fn at(&self, t: Float) -> Vec3 {
         self.origin + (t * self.dir)
     }
 }
 
 #[allow(dead_code)]
+#[derive(Copy, Clone)]
 pub struct HitRecord<'a> {
     pub point: Vec3,
     pub normal: Vec3,
+    /// Unit vector in the surface along increasing `u`, for anisotropic materials.
+    pub tangent: Vec3,
+    pub uv: (Float, Float),
     pub material: &'a Material,
-    t: f64,
-    front_face: bool
+    t: Float,
+    front_face: bool,
+    object: Option<&'a Hittable>,
 }
 
 impl<'a> HitRecord<'a> {
-    pub fn new(point: Vec3, normal: Vec3, material: &Material, t: f64, front_face: bool) -> HitRecord {
+    /// Hit with an arbitrary tangent and no texture coordinates; primitives that have
+    /// a parameterisation add them with `with_tangent` and `with_uv`.
+    pub fn new(point: Vec3, normal: Vec3, material: &Material, t: Float, front_face: bool) -> HitRecord<'_> {
         HitRecord {
             point,
             normal,
+            tangent: Onb::from_w(normal).u,
+            uv: (0.0, 0.0),
             material,
             t,
-            front_face
+            front_face,
+            object: None,
         }
     }
+
+    /// Sets the tangent to `tangent` made perpendicular to the normal, keeping the
+    /// current one where `tangent` is (nearly) parallel to the normal.
+    pub fn with_tangent(mut self, tangent: Vec3) -> HitRecord<'a> {
+        let in_plane = tangent - dot(tangent, self.normal) * self.normal;
+        if in_plane.length_squared() > 1e-12 {
+            self.tangent = in_plane.unit_vector();
+        }
+        self
+    }
+
+    pub fn with_uv(mut self, uv: (Float, Float)) -> HitRecord<'a> {
+        self.uv = uv;
+        self
+    }
+
+    /// Orthonormal frame with `w` along the normal and `u` along the tangent.
+    pub fn shading_frame(&self) -> Onb {
+        Onb::from_wu(self.normal, self.tangent)
+    }
+
+    pub fn t(&self) -> Float {
+        self.t
+    }
+
+    pub fn front_face(&self) -> bool {
+        self.front_face
+    }
+
+    /// The primitive that was hit, or the outermost `Instance` around it, so that each
+    /// placement of a shared object can be told apart.
+    pub fn object(&self) -> Option<&'a Hittable> {
+        self.object
+    }
+}
+
+/// Spherical texture coordinates of a point on the unit sphere: `u` around the y axis
+/// starting from -x, `v` from the bottom pole to the top.
+pub fn sphere_uv(outward_normal: Vec3) -> (Float, Float) {
+    let theta = (-outward_normal.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-outward_normal.z()).atan2(outward_normal.x()) + TAU / 2.0;
+    (phi / TAU, theta / (TAU / 2.0))
 }
 
 pub fn face_normal_adjustment(ray_direction: Vec3, outward_normal: Vec3) -> (Vec3, bool) {
@@ -50,17 +114,62 @@ pub fn face_normal_adjustment(ray_direction: Vec3, outward_normal: Vec3) -> (Vec
 }
 
 
+/// Per-lane winner while traversing a packet: spheres defer building their record
+/// until the closest one is known, other primitives have already built theirs.
+enum PacketHit<'a> {
+    Primitive(&'a Hittable),
+    Record(HitRecord<'a>),
+}
+
 pub enum Hittable {
     HittableList(Vec<Hittable>),
-    Sphere(Vec3, f64, Material),
-    Raymarcher(SignedDistanceField, usize, f64, Material),
+    Sphere(Vec3, Float, Material),
+    /// Infinite plane through a point, with its normal.
+    Plane(Vec3, Vec3, Material),
+    /// Parallelogram from a corner along two edge vectors; a rectangle when they're
+    /// perpendicular.  Faces along the cross product of the edges.
+    Quad(Vec3, Vec3, Vec3, Material),
+    /// Disc with centre, normal and radius.
+    Disc(Vec3, Vec3, Float, Material),
+    /// Axis aligned box between two corners; orient it with an `Instance`.
+    Box(Vec3, Vec3, Material),
+    /// Capped cylinder standing on its base centre along +y, with radius and height.
+    Cylinder(Vec3, Float, Float, Material),
+    /// Cone standing on its base centre, with base radius and height to the apex above.
+    Cone(Vec3, Float, Float, Material),
+    /// Torus around the y axis through its centre, with major and minor radius.
+    Torus(Vec3, Float, Float, Material),
+    /// Sphere traced SDF.  Marching is clipped to the SDF's bounding box, so it starts
+    /// where a ray enters the box and rays that miss it never march at all.
+    Raymarcher(SignedDistanceField, MarchSettings, Material),
+    /// Zero set of a function that isn't a distance bound, like metaballs and algebraic
+    /// surfaces, found by root finding within its bounds.
+    Implicit(ImplicitSurface, Material),
+    Instance(Arc<Hittable>, Transform),
+    /// Medium filling the inside of the boundary, which is only used for its shape.
+    /// Volumes have no surface, rays find them through `sample_medium`.
+    Volume(Box<Hittable>, Medium),
+    /// Medium filling everything below the given height, for atmospheric fog that sky
+    /// light can still stream into from above.
+    Fog(Medium, Float),
+    /// Bounding volume hierarchy node: a box around both children, which are only
+    /// visited by rays through it.  Built by `bvh::build_bvh`.
+    Bvh(Aabb, Box<Hittable>, Box<Hittable>),
 }
 
 impl Hittable {
-    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        // lists and BVHs pass on their children's records, which already name the object
+        self.surface_hit(ray, t_min, t_max).map(|hit_record| match hit_record.object {
+            Some(_) => hit_record,
+            None => HitRecord { object: Some(self), ..hit_record },
+        })
+    }
+
+    fn surface_hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
         match self {
             Hittable::HittableList(hittables) => {
-                if let Some(min_hit_opt) = hittables.iter()
+                hittables.iter()
                     .map(|x| x.hit(ray, t_min, t_max))
                     .min_by(|x, y| {
                         match (x, y) {
@@ -72,13 +181,13 @@ impl Hittable {
                             (None, None) => Equal
                         }
                     })
-                { min_hit_opt } else { None }
+                    .flatten()
             },
 
             Hittable::Sphere(center, radius, mat) => {
-                let oc = (*ray).origin - (*center);
-                let a = (*ray).dir.length_squared();
-                let half_b = dot(oc, (*ray).dir);
+                let oc = ray.origin - (*center);
+                let a = ray.dir.length_squared();
+                let half_b = dot(oc, ray.dir);
                 let c = oc.length_squared() - (*radius) * (*radius);
                 let discriminant = half_b * half_b - a * c;
 
@@ -91,35 +200,641 @@ impl Hittable {
                             let outward_normal = (point - (*center)) / (*radius);
                             let (normal, front_face) =
                                 face_normal_adjustment(ray.dir, outward_normal);
-                            return Some(HitRecord::new(point, normal, &mat, t, front_face));
+                            return Some(HitRecord::new(point, normal, mat, t, front_face)
+                                .with_tangent(cross(Vec3::y_axis(), outward_normal))
+                                .with_uv(sphere_uv(outward_normal)));
                         }
                     }
                 }
                 None
             },
 
-            Hittable::Raymarcher(distance_field, max_march_steps,
-                                 min_distance, material) => {
-                let mut t_cur = t_min;
-                for _ in 0..(*max_march_steps) {
-                    let cur_point = ray.at(t_cur);
-                    let cur_distance = distance_field.distance_estimate(cur_point);
-                    if cur_distance < *min_distance {
-                        let outward_normal: Vec3 = distance_field.normal_estimate(cur_point);
-                        //let normal: Vec3 = -ray.dir;
-                        let (normal, front_face) =
-                            face_normal_adjustment(ray.dir, outward_normal);
-                        return Some(HitRecord::new(cur_point, normal, &material, t_cur, front_face))
+            Hittable::Plane(point, normal, material) => {
+                hit_plane(ray, *point, *normal, t_min, t_max).map(|hit| primitive_record(ray, hit, material))
+            },
+
+            Hittable::Quad(corner, u, v, material) => {
+                hit_quad(ray, *corner, *u, *v, t_min, t_max).map(|hit| primitive_record(ray, hit, material))
+            },
+
+            Hittable::Disc(center, normal, radius, material) => {
+                hit_disc(ray, *center, *normal, *radius, t_min, t_max).map(|hit| primitive_record(ray, hit, material))
+            },
+
+            Hittable::Box(min, max, material) => {
+                hit_box(ray, *min, *max, t_min, t_max).map(|hit| primitive_record(ray, hit, material))
+            },
+
+            Hittable::Cylinder(base, radius, height, material) => {
+                hit_cylinder(ray, *base, *radius, *height, t_min, t_max).map(|hit| primitive_record(ray, hit, material))
+            },
+
+            Hittable::Cone(base, radius, height, material) => {
+                hit_cone(ray, *base, *radius, *height, t_min, t_max).map(|hit| primitive_record(ray, hit, material))
+            },
+
+            Hittable::Torus(center, major_radius, minor_radius, material) => {
+                hit_torus(ray, *center, *major_radius, *minor_radius, t_min, t_max)
+                    .map(|hit| primitive_record(ray, hit, material))
+            },
+
+            Hittable::Raymarcher(distance_field, settings, material) => {
+                let march = bounded_march(distance_field, settings, ray, t_min, t_max)?;
+                if march.hit {
+                    // stay in f64 until the hit point is found, then round once
+                    let point = ray.origin.cast::<f64>() + march.t * ray.dir.cast::<f64>();
+                    let outward_normal: Vec3 = distance_field.normal_estimate(point).cast();
+                    let (normal, front_face) =
+                        face_normal_adjustment(ray.dir, outward_normal);
+                    // no parameterisation, so the tangent follows the SDF gradient's
+                    // azimuth around the y axis like a sphere's
+                    Some(HitRecord::new(point.cast(), normal, material, Float::from_f64(march.t), front_face)
+                        .with_tangent(cross(Vec3::y_axis(), outward_normal)))
+                }
+                else {
+                    None
+                }
+            },
+
+            Hittable::Implicit(surface, material) => {
+                let (min, max) = surface.bounds()?;
+                let (t_enter, t_exit) = Aabb::new(min.cast(), max.cast()).hit_interval(ray, t_min, t_max)?;
+                let t = surface.find_root(ray, t_enter.to_f64(), t_exit.to_f64())?;
+                let point = ray.origin.cast::<f64>() + t * ray.dir.cast::<f64>();
+                let outward_normal: Vec3 = surface.gradient(point).unit_vector().cast();
+                let (normal, front_face) = face_normal_adjustment(ray.dir, outward_normal);
+                Some(HitRecord::new(point.cast(), normal, material, Float::from_f64(t), front_face)
+                    .with_tangent(cross(Vec3::y_axis(), outward_normal)))
+            },
+
+            Hittable::Instance(object, transform) => {
+                let (object_ray, scale) = object_space_ray(ray, transform);
+                object.hit(&object_ray, t_min * scale, t_max * scale).map(|hit_record| {
+                    HitRecord::new(transform.transform_point(hit_record.point),
+                                   transform.transform_normal(hit_record.normal).unit_vector(),
+                                   hit_record.material, hit_record.t / scale, hit_record.front_face)
+                        .with_tangent(transform.transform_vector(hit_record.tangent))
+                        .with_uv(hit_record.uv)
+                })
+            },
+
+            Hittable::Volume(_, _) | Hittable::Fog(_, _) => None,
+
+            Hittable::Bvh(aabb, left, right) => {
+                if !aabb.hit(ray, t_min, t_max) {
+                    return None;
+                }
+                let left_hit = left.hit(ray, t_min, t_max);
+                let t_closest = left_hit.as_ref().map_or(t_max, |hit_record| hit_record.t);
+                right.hit(ray, t_min, t_closest).or(left_hit)
+            },
+        }
+    }
+
+    /// Closest hit per lane of a ray packet.  Spheres and BVH boxes are intersected
+    /// across all lanes at once; anything else falls back to a scalar `hit` per lane.
+    pub fn hit_packet<L: SimdFloat>(&self, packet: &RayPacket<L>, t_min: Float, t_max: Float) -> Vec<Option<HitRecord<'_>>> {
+        let mut closest = L::splat(t_max);
+        let mut winners: [Option<PacketHit>; MAX_LANES] = Default::default();
+        self.closest_hit_packet(packet, t_min, &mut closest, &mut winners);
+
+        // only the winning sphere per lane builds a full hit record
+        winners.iter_mut().take(L::LANES).enumerate().map(|(lane, winner)| {
+            match winner.take() {
+                Some(PacketHit::Primitive(hittable)) => hittable.hit(&packet.ray(lane), t_min, t_max),
+                Some(PacketHit::Record(hit_record)) => Some(hit_record),
+                None => None,
+            }
+        }).collect()
+    }
+
+    fn closest_hit_packet<'a, L: SimdFloat>(&'a self, packet: &RayPacket<L>, t_min: Float, closest: &mut L,
+                                            winners: &mut [Option<PacketHit<'a>>; MAX_LANES]) {
+        match self {
+            Hittable::HittableList(hittables) => {
+                for hittable in hittables {
+                    hittable.closest_hit_packet(packet, t_min, closest, winners);
+                }
+            },
+            Hittable::Sphere(center, radius, _) => {
+                let t = hit_sphere_packet(packet, *center, *radius, t_min, *closest);
+                let mut closer = L::mask_bits(t.lt(*closest));
+                while closer != 0 {
+                    winners[closer.trailing_zeros() as usize] = Some(PacketHit::Primitive(self));
+                    closer &= closer - 1;
+                }
+                *closest = closest.min(t);
+            },
+            Hittable::Bvh(aabb, left, right) => {
+                if L::mask_bits(hit_aabb_packet(packet, aabb.min, aabb.max, t_min, *closest)) != 0 {
+                    left.closest_hit_packet(packet, t_min, closest, winners);
+                    right.closest_hit_packet(packet, t_min, closest, winners);
+                }
+            },
+            _ => {
+                let mut hit_t = [Float::INFINITY; MAX_LANES];
+                for (lane, t) in hit_t.iter_mut().enumerate().take(L::LANES) {
+                    if let Some(hit_record) = self.hit(&packet.ray(lane), t_min, closest.lane(lane)) {
+                        *t = hit_record.t;
+                        winners[lane] = Some(PacketHit::Record(hit_record));
                     }
-                    else {
-                        t_cur += cur_distance;
-                        if t_cur > t_max {return None}
+                }
+                *closest = closest.min(L::from_fn(|lane| hit_t[lane]));
+            },
+        }
+    }
+
+    /// Total number of raymarching steps spent along `ray`, used for step-count heatmaps.
+    pub fn march_steps(&self, ray: &Ray, t_min: Float, t_max: Float) -> usize {
+        match self {
+            Hittable::HittableList(hittables) => {
+                hittables.iter().map(|x| x.march_steps(ray, t_min, t_max)).sum()
+            },
+            Hittable::Raymarcher(distance_field, settings, _) => {
+                bounded_march(distance_field, settings, ray, t_min, t_max).map_or(0, |march| march.steps)
+            },
+            Hittable::Instance(object, transform) => {
+                let (object_ray, scale) = object_space_ray(ray, transform);
+                object.march_steps(&object_ray, t_min * scale, t_max * scale)
+            },
+            Hittable::Volume(boundary, _) => boundary.march_steps(ray, t_min, t_max),
+            Hittable::Bvh(aabb, left, right) => {
+                if !aabb.hit(ray, t_min, t_max) {
+                    return 0;
+                }
+                left.march_steps(ray, t_min, t_max) + right.march_steps(ray, t_min, t_max)
+            },
+            _ => 0,
+        }
+    }
+
+    /// Lower bound on the distance from `point` to the nearest surface, which lets the
+    /// SDF shading queries run over whole scenes and not just raymarched objects.
+    pub fn distance_estimate(&self, point: Vec3) -> Float {
+        match self {
+            Hittable::HittableList(hittables) => {
+                hittables.iter()
+                    .map(|x| x.distance_estimate(point))
+                    .fold(Float::INFINITY, Float::min)
+            },
+            Hittable::Sphere(center, radius, _) => (point - *center).length() - *radius,
+            Hittable::Plane(plane_point, normal, _) => plane_distance(point, *plane_point, *normal),
+            Hittable::Quad(corner, u, v, _) => quad_distance(point, *corner, *u, *v),
+            Hittable::Disc(center, normal, radius, _) => disc_distance(point, *center, *normal, *radius),
+            Hittable::Box(min, max, _) => box_distance(point, *min, *max),
+            Hittable::Cylinder(base, radius, height, _) => cylinder_distance(point, *base, *radius, *height),
+            Hittable::Cone(base, radius, height, _) => cone_distance(point, *base, *radius, *height),
+            Hittable::Torus(center, major_radius, minor_radius, _) => {
+                torus_distance(point, *center, *major_radius, *minor_radius)
+            },
+            Hittable::Raymarcher(distance_field, _, _) => {
+                Float::from_f64(distance_field.distance_estimate(point.cast()))
+            },
+            Hittable::Implicit(surface, _) => Float::from_f64(surface.distance_estimate(point.cast())),
+            Hittable::Instance(object, transform) => {
+                object.distance_estimate(transform.inverse_transform_point(point))
+                    / transform.inverse_stretch_bound()
+            },
+            Hittable::Volume(_, _) | Hittable::Fog(_, _) => Float::INFINITY,
+            Hittable::Bvh(_, left, right) => {
+                // a subtree whose box is further than the nearest surface so far can't be nearer
+                [left, right].iter().fold(Float::INFINITY, |nearest, child| match child.as_ref() {
+                    Hittable::Bvh(aabb, _, _) if aabb.distance(point) >= nearest => nearest,
+                    _ => nearest.min(child.distance_estimate(point)),
+                })
+            },
+        }
+    }
+
+    /// Nearest scattering event in the scene's media along `ray` before `t_max`,
+    /// normally the closest surface hit.  Overlapping media each sample their own
+    /// distance and the nearest wins, which is the same as sampling their sum.
+    pub fn sample_medium<S: Sampler + ?Sized>(&self, sampler: &mut S, ray: &Ray, t_min: Float,
+                                              t_max: Float) -> Option<MediumEvent<'_>> {
+        match self {
+            Hittable::HittableList(hittables) => {
+                let mut closest: Option<MediumEvent> = None;
+                for hittable in hittables {
+                    let t_closest = closest.as_ref().map_or(t_max, |event| event.t);
+                    if let Some(event) = hittable.sample_medium(sampler, ray, t_min, t_closest) {
+                        closest = Some(event);
                     }
                 }
-                None
+                closest
+            },
+
+            Hittable::Volume(boundary, medium) => {
+                volume_segments(boundary, ray, t_min, t_max).into_iter().find_map(|(t_enter, t_exit)| {
+                    medium.sample_distance(sampler, ray, t_enter, t_exit)
+                }).map(|t| MediumEvent { point: ray.at(t), t, medium })
+            },
+
+            Hittable::Fog(medium, height) => {
+                fog_segment(*height, ray, t_min, t_max).and_then(|(t_enter, t_exit)| {
+                    medium.sample_distance(sampler, ray, t_enter, t_exit)
+                }).map(|t| MediumEvent { point: ray.at(t), t, medium })
+            },
+
+            Hittable::Instance(object, transform) => {
+                let (object_ray, scale) = object_space_ray(ray, transform);
+                object.sample_medium(sampler, &object_ray, t_min * scale, t_max * scale).map(|event| {
+                    MediumEvent { point: transform.transform_point(event.point), t: event.t / scale, medium: event.medium }
+                })
+            },
+
+            Hittable::Bvh(aabb, left, right) => {
+                if !aabb.hit(ray, t_min, t_max) {
+                    return None;
+                }
+                let left_event = left.sample_medium(sampler, ray, t_min, t_max);
+                let t_closest = left_event.as_ref().map_or(t_max, |event| event.t);
+                right.sample_medium(sampler, ray, t_min, t_closest).or(left_event)
+            },
+
+            _ => None,
+        }
+    }
+
+    /// Estimated fraction of light that gets through the scene's media along `ray`
+    /// between `t_min` and `t_max`.  Surfaces are ignored, shadow rays test them separately.
+    pub fn transmittance<S: Sampler + ?Sized>(&self, sampler: &mut S, ray: &Ray, t_min: Float, t_max: Float) -> Float {
+        self.media_transmittance(ray, t_min, t_max, &mut |medium, ray, t_enter, t_exit| {
+            medium.transmittance(sampler, ray, t_enter, t_exit)
+        })
+    }
+
+    /// Noise-free transmittance from `steps` point quadrature through every medium
+    /// segment, for previews.
+    pub fn transmittance_quadrature(&self, ray: &Ray, t_min: Float, t_max: Float, steps: usize) -> Float {
+        self.media_transmittance(ray, t_min, t_max, &mut |medium, ray, t_enter, t_exit| {
+            medium.transmittance_quadrature(ray, t_enter, t_exit, steps)
+        })
+    }
+
+    /// Product of `segment` over the spans of `ray` inside media, with the ray in the
+    /// medium's own space.
+    fn media_transmittance(&self, ray: &Ray, t_min: Float, t_max: Float,
+                           segment: &mut dyn FnMut(&Medium, &Ray, Float, Float) -> Float) -> Float {
+        match self {
+            Hittable::HittableList(hittables) => {
+                hittables.iter().map(|x| x.media_transmittance(ray, t_min, t_max, segment)).product()
+            },
+            Hittable::Volume(boundary, medium) => {
+                volume_segments(boundary, ray, t_min, t_max).into_iter()
+                    .map(|(t_enter, t_exit)| segment(medium, ray, t_enter, t_exit))
+                    .product()
+            },
+            Hittable::Fog(medium, height) => {
+                fog_segment(*height, ray, t_min, t_max)
+                    .map_or(1.0, |(t_enter, t_exit)| segment(medium, ray, t_enter, t_exit))
+            },
+            Hittable::Instance(object, transform) => {
+                let (object_ray, scale) = object_space_ray(ray, transform);
+                object.media_transmittance(&object_ray, t_min * scale, t_max * scale, segment)
+            },
+            Hittable::Bvh(aabb, left, right) => {
+                if !aabb.hit(ray, t_min, t_max) {
+                    return 1.0;
+                }
+                left.media_transmittance(ray, t_min, t_max, segment) * right.media_transmittance(ray, t_min, t_max, segment)
             },
+            _ => 1.0,
         }
     }
+
+    /// Box around everything in the hittable, `None` if it's unbounded (planes, fog)
+    /// or empty.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        match self {
+            Hittable::HittableList(hittables) => {
+                let mut boxes = hittables.iter().map(|x| x.bounding_box());
+                let first = boxes.next()??;
+                boxes.try_fold(first, |total, aabb| aabb.map(|aabb| total.union(&aabb)))
+            },
+            Hittable::Sphere(center, radius, _) => {
+                Some(Aabb::new(*center - Vec3::splat(radius.abs()), *center + Vec3::splat(radius.abs())))
+            },
+            Hittable::Plane(_, _, _) | Hittable::Fog(_, _) => None,
+            Hittable::Quad(corner, u, v, _) => {
+                Some(Aabb::around_points(&[*corner, *corner + *u, *corner + *v, *corner + *u + *v]).padded(1e-4))
+            },
+            Hittable::Disc(center, normal, radius, _) => {
+                // the disc's extent along each axis shrinks as the normal turns towards it
+                let n = normal.unit_vector();
+                let extent = *radius * Vec3::new((1.0 - n.x() * n.x()).max(0.0).sqrt(),
+                                                 (1.0 - n.y() * n.y()).max(0.0).sqrt(),
+                                                 (1.0 - n.z() * n.z()).max(0.0).sqrt());
+                Some(Aabb::new(*center - extent, *center + extent).padded(1e-4))
+            },
+            Hittable::Box(min, max, _) => Some(Aabb::new(*min, *max)),
+            Hittable::Cylinder(base, radius, height, _) | Hittable::Cone(base, radius, height, _) => {
+                Some(Aabb::new(*base - Vec3::new(*radius, 0.0, *radius), *base + Vec3::new(*radius, *height, *radius)))
+            },
+            Hittable::Torus(center, major_radius, minor_radius, _) => {
+                let extent = Vec3::new(major_radius + minor_radius, *minor_radius, major_radius + minor_radius);
+                Some(Aabb::new(*center - extent, *center + extent))
+            },
+            Hittable::Raymarcher(distance_field, _, _) => {
+                let (min, max) = distance_field.bounds();
+                Some(Aabb::new(min.cast(), max.cast()))
+            },
+            Hittable::Implicit(surface, _) => {
+                surface.bounds().map(|(min, max)| Aabb::new(min.cast(), max.cast()))
+            },
+            Hittable::Instance(object, transform) => object.bounding_box().map(|aabb| aabb.transformed(transform)),
+            Hittable::Volume(boundary, _) => boundary.bounding_box(),
+            Hittable::Bvh(aabb, _, _) => Some(*aabb),
+        }
+    }
+
+    pub fn ambient_occlusion(&self, point: Vec3, normal: Vec3, num_samples: usize, step_size: Float) -> Float {
+        ambient_occlusion(|p| self.distance_estimate(p), point, normal, num_samples, step_size)
+    }
+
+    pub fn soft_shadow(&self, origin: Vec3, light_dir: Vec3, t_min: Float, t_max: Float, penumbra: Float) -> Float {
+        soft_shadow(|p| self.distance_estimate(p), origin, light_dir, t_min, t_max, penumbra)
+    }
 }
 
+fn primitive_record<'a>(ray: &Ray, hit: PrimitiveHit, material: &'a Material) -> HitRecord<'a> {
+    let (normal, front_face) = face_normal_adjustment(ray.dir, hit.outward_normal);
+    HitRecord::new(ray.at(hit.t), normal, material, hit.t, front_face)
+        .with_tangent(hit.tangent)
+        .with_uv(hit.uv)
+}
+
+/// Sphere traces the part of `ray` inside the SDF's bounding box, padded by the hit
+/// distance, or `None` if the ray misses the box.
+fn bounded_march(distance_field: &SignedDistanceField, settings: &MarchSettings, ray: &Ray, t_min: Float,
+                 t_max: Float) -> Option<MarchResult> {
+    let (min, max) = distance_field.bounds();
+    let (t_enter, t_exit) = Aabb::new(min.cast(), max.cast())
+        .padded(Float::from_f64(settings.min_distance))
+        .hit_interval(ray, t_min, t_max)?;
+    Some(distance_field.sphere_trace(ray, t_enter.to_f64(), t_exit.to_f64(), settings))
+}
+
+/// Takes a world space ray into the instance's object space.  Rays keep unit length
+/// directions, so object space distances are world space distances times `scale`.
+fn object_space_ray(ray: &Ray, transform: &Transform) -> (Ray, Float) {
+    let object_dir = transform.inverse_transform_vector(ray.dir);
+    let scale = object_dir.length();
+    (Ray::new(transform.inverse_transform_point(ray.origin), object_dir), scale)
+}
+
+/// Parts of `ray` between `t_min` and `t_max` inside a volume's boundary, found by
+/// walking from one boundary crossing to the next so concave and disconnected
+/// boundaries (like SDFs) work as well as spheres.
+fn volume_segments(boundary: &Hittable, ray: &Ray, t_min: Float, t_max: Float) -> Vec<(Float, Float)> {
+    let mut segments = Vec::new();
+    let mut t_enter = t_min;
+    loop {
+        let crossing = boundary.hit(ray, t_enter, t_max);
+        // the side of the next crossing the ray comes from says whether it's inside,
+        // which holds even when it starts on the boundary where the distance is zero
+        let inside = match &crossing {
+            Some(hit_record) => !hit_record.front_face,
+            None => boundary.distance_estimate(ray.at(t_enter)) < 0.0,
+        };
+        let t_exit = crossing.as_ref().map_or(t_max, |hit_record| hit_record.t);
+        if inside && t_exit > t_enter {
+            segments.push((t_enter, t_exit));
+        }
+        if crossing.is_none() {
+            return segments;
+        }
+        t_enter = t_exit + 1e-4;
+    }
+}
+
+/// Part of `ray` between `t_min` and `t_max` below the fog's `height`.
+fn fog_segment(height: Float, ray: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float)> {
+    let t_cross = (height - ray.origin.y()) / ray.dir.y();
+    let (t_enter, t_exit) = if ray.dir.y() > 0.0 {
+        (t_min, t_max.min(t_cross))
+    } else if ray.dir.y() < 0.0 {
+        (t_min.max(t_cross), t_max)
+    } else if ray.origin.y() < height {
+        (t_min, t_max)
+    } else {
+        return None;
+    };
+    if t_enter < t_exit { Some((t_enter, t_exit)) } else { None }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::bvh::{build_bvh};
+    use crate::math::simd::{RayPacket4, RayPacket8};
+
+    fn packet_scene() -> Hittable {
+        let material = || Material::Lambertian(Vec3::new(0.5, 0.5, 0.5));
+        let mut hittables: Vec<Hittable> = (0..9).map(|i| {
+            let (row, column) = ((i / 3) as Float, (i % 3) as Float);
+            Hittable::Sphere(Vec3::new(column - 1.0, row - 1.0, -3.0 - 0.5 * column), 0.3 + 0.05 * row, material())
+        }).collect();
+        // overlaps the spheres, so lanes are won by different kinds of object
+        hittables.push(Hittable::Plane(Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 0.0, 1.0), material()));
+        hittables.push(Hittable::Disc(Vec3::new(0.6, 0.2, -2.5), Vec3::new(0.0, 0.3, 1.0), 0.4, material()));
+        build_bvh(hittables)
+    }
+
+    /// Camera rays over a grid spanning the scene, with the given number of rays per packet.
+    fn grid_rays(lanes: usize) -> Vec<Vec<Ray>> {
+        let rays: Vec<Ray> = (0..32 * 32).map(|i| {
+            let (x, y) = ((i % 32) as Float / 31.0, (i / 32) as Float / 31.0);
+            Ray::new(Vec3::new(0.1, -0.2, 1.0), Vec3::new(1.4 * x - 0.7, 1.4 * y - 0.7, -1.0))
+        }).collect();
+        rays.chunks(lanes).map(|chunk| chunk.to_vec()).collect()
+    }
+
+    fn assert_matches_scalar_hits(world: &Hittable, rays: &[Ray], packet_hits: Vec<Option<HitRecord>>) {
+        assert_eq!(packet_hits.len(), rays.len());
+        for (ray, packet_hit) in rays.iter().zip(packet_hits) {
+            match (world.hit(ray, 0.001, 100.0), packet_hit) {
+                (Some(scalar), Some(packet)) => {
+                    assert!((scalar.t - packet.t).abs() < 1e-4, "{:?}: {} vs {}", ray, scalar.t, packet.t);
+                    assert!(scalar.normal.approx_eq(packet.normal, 1e-4), "{:?}", ray);
+                    assert!(std::ptr::eq(scalar.material, packet.material), "{:?}", ray);
+                },
+                (None, None) => {},
+                (scalar, packet) => panic!("{:?}: scalar hit {}, packet hit {}", ray, scalar.is_some(), packet.is_some()),
+            }
+        }
+    }
+
+    #[test]
+    fn hit_packet_matches_scalar_hits_four_lanes() {
+        let world = packet_scene();
+        for rays in grid_rays(RayPacket4::LANES) {
+            assert_matches_scalar_hits(&world, &rays, world.hit_packet(&RayPacket4::new(&rays), 0.001, 100.0));
+        }
+    }
+
+    #[test]
+    fn hit_packet_matches_scalar_hits_eight_lanes() {
+        let world = packet_scene();
+        for rays in grid_rays(RayPacket8::LANES) {
+            assert_matches_scalar_hits(&world, &rays, world.hit_packet(&RayPacket8::new(&rays), 0.001, 100.0));
+        }
+    }
+
+    fn grey() -> Material {
+        Material::Lambertian(Vec3::new(0.5, 0.5, 0.5))
+    }
+
+    /// Rays from around `target` aimed at points scattered about it.
+    fn rays_towards(target: Vec3, spread: Float) -> Vec<Ray> {
+        (0..64).map(|i| {
+            let angle = i as Float * 0.7;
+            let origin = target + Vec3::new(5.0 * angle.cos(), 2.0 * (angle * 0.3).sin(), 5.0 * angle.sin());
+            let aim = target + spread * Vec3::new((angle * 1.3).sin(), (angle * 2.1).cos(), (angle * 0.9).sin());
+            Ray::new(origin, aim - origin)
+        }).collect()
+    }
+
+    #[test]
+    fn scaled_instance_hits_like_the_world_space_sphere() {
+        let center = Vec3::new(0.5, -1.0, 2.0);
+        let instance = Hittable::Instance(Arc::new(Hittable::Sphere(Vec3::zero(), 0.5, grey())),
+                                          Transform::uniform_scale(2.0).then(&Transform::translation(center)));
+        let sphere = Hittable::Sphere(center, 1.0, grey());
+        for ray in rays_towards(center, 1.2) {
+            match (instance.hit(&ray, 0.001, 100.0), sphere.hit(&ray, 0.001, 100.0)) {
+                (Some(a), Some(b)) => {
+                    assert!((a.t - b.t).abs() < 1e-4, "{:?}: {} vs {}", ray, a.t, b.t);
+                    assert!(a.point.approx_eq(b.point, 1e-4), "{:?}", ray);
+                    assert!(a.normal.approx_eq(b.normal, 1e-4), "{:?}", ray);
+                    assert_eq!(a.front_face, b.front_face);
+                },
+                (None, None) => {},
+                (a, b) => panic!("{:?}: instance hit {}, sphere hit {}", ray, a.is_some(), b.is_some()),
+            }
+        }
+    }
+
+    #[test]
+    fn nonuniform_instance_rescales_t_and_normals() {
+        // unit sphere stretched into an ellipsoid with semi-axes (3, 1, 0.5)
+        let radii = Vec3::new(3.0, 1.0, 0.5);
+        let ellipsoid = Hittable::Instance(Arc::new(Hittable::Sphere(Vec3::zero(), 1.0, grey())), Transform::scale(radii));
+
+        // along the axes t is the world space distance to the stretched surface
+        let along_x = ellipsoid.hit(&Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::x_axis()), 0.001, 100.0).unwrap();
+        assert!((along_x.t - 7.0).abs() < 1e-4, "{}", along_x.t);
+        let along_z = ellipsoid.hit(&Ray::new(Vec3::new(0.0, 0.0, 10.0), -Vec3::z_axis()), 0.001, 100.0).unwrap();
+        assert!((along_z.t - 9.5).abs() < 1e-4, "{}", along_z.t);
+
+        for ray in rays_towards(Vec3::zero(), 0.8) {
+            if let Some(hit) = ellipsoid.hit(&ray, 0.001, 100.0) {
+                assert!(ray.at(hit.t).approx_eq(hit.point, 1e-4), "{:?}", ray);
+                let p = hit.point;
+                assert!(((p / radii).length() - 1.0).abs() < 1e-4, "{:?}", ray);
+                // the inverse transpose gives the gradient of (x/a)^2 + (y/b)^2 + (z/c)^2
+                let gradient = (p / (radii * radii)).unit_vector();
+                let outward = if hit.front_face { hit.normal } else { -hit.normal };
+                assert!(outward.approx_eq(gradient, 1e-4), "{:?}: {:?} vs {:?}", ray, outward, gradient);
+            }
+        }
+    }
+
+    #[test]
+    fn instance_distance_estimate_is_conservative() {
+        let stretched = Transform::trs(Vec3::new(1.0, 0.5, -2.0), Vec3::new(1.0, 2.0, 0.5), 0.8, Vec3::new(2.5, 0.7, 1.2));
+        let instance = Hittable::Instance(Arc::new(Hittable::Sphere(Vec3::zero(), 1.0, grey())), stretched);
+        for ray in rays_towards(Vec3::new(1.0, 0.5, -2.0), 3.0) {
+            let point = ray.origin;
+            let estimate = instance.distance_estimate(point);
+            assert!(estimate > 0.0, "{:?}", point);
+            // no surface within the estimated distance, in any direction
+            for direction in rays_towards(Vec3::zero(), 1.0).iter().map(|probe| probe.dir) {
+                if let Some(hit) = instance.hit(&Ray::new(point, direction), 0.0, 100.0) {
+                    assert!(hit.t >= estimate * (1.0 - 1e-4), "{:?}: hit at {} within {}", point, hit.t, estimate);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotated_instance_bounding_box() {
+        // a quarter turn about y stretches a 2 x 2 x 2 box's footprint into a diamond
+        let rotated = Hittable::Instance(Arc::new(Hittable::Box(-Vec3::one(), Vec3::one(), grey())),
+                                         Transform::rotation(Vec3::y_axis(), TAU / 8.0)
+                                             .then(&Transform::translation(Vec3::new(0.0, 3.0, 0.0))));
+        let aabb = rotated.bounding_box().unwrap();
+        let half_diagonal = (2.0 as Float).sqrt();
+        assert!(aabb.min.approx_eq(Vec3::new(-half_diagonal, 2.0, -half_diagonal), 1e-4), "{:?}", aabb.min);
+        assert!(aabb.max.approx_eq(Vec3::new(half_diagonal, 4.0, half_diagonal), 1e-4), "{:?}", aabb.max);
+    }
+
+    fn inside_length(boundary: &Hittable, ray: &Ray) -> Float {
+        volume_segments(boundary, ray, 0.0, 10.0).iter().map(|(t_enter, t_exit)| t_exit - t_enter).sum()
+    }
+
+    #[test]
+    fn volume_segments_from_the_boundary() {
+        let boundaries = [
+            Hittable::Sphere(Vec3::zero(), 1.0, grey()),
+            Hittable::Box(Vec3::new(-1.0, -1.0, -1.0), Vec3::one(), grey()),
+        ];
+        for boundary in &boundaries {
+            // starting on the surface, where the distance estimate is zero
+            let inwards = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+            let length = inside_length(boundary, &inwards);
+            assert!((length - 2.0).abs() < 1e-3, "inwards {}", length);
+            let outwards = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, 1.0));
+            assert!(inside_length(boundary, &outwards) < 1e-3, "outwards {}", inside_length(boundary, &outwards));
+            // and from well inside and outside
+            let through = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+            assert!((inside_length(boundary, &through) - 2.0).abs() < 1e-3);
+            let from_centre = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+            assert!((inside_length(boundary, &from_centre) - 1.0).abs() < 1e-3);
+        }
+    }
+
+    fn marched_fields() -> Vec<SignedDistanceField> {
+        vec![
+            SignedDistanceField::Sphere(Vec3::new(0.5, 0.0, -3.0).cast(), 0.8),
+            SignedDistanceField::SierpinskiTetrasphere(Vec3::new(0.0, 0.2, -3.0).cast(), 3),
+        ]
+    }
+
+    #[test]
+    fn rays_missing_the_bound_never_march() {
+        let settings = MarchSettings::new(512, 1e-7);
+        for distance_field in marched_fields() {
+            let (min, max) = distance_field.bounds();
+            let world = Hittable::Raymarcher(distance_field, settings, grey());
+            // passing just outside the box, on either side of it
+            for ray in [Ray::new(Vec3::new(max.x() as Float + 0.01, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+                        Ray::new(Vec3::new(0.0, min.y() as Float - 0.01, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+                        Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.2))] {
+                assert_eq!(world.march_steps(&ray, 0.001, 100.0), 0, "{:?}", ray);
+                assert!(world.hit(&ray, 0.001, 100.0).is_none(), "{:?}", ray);
+            }
+        }
+    }
+
+    #[test]
+    fn bound_clipped_marching_hits_like_unclipped_marching() {
+        let settings = MarchSettings::new(512, 1e-7);
+        for (i, distance_field) in marched_fields().iter().enumerate() {
+            let world = Hittable::Raymarcher(marched_fields().remove(i), settings, grey());
+            let mut hits = 0;
+            for ray in rays_towards(Vec3::new(0.2, 0.1, -3.0), 1.2) {
+                let unclipped = distance_field.sphere_trace(&ray, 0.001, 100.0, &settings);
+                match world.hit(&ray, 0.001, 100.0) {
+                    Some(hit_record) => {
+                        hits += 1;
+                        assert!(unclipped.hit, "{:?}", ray);
+                        assert!((hit_record.t().to_f64() - unclipped.t).abs() < 1e-5, "{:?}: {} vs {}",
+                                ray, hit_record.t(), unclipped.t);
+                    },
+                    None => assert!(!unclipped.hit, "{:?}", ray),
+                }
+            }
+            assert!(hits > 0);
+        }
+    }
+}
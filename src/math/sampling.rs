@@ -0,0 +1,531 @@
+use std::sync::OnceLock;
+use rand::Rng;
+use rand::prelude::ThreadRng;
+
+use crate::math::utils::{Float};
+
+/// Source of sample values in [0, 1).  Each pixel sample draws its dimensions in a fixed
+/// order: the pixel jitter first, then two or three per bounce for the BSDF, so the
+/// same dimension always drives the same decision and low-discrepancy samplers stay
+/// decorrelated between them.
+pub trait Sampler {
+    /// Begins sample `sample_index` of pixel (x, y), restarting at dimension 0.
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: usize);
+    fn next_1d(&mut self) -> Float;
+    fn next_2d(&mut self) -> (Float, Float);
+}
+
+/// `ThreadRng` is the independent sampler: every value is a fresh uniform random
+/// number and the pixel and sample index are ignored.
+impl Sampler for ThreadRng {
+    fn start_pixel_sample(&mut self, _x: u32, _y: u32, _sample_index: usize) {}
+
+    fn next_1d(&mut self) -> Float {
+        self.gen_range(0.0, 1.0)
+    }
+
+    fn next_2d(&mut self) -> (Float, Float) {
+        (self.gen_range(0.0, 1.0), self.gen_range(0.0, 1.0))
+    }
+}
+
+impl<S: Sampler + ?Sized> Sampler for Box<S> {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: usize) {
+        (**self).start_pixel_sample(x, y, sample_index)
+    }
+
+    fn next_1d(&mut self) -> Float {
+        (**self).next_1d()
+    }
+
+    fn next_2d(&mut self) -> (Float, Float) {
+        (**self).next_2d()
+    }
+}
+
+/// Which sampler the renderer builds for each pixel.
+#[derive(Debug, Copy, Clone)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// `seed` decorrelates otherwise identical renders, e.g. consecutive frames.
+    pub fn build(&self, samples_per_pixel: usize, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(rand::thread_rng()),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(seed)),
+        }
+    }
+}
+
+/// Position within a pixel's sample sequence shared by the deterministic samplers.
+#[derive(Debug, Copy, Clone, Default)]
+struct SampleState {
+    x: u32,
+    y: u32,
+    sample_index: usize,
+    dimension: u64,
+}
+
+impl SampleState {
+    fn start(&mut self, x: u32, y: u32, sample_index: usize) {
+        *self = SampleState { x, y, sample_index, dimension: 0 };
+    }
+
+    /// Claims the next `count` dimensions, returning the first.
+    fn take(&mut self, count: u64) -> u64 {
+        self.dimension += count;
+        self.dimension - count
+    }
+
+    fn pixel_hash(&self, dimension: u64, seed: u64) -> u64 {
+        hash(&[self.x as u64, self.y as u64, dimension, seed])
+    }
+}
+
+/// Jittered stratification: every dimension splits [0, 1) (or the unit square for
+/// 2D draws) into one stratum per sample, visited in a random order per pixel and
+/// dimension so that strata of different dimensions don't line up.
+#[derive(Debug, Copy, Clone)]
+pub struct StratifiedSampler {
+    samples_per_pixel: usize,
+    seed: u64,
+    state: SampleState,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> StratifiedSampler {
+        StratifiedSampler { samples_per_pixel: samples_per_pixel.max(1), seed, state: SampleState::default() }
+    }
+
+    fn stratum(&self, dimension: u64, num_strata: usize) -> usize {
+        let index = (self.state.sample_index % num_strata) as u32;
+        permutation_element(index, num_strata as u32, self.state.pixel_hash(dimension, self.seed) as u32) as usize
+    }
+
+    fn jitter(&self, dimension: u64) -> f64 {
+        hash(&[self.state.pixel_hash(dimension, self.seed), self.state.sample_index as u64]) as u32 as f64 / 4_294_967_296.0
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: usize) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn next_1d(&mut self) -> Float {
+        let dimension = self.state.take(1);
+        let stratum = self.stratum(dimension, self.samples_per_pixel);
+        round_down((stratum as f64 + self.jitter(dimension)) / self.samples_per_pixel as f64)
+    }
+
+    fn next_2d(&mut self) -> (Float, Float) {
+        let dimension = self.state.take(2);
+        let x_strata = (self.samples_per_pixel as Float).sqrt().floor() as usize;
+        let y_strata = self.samples_per_pixel / x_strata;
+        let stratum = self.stratum(dimension, x_strata * y_strata);
+        let (sx, sy) = (stratum % x_strata, stratum / x_strata);
+        (round_down((sx as f64 + self.jitter(dimension)) / x_strata as f64),
+         round_down((sy as f64 + self.jitter(dimension + 1)) / y_strata as f64))
+    }
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Halton sequence with one prime base per dimension, randomised per pixel by a
+/// Cranley-Patterson rotation.  Dimensions past the prime table fall back to hashed
+/// uniform values.
+#[derive(Debug, Copy, Clone)]
+pub struct HaltonSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler { seed, state: SampleState::default() }
+    }
+
+    fn sample(&self, dimension: u64) -> Float {
+        let rotation = to_unit(self.state.pixel_hash(dimension, self.seed) as u32);
+        let value = match PRIMES.get(dimension as usize) {
+            Some(base) => radical_inverse(*base, self.state.sample_index as u64),
+            None => to_unit(hash(&[self.state.pixel_hash(dimension, self.seed), self.state.sample_index as u64]) as u32),
+        };
+        wrap(value + rotation)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: usize) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn next_1d(&mut self) -> Float {
+        let dimension = self.state.take(1);
+        self.sample(dimension)
+    }
+
+    fn next_2d(&mut self) -> (Float, Float) {
+        let dimension = self.state.take(2);
+        (self.sample(dimension), self.sample(dimension + 1))
+    }
+}
+
+/// Owen scrambled Sobol points (Burley 2020): every 2D draw takes the first two Sobol
+/// dimensions, which form a (0, 2)-sequence, with a per pixel and dimension index
+/// shuffle and nested uniform scrambling to keep the draws independent of each other.
+#[derive(Debug, Copy, Clone)]
+pub struct SobolSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler { seed, state: SampleState::default() }
+    }
+
+    fn shuffled_index(&self, seed: u32) -> u32 {
+        nested_uniform_scramble(self.state.sample_index as u32, seed)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: usize) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn next_1d(&mut self) -> Float {
+        let dimension = self.state.take(1);
+        let seed = self.state.pixel_hash(dimension, self.seed);
+        let index = self.shuffled_index(seed as u32);
+        to_unit(nested_uniform_scramble(index.reverse_bits(), (seed >> 32) as u32))
+    }
+
+    fn next_2d(&mut self) -> (Float, Float) {
+        let dimension = self.state.take(2);
+        let seed = self.state.pixel_hash(dimension, self.seed);
+        let index = self.shuffled_index(seed as u32);
+        let (x, y) = sobol_2d(index);
+        let scramble_seed = mix_bits(seed);
+        (to_unit(nested_uniform_scramble(x, scramble_seed as u32)),
+         to_unit(nested_uniform_scramble(y, (scramble_seed >> 32) as u32)))
+    }
+}
+
+/// Blue noise dithered sampling (Georgiev and Fajardo 2016): each dimension walks a
+/// golden ratio (1D) or plastic number (2D) sequence over the sample index, offset
+/// per pixel by a toroidally shifted blue noise tile.  Errors of neighbouring pixels
+/// are then anti-correlated and show up as high frequency, easily filtered noise.
+#[derive(Debug, Copy, Clone)]
+pub struct BlueNoiseSampler {
+    seed: u64,
+    state: SampleState,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(seed: u64) -> BlueNoiseSampler {
+        BlueNoiseSampler { seed, state: SampleState::default() }
+    }
+
+    fn offset(&self, dimension: u64) -> Float {
+        let shift = hash(&[dimension, self.seed]);
+        let x = (self.state.x as usize + (shift & 0xffff) as usize) % BLUE_NOISE_SIZE;
+        let y = (self.state.y as usize + ((shift >> 16) & 0xffff) as usize) % BLUE_NOISE_SIZE;
+        blue_noise_tile()[y * BLUE_NOISE_SIZE + x]
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: usize) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn next_1d(&mut self) -> Float {
+        let dimension = self.state.take(1);
+        let golden = 0.618_033_988_749_895;
+        wrap(self.offset(dimension) + (golden * self.state.sample_index as f64).fract() as Float)
+    }
+
+    fn next_2d(&mut self) -> (Float, Float) {
+        let dimension = self.state.take(2);
+        // R2 sequence (Roberts 2018), the 2D generalisation of the golden ratio
+        let plastic: f64 = 1.324_717_957_244_746;
+        let n = self.state.sample_index as f64;
+        (wrap(self.offset(dimension) + (n / plastic).fract() as Float),
+         wrap(self.offset(dimension + 1) + (n / (plastic * plastic)).fract() as Float))
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Values in [0, 1) for a tileable blue noise mask, built once on first use.
+fn blue_noise_tile() -> &'static [Float] {
+    static TILE: OnceLock<Vec<Float>> = OnceLock::new();
+    TILE.get_or_init(|| {
+        let num_pixels = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+        void_and_cluster(BLUE_NOISE_SIZE, 1.9).iter()
+            .map(|rank| (*rank as Float + 0.5) / num_pixels as Float)
+            .collect()
+    })
+}
+
+/// Ulichney's void-and-cluster method: ranks every cell of a `size` x `size` torus so
+/// that each prefix of the ranking is an evenly spread point set.
+fn void_and_cluster(size: usize, sigma: f64) -> Vec<usize> {
+    let num_pixels = size * size;
+    let kernel: Vec<f64> = (0..num_pixels).map(|index| {
+        let wrapped = |d: usize| d.min(size - d) as f64;
+        let (dx, dy) = (wrapped(index % size), wrapped(index / size));
+        (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+    }).collect();
+
+    let mut energy = vec![0.0; num_pixels];
+    let mut is_set = vec![false; num_pixels];
+    let toggle = |index: usize, set: bool, energy: &mut Vec<f64>, is_set: &mut Vec<bool>| {
+        is_set[index] = set;
+        let sign = if set { 1.0 } else { -1.0 };
+        let (px, py) = (index % size, index / size);
+        for (cell, e) in energy.iter_mut().enumerate() {
+            let dx = (cell % size + size - px) % size;
+            let dy = (cell / size + size - py) % size;
+            *e += sign * kernel[dy * size + dx];
+        }
+    };
+    let tightest_cluster = |energy: &[f64], is_set: &[bool]| {
+        (0..num_pixels).filter(|i| is_set[*i]).max_by(|a, b| energy[*a].partial_cmp(&energy[*b]).unwrap()).unwrap()
+    };
+    let largest_void = |energy: &[f64], is_set: &[bool]| {
+        (0..num_pixels).filter(|i| !is_set[*i]).min_by(|a, b| energy[*a].partial_cmp(&energy[*b]).unwrap()).unwrap()
+    };
+
+    // initial pattern: a sparse hashed set, relaxed by moving points from the tightest
+    // cluster into the largest void until that no longer changes anything
+    let initial_count = num_pixels / 10;
+    let mut placed = 0;
+    let mut candidate = 0;
+    while placed < initial_count {
+        let index = (hash(&[candidate, 0x5eed]) % num_pixels as u64) as usize;
+        candidate += 1;
+        if !is_set[index] {
+            toggle(index, true, &mut energy, &mut is_set);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&energy, &is_set);
+        toggle(cluster, false, &mut energy, &mut is_set);
+        let void = largest_void(&energy, &is_set);
+        toggle(void, true, &mut energy, &mut is_set);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; num_pixels];
+    let (initial_energy, initial_set) = (energy.clone(), is_set.clone());
+    for r in (0..initial_count).rev() {
+        let cluster = tightest_cluster(&energy, &is_set);
+        toggle(cluster, false, &mut energy, &mut is_set);
+        rank[cluster] = r;
+    }
+    energy = initial_energy;
+    is_set = initial_set;
+    for r in initial_count..num_pixels {
+        let void = largest_void(&energy, &is_set);
+        toggle(void, true, &mut energy, &mut is_set);
+        rank[void] = r;
+    }
+    rank
+}
+
+const ONE_MINUS_EPSILON: Float = 1.0 - Float::EPSILON / 2.0;
+
+/// Upper 32 bits of a fraction as a float in [0, 1).
+fn to_unit(bits: u32) -> Float {
+    round_down(bits as f64 / 4_294_967_296.0)
+}
+
+/// Nearest `Float` at or below `value`, so that with `f32` values just under a stratum
+/// boundary (or under 1) don't round up across it.  `value` is never negative, so
+/// stepping the bits down by one moves to the next smaller float.
+fn round_down(value: f64) -> Float {
+    let rounded = value as Float;
+    if rounded as f64 > value { Float::from_bits(rounded.to_bits() - 1) } else { rounded }
+}
+
+fn wrap(value: Float) -> Float {
+    let wrapped = if value >= 1.0 { value - 1.0 } else { value };
+    wrapped.min(ONE_MINUS_EPSILON)
+}
+
+fn radical_inverse(base: u64, mut index: u64) -> Float {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed = 0;
+    let mut inverse_base_power = 1.0;
+    while index > 0 {
+        reversed = reversed * base + index % base;
+        inverse_base_power *= inverse_base;
+        index /= base;
+    }
+    round_down(reversed as f64 * inverse_base_power)
+}
+
+/// First two Sobol dimensions as 32 bit fractions.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut y = 0;
+    let mut direction: u32 = 1 << 31;
+    let mut i = index;
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= direction;
+        }
+        i >>= 1;
+        direction ^= direction >> 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+/// Laine-Karras style hash that only lets higher bits depend on lower ones, which on
+/// bit reversed input gives a nested uniform (Owen) scramble.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Element `i` of a random permutation of 0..n chosen by `seed` (Kensler 2013).
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % n
+}
+
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, v| mix_bits(h ^ mix_bits(v.wrapping_add(0x9e3779b97f4a7c15))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_KINDS: [SamplerKind; 5] = [
+        SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise,
+    ];
+
+    /// Alternating 1D and 2D draws for every sample of pixel (x, y), enough of them to
+    /// run past the Halton prime table.
+    fn draws(kind: SamplerKind, samples_per_pixel: usize, x: u32, y: u32) -> Vec<Vec<(Float, Float)>> {
+        let mut sampler = kind.build(samples_per_pixel, 7);
+        (0..samples_per_pixel).map(|s| {
+            sampler.start_pixel_sample(x, y, s);
+            (0..24).map(|d| if d % 2 == 0 { (sampler.next_1d(), 0.0) } else { sampler.next_2d() }).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn samples_are_in_unit_interval() {
+        for kind in ALL_KINDS {
+            for (x, y) in [(0, 0), (5, 3), (1023, 767)] {
+                for sample in draws(kind, 16, x, y) {
+                    for (a, b) in sample {
+                        assert!((0.0..1.0).contains(&a) && (0.0..1.0).contains(&b), "{:?}: {} {}", kind, a, b);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Every draw of the pixel's samples lands once in each of its `n` 1D strata, or
+    /// each cell of a ⌊√n⌋ × n / ⌊√n⌋ grid for 2D draws.
+    fn assert_fills_strata(kind: SamplerKind, samples_per_pixel: usize) {
+        let x_strata = (samples_per_pixel as Float).sqrt().floor() as usize;
+        let y_strata = samples_per_pixel / x_strata;
+        for (x, y) in [(0, 0), (17, 4), (300, 200)] {
+            let samples = draws(kind, samples_per_pixel, x, y);
+            for d in 0..samples[0].len() {
+                let mut filled = vec![false; samples_per_pixel];
+                for sample in &samples {
+                    let (a, b) = sample[d];
+                    let stratum = if d % 2 == 0 {
+                        (a * samples_per_pixel as Float) as usize
+                    } else {
+                        (a * x_strata as Float) as usize + x_strata * (b * y_strata as Float) as usize
+                    };
+                    assert!(!filled[stratum], "{:?} with {} samples: draw {} repeats stratum {}",
+                            kind, samples_per_pixel, d, stratum);
+                    filled[stratum] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_fills_every_stratum() {
+        for samples_per_pixel in [1, 2, 4, 8, 16, 64, 256] {
+            assert_fills_strata(SamplerKind::Stratified, samples_per_pixel);
+        }
+    }
+
+    #[test]
+    fn sobol_fills_every_stratum() {
+        for samples_per_pixel in [1, 2, 4, 8, 16, 64, 256] {
+            assert_fills_strata(SamplerKind::Sobol, samples_per_pixel);
+        }
+    }
+}
@@ -1,12 +1,23 @@
+use std::sync::Arc;
+
 use crate::math::math3::{Vec3};
+use crate::math::raytracing::Ray;
+use crate::math::utils::{Real};
+use crate::math::voxel_grid::{VoxelGrid};
+
+/// Distance fields are always evaluated in `f64`: the fractal marcher's hit epsilon
+/// is far below what `f32` can resolve at typical scene distances.
+type DVec3 = Vec3<f64>;
 
 pub enum SignedDistanceField {
-    Sphere(Vec3, f64),
-    SierpinskiTetrasphere(Vec3, usize),
+    Sphere(DVec3, f64),
+    SierpinskiTetrasphere(DVec3, usize),
+    /// Sampled distances, shared so large grids can be instanced.
+    VoxelGrid(Arc<VoxelGrid>),
 }
 
 impl SignedDistanceField {
-    pub fn distance_estimate(&self, point: Vec3) -> f64 {
+    pub fn distance_estimate(&self, point: DVec3) -> f64 {
         match self {
             Self::Sphere(center, radius) => {
                 let dif_to_center = point - *center;
@@ -15,7 +26,7 @@ impl SignedDistanceField {
 
             Self::SierpinskiTetrasphere(center, num_fractal_iterations) => {
                 let mut cur_vec = point - *center;
-                let offset = Vec3::one();
+                let offset = DVec3::one();
                 for _ in 0..(*num_fractal_iterations) {
                     if cur_vec.x() + cur_vec.y() < 0.0 {
                         cur_vec = Vec3::new(-cur_vec.y(), -cur_vec.x(), cur_vec.z());
@@ -29,11 +40,29 @@ impl SignedDistanceField {
                     cur_vec = cur_vec * 2.0 - offset;
                 }
                 (cur_vec.length() - 0.9) * 2.0_f64.powi(-(*num_fractal_iterations as i32))
-            }
+            },
+
+            Self::VoxelGrid(grid) => grid.distance(point),
         }
     }
 
-    pub fn normal_estimate(&self, point: Vec3) -> Vec3 {
+    /// Corners of a box the surface lies within.
+    pub fn bounds(&self) -> (DVec3, DVec3) {
+        match self {
+            Self::Sphere(center, radius) => (*center - DVec3::splat(*radius), *center + DVec3::splat(*radius)),
+
+            // the folds keep every sphere centre inside the tetrahedron with corners at
+            // (±1, ±1, ±1), and the last spheres have radius 0.9 / 2^iterations
+            Self::SierpinskiTetrasphere(center, num_fractal_iterations) => {
+                let extent = DVec3::splat(1.0 + 0.9 * 2.0_f64.powi(-(*num_fractal_iterations as i32)));
+                (*center - extent, *center + extent)
+            },
+
+            Self::VoxelGrid(grid) => grid.bounds(),
+        }
+    }
+
+    pub fn normal_estimate(&self, point: DVec3) -> DVec3 {
         match self {
             Self::Sphere(center, _) => {
                 let dif_to_center = point - *center;
@@ -41,11 +70,250 @@ impl SignedDistanceField {
             },
 
             Self::SierpinskiTetrasphere(_, _) => {
-                // TODO: placeholder! DO NOT USE FOR specular reflections!
-                Vec3::z_axis()
+                gradient_estimate(|p| self.distance_estimate(p), point, 0.00001).unit_vector()
+            },
+
+            // trilinear gradients jump between voxels, so difference across half of one
+            Self::VoxelGrid(grid) => {
+                gradient_estimate(|p| self.distance_estimate(p), point, 0.5 * grid.voxel_size).unit_vector()
+            },
+        }
+    }
+
+    pub fn ambient_occlusion(&self, point: DVec3, normal: DVec3, num_samples: usize, step_size: f64) -> f64 {
+        ambient_occlusion(|p| self.distance_estimate(p), point, normal, num_samples, step_size)
+    }
+
+    pub fn soft_shadow(&self, origin: DVec3, light_dir: DVec3, t_min: f64, t_max: f64, penumbra: f64) -> f64 {
+        soft_shadow(|p| self.distance_estimate(p), origin, light_dir, t_min, t_max, penumbra)
+    }
+
+    /// Enhanced sphere tracing (Keinert et al. 2014): steps are over-relaxed by
+    /// `settings.relaxation` and fall back to a plain step whenever the unbounding
+    /// spheres of consecutive steps stop overlapping.  A hit is accepted once the
+    /// distance drops under `min_distance` or under the pixel footprint at `t`.
+    pub fn sphere_trace(&self, ray: &Ray, t_min: f64, t_max: f64, settings: &MarchSettings) -> MarchResult {
+        let origin: DVec3 = ray.origin.cast();
+        let dir: DVec3 = ray.dir.cast();
+        let mut omega = settings.relaxation;
+        let mut t_cur = t_min;
+        let mut step_length = 0.0;
+        let mut prev_radius = 0.0;
+        let mut candidate_t = t_min;
+        let mut candidate_error = f64::INFINITY;
+
+        for step in 0..settings.max_march_steps {
+            let radius = self.distance_estimate(origin + t_cur * dir).abs();
+            let relaxation_failed = omega > 1.0 && radius + prev_radius < step_length;
+
+            if relaxation_failed {
+                // the over-relaxed step skipped past the surface: return to the
+                // previous point and continue with plain sphere tracing
+                t_cur -= step_length;
+                omega = 1.0;
+                step_length = 0.0;
+                prev_radius = 0.0;
+                continue;
+            }
+
+            let epsilon = settings.min_distance.max(settings.pixel_footprint * t_cur);
+            if radius < epsilon {
+                return MarchResult { t: t_cur, steps: step + 1, hit: true };
+            }
+
+            let error = radius / t_cur.max(settings.min_distance);
+            if error < candidate_error {
+                candidate_t = t_cur;
+                candidate_error = error;
+            }
+
+            step_length = radius * omega;
+            prev_radius = radius;
+            t_cur += step_length;
+            if t_cur > t_max {
+                if omega > 1.0 {
+                    // an over-relaxed step may have jumped past t_max and a surface
+                    // with it, so retry the last step unrelaxed before giving up
+                    t_cur -= step_length;
+                    omega = 1.0;
+                    step_length = 0.0;
+                    prev_radius = 0.0;
+                    continue;
+                }
+                return MarchResult { t: t_cur, steps: step + 1, hit: false };
             }
         }
+
+        match settings.on_exhausted {
+            MarchExhausted::Miss => MarchResult { t: t_cur, steps: settings.max_march_steps, hit: false },
+            MarchExhausted::ClosestApproach(max_error) => MarchResult {
+                t: candidate_t, steps: settings.max_march_steps, hit: candidate_error < max_error
+            },
+        }
     }
 }
 
+/// Central-difference gradient of a distance function using the four vertices of a
+/// tetrahedron, which needs one evaluation fewer than the axis-aligned version.
+pub fn gradient_estimate<R: Real, F: Fn(Vec3<R>) -> R>(distance: F, point: Vec3<R>, h: R) -> Vec3<R> {
+    let (one, minus_one) = (R::ONE, -R::ONE);
+    let k0 = Vec3::new(one, minus_one, minus_one);
+    let k1 = Vec3::new(minus_one, minus_one, one);
+    let k2 = Vec3::new(minus_one, one, minus_one);
+    let k3 = Vec3::new(one, one, one);
+    k0 * distance(point + k0 * h) + k1 * distance(point + k1 * h)
+        + k2 * distance(point + k2 * h) + k3 * distance(point + k3 * h)
+}
 
+/// Cone-traced ambient occlusion: compares the distance field a few steps along the
+/// normal with the distance travelled.  Returns 1 for fully open, 0 for fully occluded.
+pub fn ambient_occlusion<R: Real, F: Fn(Vec3<R>) -> R>(distance: F, point: Vec3<R>, normal: Vec3<R>,
+                                                      num_samples: usize, step_size: R) -> R {
+    let mut occlusion = R::ZERO;
+    let mut falloff = R::ONE;
+    for i in 1..=num_samples {
+        let h = step_size * R::from_f64(i as f64);
+        occlusion += (h - distance(point + normal * h)) * falloff;
+        falloff *= R::from_f64(0.5);
+    }
+    (R::ONE - R::from_f64(2.0) * occlusion / step_size).max(R::ZERO).min(R::ONE)
+}
+
+/// Penumbra estimate from marching toward a light: the closest miss relative to the
+/// distance travelled gives the fraction of the light visible.  Larger `penumbra`
+/// values give harder shadows.
+pub fn soft_shadow<R: Real, F: Fn(Vec3<R>) -> R>(distance: F, origin: Vec3<R>, light_dir: Vec3<R>,
+                                                t_min: R, t_max: R, penumbra: R) -> R {
+    let hit_distance = R::from_f64(0.00001);
+    let mut visibility = R::ONE;
+    let mut t_cur = t_min;
+    let mut prev_distance = R::INFINITY;
+    for _ in 0..128 {
+        let cur_distance = distance(origin + light_dir * t_cur);
+        if cur_distance < hit_distance {
+            return R::ZERO;
+        }
+        // improved penumbra (Aaltonen): estimate the closest approach between the
+        // last two unbounding spheres instead of using the sample point itself
+        let y = cur_distance * cur_distance / (R::from_f64(2.0) * prev_distance);
+        let d = (cur_distance * cur_distance - y * y).max(R::ZERO).sqrt();
+        visibility = visibility.min(penumbra * d / (t_cur - y).max(hit_distance));
+        prev_distance = cur_distance;
+        t_cur += cur_distance;
+        if t_cur > t_max {
+            break;
+        }
+    }
+    visibility.max(R::ZERO).min(R::ONE)
+}
+
+/// What a raymarcher reports when it runs out of steps before reaching the surface.
+#[derive(Debug, Copy, Clone)]
+pub enum MarchExhausted {
+    /// Treat the ray as a miss (can leave holes in thin fractal detail).
+    Miss,
+    /// Report a hit at the point where distance / t was smallest along the march,
+    /// provided that ratio is below the given tolerance.
+    ClosestApproach(f64),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct MarchSettings {
+    pub max_march_steps: usize,
+    pub min_distance: f64,
+    /// Angular size of a pixel; the hit epsilon grows as `pixel_footprint * t`.
+    pub pixel_footprint: f64,
+    /// Over-relaxation factor in [1, 2); 1 is plain sphere tracing.
+    pub relaxation: f64,
+    pub on_exhausted: MarchExhausted,
+}
+
+impl MarchSettings {
+    /// Plain sphere tracing that misses when it runs out of steps, like the original
+    /// marcher; relaxation and closest approach hits are opted into with the builders.
+    pub fn new(max_march_steps: usize, min_distance: f64) -> MarchSettings {
+        MarchSettings {
+            max_march_steps,
+            min_distance,
+            pixel_footprint: 0.0,
+            relaxation: 1.0,
+            on_exhausted: MarchExhausted::Miss,
+        }
+    }
+
+    pub fn with_pixel_footprint(self, pixel_footprint: f64) -> MarchSettings {
+        MarchSettings { pixel_footprint, ..self }
+    }
+
+    pub fn with_relaxation(self, relaxation: f64) -> MarchSettings {
+        MarchSettings { relaxation, ..self }
+    }
+
+    pub fn with_on_exhausted(self, on_exhausted: MarchExhausted) -> MarchSettings {
+        MarchSettings { on_exhausted, ..self }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct MarchResult {
+    pub t: f64,
+    pub steps: usize,
+    pub hit: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::utils::{Float};
+
+    fn sphere() -> SignedDistanceField {
+        SignedDistanceField::Sphere(Vec3::new(0.0, 0.0, -5.0), 1.0)
+    }
+
+    #[test]
+    fn sphere_trace_hits_sphere_at_analytic_distance() {
+        let ray = Ray::new(Vec3::new(0.0, 0.3, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        // the near side of the sphere is where z = -5 + √(1 - 0.3²)
+        let expected = 5.0 - (1.0 - 0.09_f64).sqrt();
+        for relaxation in [1.0, 1.3, 1.6, 1.9] {
+            let settings = MarchSettings::new(256, 1e-7).with_relaxation(relaxation);
+            let march = sphere().sphere_trace(&ray, 0.0, 100.0, &settings);
+            assert!(march.hit, "relaxation {}", relaxation);
+            assert!((march.t - expected).abs() < 1e-6, "relaxation {}: {} vs {}", relaxation, march.t, expected);
+        }
+    }
+
+    #[test]
+    fn sphere_trace_backtracks_after_overshooting() {
+        // the first step of 1.6 × 4 lands at t = 6.4, inside the sphere and short of its
+        // far side at t = 6; without backtracking the march would stop there
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let settings = MarchSettings::new(64, 1e-7).with_relaxation(1.6);
+        let march = sphere().sphere_trace(&ray, 0.0, 100.0, &settings);
+        assert!(march.hit);
+        assert!((march.t - 4.0).abs() < 1e-6, "{}", march.t);
+        // the step out, the rejected step back and the plain step onto the surface
+        assert!(march.steps <= 4, "{}", march.steps);
+    }
+
+    #[test]
+    fn exhausted_march_misses_or_reports_closest_approach() {
+        // grazes the sphere 1e-3 above its top, where steps shrink to nearly nothing
+        let ray = Ray::new(Vec3::new(0.0, 1.001, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let settings = MarchSettings::new(12, 1e-7).with_relaxation(1.0);
+
+        let miss = sphere().sphere_trace(&ray, 0.0, 100.0, &settings.with_on_exhausted(MarchExhausted::Miss));
+        assert!(!miss.hit);
+        assert_eq!(miss.steps, 12);
+
+        // distance / t at the closest approach is about 1e-3 / 5
+        let closest = MarchExhausted::ClosestApproach(1e-2);
+        let approach = sphere().sphere_trace(&ray, 0.0, 100.0, &settings.with_on_exhausted(closest));
+        assert!(approach.hit);
+        assert_eq!(approach.steps, 12);
+        assert!(ray.at(approach.t as Float).z() < -4.0, "{}", approach.t);
+
+        let strict = MarchExhausted::ClosestApproach(1e-5);
+        assert!(!sphere().sphere_trace(&ray, 0.0, 100.0, &settings.with_on_exhausted(strict)).hit);
+    }
+}
@@ -0,0 +1,361 @@
+use std::ops::{Add, Sub, Mul, Div};
+#[cfg(feature = "simd")]
+use wide::{CmpLt, CmpLe};
+
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Ray};
+use crate::math::utils::{Float};
+
+/// A vector of float lanes traced in lockstep.  With the `simd` feature the lanes are
+/// backed by the `wide` crate's vector types (SSE/AVX/NEON as available); without it
+/// they fall back to plain arrays and scalar loops.
+pub trait SimdFloat: Copy + Add<Output = Self> + Sub<Output = Self>
+    + Mul<Output = Self> + Div<Output = Self> {
+    type Mask: Copy;
+    const LANES: usize;
+
+    fn splat(x: Float) -> Self;
+    fn from_fn<F: Fn(usize) -> Float>(f: F) -> Self;
+    fn lane(&self, i: usize) -> Float;
+    fn sqrt(self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn lt(self, other: Self) -> Self::Mask;
+    fn le(self, other: Self) -> Self::Mask;
+    fn mask_and(a: Self::Mask, b: Self::Mask) -> Self::Mask;
+    /// Bit `i` is set where lane `i` of the mask is true.
+    fn mask_bits(mask: Self::Mask) -> u32;
+    fn select(mask: Self::Mask, if_true: Self, if_false: Self) -> Self;
+}
+
+macro_rules! simd_float {
+    ($name:ident, $scalar:ty, $lanes:expr, $wide:ident) => {
+        #[derive(Debug, Copy, Clone)]
+        pub struct $name {
+            #[cfg(feature = "simd")]
+            v: wide::$wide,
+            #[cfg(not(feature = "simd"))]
+            v: [$scalar; $lanes],
+        }
+
+        impl $name {
+            #[cfg(feature = "simd")]
+            fn from_array(a: [$scalar; $lanes]) -> $name {
+                $name { v: wide::$wide::new(a) }
+            }
+
+            #[cfg(not(feature = "simd"))]
+            fn from_array(a: [$scalar; $lanes]) -> $name {
+                $name { v: a }
+            }
+
+            #[cfg(feature = "simd")]
+            pub fn to_array(self) -> [$scalar; $lanes] {
+                self.v.to_array()
+            }
+
+            #[cfg(not(feature = "simd"))]
+            pub fn to_array(self) -> [$scalar; $lanes] {
+                self.v
+            }
+
+            #[cfg(not(feature = "simd"))]
+            fn zip<F: Fn($scalar, $scalar) -> $scalar>(self, other: $name, f: F) -> $name {
+                let mut out = self.v;
+                for (lane, value) in out.iter_mut().enumerate() {
+                    *value = f(self.v[lane], other.v[lane]);
+                }
+                $name { v: out }
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            #[cfg(feature = "simd")]
+            fn add(self, other: $name) -> $name { $name { v: self.v + other.v } }
+            #[cfg(not(feature = "simd"))]
+            fn add(self, other: $name) -> $name { self.zip(other, |a, b| a + b) }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            #[cfg(feature = "simd")]
+            fn sub(self, other: $name) -> $name { $name { v: self.v - other.v } }
+            #[cfg(not(feature = "simd"))]
+            fn sub(self, other: $name) -> $name { self.zip(other, |a, b| a - b) }
+        }
+
+        impl Mul for $name {
+            type Output = $name;
+
+            #[cfg(feature = "simd")]
+            fn mul(self, other: $name) -> $name { $name { v: self.v * other.v } }
+            #[cfg(not(feature = "simd"))]
+            fn mul(self, other: $name) -> $name { self.zip(other, |a, b| a * b) }
+        }
+
+        impl Div for $name {
+            type Output = $name;
+
+            #[cfg(feature = "simd")]
+            fn div(self, other: $name) -> $name { $name { v: self.v / other.v } }
+            #[cfg(not(feature = "simd"))]
+            fn div(self, other: $name) -> $name { self.zip(other, |a, b| a / b) }
+        }
+
+        impl SimdFloat for $name {
+            #[cfg(feature = "simd")]
+            type Mask = wide::$wide;
+            #[cfg(not(feature = "simd"))]
+            type Mask = [bool; $lanes];
+            const LANES: usize = $lanes;
+
+            fn splat(x: Float) -> $name {
+                $name::from_array([x as $scalar; $lanes])
+            }
+
+            fn from_fn<F: Fn(usize) -> Float>(f: F) -> $name {
+                let mut a = [0.0; $lanes];
+                for (lane, value) in a.iter_mut().enumerate() {
+                    *value = f(lane) as $scalar;
+                }
+                $name::from_array(a)
+            }
+
+            fn lane(&self, i: usize) -> Float {
+                self.to_array()[i] as Float
+            }
+
+            #[cfg(feature = "simd")]
+            fn sqrt(self) -> $name { $name { v: self.v.sqrt() } }
+            #[cfg(not(feature = "simd"))]
+            fn sqrt(self) -> $name { self.zip(self, |a, _| a.sqrt()) }
+
+            #[cfg(feature = "simd")]
+            fn min(self, other: $name) -> $name { $name { v: self.v.min(other.v) } }
+            #[cfg(not(feature = "simd"))]
+            fn min(self, other: $name) -> $name { self.zip(other, |a, b| a.min(b)) }
+
+            #[cfg(feature = "simd")]
+            fn max(self, other: $name) -> $name { $name { v: self.v.max(other.v) } }
+            #[cfg(not(feature = "simd"))]
+            fn max(self, other: $name) -> $name { self.zip(other, |a, b| a.max(b)) }
+
+            #[cfg(feature = "simd")]
+            fn lt(self, other: $name) -> Self::Mask { self.v.cmp_lt(other.v) }
+            #[cfg(not(feature = "simd"))]
+            fn lt(self, other: $name) -> Self::Mask {
+                let mut mask = [false; $lanes];
+                for (lane, value) in mask.iter_mut().enumerate() {
+                    *value = self.v[lane] < other.v[lane];
+                }
+                mask
+            }
+
+            #[cfg(feature = "simd")]
+            fn le(self, other: $name) -> Self::Mask { self.v.cmp_le(other.v) }
+            #[cfg(not(feature = "simd"))]
+            fn le(self, other: $name) -> Self::Mask {
+                let mut mask = [false; $lanes];
+                for (lane, value) in mask.iter_mut().enumerate() {
+                    *value = self.v[lane] <= other.v[lane];
+                }
+                mask
+            }
+
+            #[cfg(feature = "simd")]
+            fn mask_and(a: Self::Mask, b: Self::Mask) -> Self::Mask { a & b }
+            #[cfg(not(feature = "simd"))]
+            fn mask_and(a: Self::Mask, b: Self::Mask) -> Self::Mask {
+                let mut mask = a;
+                for (lane, value) in mask.iter_mut().enumerate() {
+                    *value = *value && b[lane];
+                }
+                mask
+            }
+
+            #[cfg(feature = "simd")]
+            fn mask_bits(mask: Self::Mask) -> u32 { mask.move_mask() as u32 }
+            #[cfg(not(feature = "simd"))]
+            fn mask_bits(mask: Self::Mask) -> u32 {
+                mask.iter().enumerate().fold(0, |bits, (lane, &set)| bits | ((set as u32) << lane))
+            }
+
+            #[cfg(feature = "simd")]
+            fn select(mask: Self::Mask, if_true: $name, if_false: $name) -> $name {
+                $name { v: mask.blend(if_true.v, if_false.v) }
+            }
+            #[cfg(not(feature = "simd"))]
+            fn select(mask: Self::Mask, if_true: $name, if_false: $name) -> $name {
+                let mut out = if_false.v;
+                for (lane, value) in out.iter_mut().enumerate() {
+                    if mask[lane] {
+                        *value = if_true.v[lane];
+                    }
+                }
+                $name { v: out }
+            }
+        }
+    };
+}
+
+simd_float!(F64x4, f64, 4, f64x4);
+simd_float!(F32x8, f32, 8, f32x8);
+
+/// Widest lane count of the types above, for fixed size per-lane scratch arrays.
+pub const MAX_LANES: usize = 8;
+
+/// Structure-of-arrays vectors, one `Vec3` per lane.
+#[derive(Debug, Copy, Clone)]
+pub struct Vec3xN<L: SimdFloat> {
+    pub x: L,
+    pub y: L,
+    pub z: L,
+}
+
+impl<L: SimdFloat> Vec3xN<L> {
+    pub fn splat(v: Vec3) -> Vec3xN<L> {
+        Vec3xN { x: L::splat(v.x()), y: L::splat(v.y()), z: L::splat(v.z()) }
+    }
+
+    pub fn from_fn<F: Fn(usize) -> Vec3>(f: F) -> Vec3xN<L> {
+        Vec3xN {
+            x: L::from_fn(|i| f(i).x()),
+            y: L::from_fn(|i| f(i).y()),
+            z: L::from_fn(|i| f(i).z()),
+        }
+    }
+
+    pub fn lane(&self, i: usize) -> Vec3 {
+        Vec3::new(self.x.lane(i), self.y.lane(i), self.z.lane(i))
+    }
+
+    pub fn dot(&self, other: &Vec3xN<L>) -> L {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+}
+
+impl<L: SimdFloat> Sub for Vec3xN<L> {
+    type Output = Vec3xN<L>;
+
+    fn sub(self, other: Vec3xN<L>) -> Vec3xN<L> {
+        Vec3xN { x: self.x - other.x, y: self.y - other.y, z: self.z - other.z }
+    }
+}
+
+/// `L::LANES` rays traced together; directions are unit length like `Ray`.
+#[derive(Debug, Copy, Clone)]
+pub struct RayPacket<L: SimdFloat> {
+    pub origin: Vec3xN<L>,
+    pub dir: Vec3xN<L>,
+    /// Componentwise reciprocal of `dir`, for slab tests against boxes.
+    pub inv_dir: Vec3xN<L>,
+}
+
+pub type RayPacket4 = RayPacket<F64x4>;
+pub type RayPacket8 = RayPacket<F32x8>;
+
+impl<L: SimdFloat> RayPacket<L> {
+    pub const LANES: usize = L::LANES;
+
+    /// Packs exactly `L::LANES` rays.
+    pub fn new(rays: &[Ray]) -> RayPacket<L> {
+        assert_eq!(rays.len(), L::LANES, "ray packet needs exactly one ray per lane");
+        RayPacket {
+            origin: Vec3xN::from_fn(|i| rays[i].origin),
+            dir: Vec3xN::from_fn(|i| rays[i].dir),
+            inv_dir: Vec3xN::from_fn(|i| Vec3::one() / rays[i].dir),
+        }
+    }
+
+    pub fn ray(&self, i: usize) -> Ray {
+        Ray::new(self.origin.lane(i), self.dir.lane(i))
+    }
+}
+
+/// Nearest sphere intersection in (t_min, t_max) per lane, `Float::INFINITY` for misses.
+pub fn hit_sphere_packet<L: SimdFloat>(packet: &RayPacket<L>, center: Vec3, radius: Float,
+                                       t_min: Float, t_max: L) -> L {
+    let oc = packet.origin - Vec3xN::splat(center);
+    let half_b = oc.dot(&packet.dir);
+    let c = oc.dot(&oc) - L::splat(radius * radius);
+    let discriminant = half_b * half_b - c;
+
+    let zero = L::splat(0.0);
+    let missed = L::splat(Float::INFINITY);
+    let hit_any = zero.lt(discriminant);
+    if L::mask_bits(hit_any) == 0 {
+        // coherent packets usually miss most spheres together
+        return missed;
+    }
+    let t_min = L::splat(t_min);
+    let root = discriminant.max(zero).sqrt();
+
+    let near = zero - half_b - root;
+    let far = zero - half_b + root;
+    let near_valid = L::mask_and(hit_any, L::mask_and(t_min.lt(near), near.lt(t_max)));
+    let far_valid = L::mask_and(hit_any, L::mask_and(t_min.lt(far), far.lt(t_max)));
+    L::select(near_valid, near, L::select(far_valid, far, missed))
+}
+
+/// Slab test of every lane against the axis aligned box from `min` to `max`: set where
+/// the lane's ray passes through the box between `t_min` and that lane's `t_max`.
+pub fn hit_aabb_packet<L: SimdFloat>(packet: &RayPacket<L>, min: Vec3, max: Vec3, t_min: Float, t_max: L) -> L::Mask {
+    let zero = L::splat(0.0);
+    let mut t_near = L::splat(t_min);
+    let mut t_far = t_max;
+    let axes = [(packet.origin.x, packet.inv_dir.x), (packet.origin.y, packet.inv_dir.y),
+                (packet.origin.z, packet.inv_dir.z)];
+    for (axis, (origin, inv_dir)) in axes.iter().enumerate() {
+        let t0 = (L::splat(min[axis]) - *origin) * *inv_dir;
+        let t1 = (L::splat(max[axis]) - *origin) * *inv_dir;
+        let backwards = inv_dir.lt(zero);
+        let (enter, exit) = (L::select(backwards, t1, t0), L::select(backwards, t0, t1));
+        // written as selects rather than min/max so NaNs from rays lying in a slab's
+        // plane are ignored, like the scalar test
+        t_near = L::select(t_near.lt(enter), enter, t_near);
+        t_far = L::select(exit.lt(t_far), exit, t_far);
+    }
+    t_near.le(t_far)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_box_hits<L: SimdFloat>() {
+        let (min, max) = (Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        // lanes alternate hits and misses: straight through, beside the box, starting
+        // inside, moving away, along an edge plane, diagonally, and stopping short
+        let cases = [
+            (Ray::new(Vec3::new(0.5, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), 100.0, true),
+            (Ray::new(Vec3::new(1.5, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), 100.0, false),
+            (Ray::new(Vec3::new(0.2, 0.3, 0.1), Vec3::new(1.0, -2.0, 0.5)), 100.0, true),
+            (Ray::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), 100.0, false),
+            (Ray::new(Vec3::new(-5.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 100.0, true),
+            (Ray::new(Vec3::new(-5.0, -5.0, 3.0), Vec3::new(1.0, 1.0, 0.0)), 100.0, false),
+            (Ray::new(Vec3::new(-4.0, -4.0, -4.0), Vec3::new(1.0, 1.0, 1.0)), 100.0, true),
+            (Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), 3.0, false),
+        ];
+        for lanes in cases.chunks(L::LANES) {
+            let rays: Vec<Ray> = lanes.iter().map(|(ray, _, _)| *ray).collect();
+            let t_max = L::from_fn(|lane| lanes[lane].1);
+            let bits = L::mask_bits(hit_aabb_packet(&RayPacket::<L>::new(&rays), min, max, 0.001, t_max));
+            for (lane, (ray, _, expected)) in lanes.iter().enumerate() {
+                assert_eq!(bits & (1 << lane) != 0, *expected, "lane {}: {:?}", lane, ray);
+            }
+        }
+    }
+
+    #[test]
+    fn hit_aabb_packet_four_lanes() {
+        assert_box_hits::<F64x4>();
+    }
+
+    #[test]
+    fn hit_aabb_packet_eight_lanes() {
+        assert_box_hits::<F32x8>();
+    }
+}
@@ -0,0 +1,382 @@
+use std::ops::{Add, Mul};
+use std::sync::OnceLock;
+
+use crate::math::math3::{Vec3};
+use crate::math::utils::{Float};
+
+pub const LAMBDA_MIN: Float = 360.0;
+pub const LAMBDA_MAX: Float = 830.0;
+/// Wavelengths traced together per camera sample.
+pub const NUM_WAVELENGTHS: usize = 4;
+
+/// Hero wavelength sampling (Wilkie et al. 2014): one wavelength is importance sampled
+/// over the visible range and the others are spread evenly from it, so every path
+/// carries several wavelengths until something dispersive forces it to pick one.
+#[derive(Debug, Copy, Clone)]
+pub struct SampledWavelengths {
+    lambda: [Float; NUM_WAVELENGTHS],
+    pdf: [Float; NUM_WAVELENGTHS],
+}
+
+impl SampledWavelengths {
+    pub fn sample_visible(u: Float) -> SampledWavelengths {
+        let mut lambda = [0.0; NUM_WAVELENGTHS];
+        let mut pdf = [0.0; NUM_WAVELENGTHS];
+        for i in 0..NUM_WAVELENGTHS {
+            let u_i = (u + i as Float / NUM_WAVELENGTHS as Float).fract();
+            lambda[i] = sample_visible_wavelength(u_i);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+        SampledWavelengths { lambda, pdf }
+    }
+
+    /// The wavelength that keeps going after `terminate_secondary`, in nanometres.
+    pub fn hero(&self) -> Float {
+        self.lambda[0]
+    }
+
+    pub fn lambda(&self, i: usize) -> Float {
+        self.lambda[i]
+    }
+
+    /// Drops all but the hero wavelength, for events like refraction through a
+    /// dispersive medium that send each wavelength in a different direction.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        // the hero is now the only sample of the estimate instead of one of N
+        self.pdf[0] /= NUM_WAVELENGTHS as Float;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf.iter().skip(1).all(|pdf| *pdf == 0.0)
+    }
+}
+
+/// Pdf roughly following the luminous efficiency curve (as in pbrt-v4), which puts
+/// fewer samples where the eye can't see and so reduces colour noise.
+fn sample_visible_wavelength(u: Float) -> Float {
+    538.0 - 138.88889 * (0.85691062 - 1.827502 * u).atanh()
+}
+
+fn visible_wavelength_pdf(lambda: Float) -> Float {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.003939804 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+/// Spectral quantity at the wavelengths of a `SampledWavelengths`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampledSpectrum(pub [Float; NUM_WAVELENGTHS]);
+
+impl SampledSpectrum {
+    pub fn splat(value: Float) -> SampledSpectrum {
+        SampledSpectrum([value; NUM_WAVELENGTHS])
+    }
+
+    pub fn zero() -> SampledSpectrum {
+        SampledSpectrum::splat(0.0)
+    }
+
+    /// Smooth reflectance spectrum with roughly the given linear RGB colour (Smits
+    /// 1999).  Emitted RGB colours go through the same mapping; the white balance in
+    /// `to_rgb` takes a constant spectrum back to white.
+    pub fn from_rgb(rgb: Vec3, wavelengths: &SampledWavelengths) -> SampledSpectrum {
+        let mut values = [0.0; NUM_WAVELENGTHS];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = smits_rgb_to_spectrum(rgb, wavelengths.lambda[i]);
+        }
+        SampledSpectrum(values)
+    }
+
+    /// Monte Carlo estimate of the CIE XYZ colour of the spectrum.
+    pub fn to_xyz(&self, wavelengths: &SampledWavelengths) -> Vec3 {
+        let mut xyz = Vec3::zero();
+        for i in 0..NUM_WAVELENGTHS {
+            if wavelengths.pdf[i] > 0.0 {
+                xyz += self.0[i] / wavelengths.pdf[i] * cie_xyz(wavelengths.lambda[i]);
+            }
+        }
+        xyz / NUM_WAVELENGTHS as Float
+    }
+
+    /// Linear sRGB, white balanced so that a constant spectrum of 1 comes out as (1, 1, 1).
+    pub fn to_rgb(&self, wavelengths: &SampledWavelengths) -> Vec3 {
+        xyz_to_linear_srgb(self.to_xyz(wavelengths)) / *white_rgb()
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(self, other: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.0;
+        for (i, value) in values.iter_mut().enumerate() {
+            *value += other.0[i];
+        }
+        SampledSpectrum(values)
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, other: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.0;
+        for (i, value) in values.iter_mut().enumerate() {
+            *value *= other.0[i];
+        }
+        SampledSpectrum(values)
+    }
+}
+
+impl Mul<Float> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, scale: Float) -> SampledSpectrum {
+        SampledSpectrum(self.0.map(|value| value * scale))
+    }
+}
+
+/// Index of refraction as a function of wavelength.
+#[derive(Debug, Copy, Clone)]
+pub enum Ior {
+    Constant(Float),
+    /// Cauchy's equation `A + B / λ²` with λ in micrometres.
+    Cauchy(Float, Float),
+    /// Sellmeier coefficients `B` and `C` (µm²): `n² = 1 + Σ B λ² / (λ² - C)`.
+    Sellmeier([Float; 3], [Float; 3]),
+}
+
+/// Wavelength that RGB rendering evaluates dispersive indices at: the sodium D line,
+/// where catalogue refractive indices are quoted.
+pub const RGB_WAVELENGTH: Float = 589.3;
+
+impl Ior {
+    /// Schott N-BK7 crown glass, the common optical glass.
+    pub fn bk7() -> Ior {
+        Ior::Sellmeier([1.0396122, 0.23179235, 1.0104694], [0.0060006985, 0.020017914, 103.56065])
+    }
+
+    /// Schott SF11 dense flint glass, strongly dispersive.
+    pub fn sf11() -> Ior {
+        Ior::Sellmeier([1.737597, 0.31374735, 1.8987811], [0.013188707, 0.062306814, 155.2363])
+    }
+
+    pub fn at(&self, lambda_nm: Float) -> Float {
+        let lambda_um = lambda_nm / 1000.0;
+        let lambda2 = lambda_um * lambda_um;
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy(a, b) => a + b / lambda2,
+            Ior::Sellmeier(b, c) => {
+                (1.0 + (0..3).map(|i| b[i] * lambda2 / (lambda2 - c[i])).sum::<Float>()).sqrt()
+            },
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+/// Complex index of refraction `eta + i k` of a conductor, per RGB channel.
+#[derive(Debug, Copy, Clone)]
+pub struct ComplexIor {
+    pub eta: Vec3,
+    pub k: Vec3,
+}
+
+/// Measured metals, sampled at roughly 650, 550 and 450 nm.
+impl ComplexIor {
+    pub fn new(eta: Vec3, k: Vec3) -> ComplexIor {
+        ComplexIor { eta, k }
+    }
+
+    pub fn gold() -> ComplexIor {
+        ComplexIor::new(Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603))
+    }
+
+    pub fn silver() -> ComplexIor {
+        ComplexIor::new(Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147))
+    }
+
+    pub fn copper() -> ComplexIor {
+        ComplexIor::new(Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142))
+    }
+
+    pub fn aluminium() -> ComplexIor {
+        ComplexIor::new(Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837))
+    }
+
+    pub fn iron() -> ComplexIor {
+        ComplexIor::new(Vec3::new(2.912, 2.950, 2.585), Vec3::new(3.089, 2.932, 2.767))
+    }
+
+    pub fn chromium() -> ComplexIor {
+        ComplexIor::new(Vec3::new(3.106, 3.181, 2.322), Vec3::new(3.331, 3.332, 3.135))
+    }
+
+    pub fn titanium() -> ComplexIor {
+        ComplexIor::new(Vec3::new(2.741, 2.541, 2.267), Vec3::new(3.814, 3.435, 3.039))
+    }
+
+    /// Unpolarised Fresnel reflectance from air at an angle with cosine `cos_theta`.
+    pub fn reflectance(&self, cos_theta: Float) -> Vec3 {
+        Vec3::new(fresnel_conductor(cos_theta, self.eta.x(), self.k.x()),
+                  fresnel_conductor(cos_theta, self.eta.y(), self.k.y()),
+                  fresnel_conductor(cos_theta, self.eta.z(), self.k.z()))
+    }
+
+    /// Reflectance at normal incidence, the colour the metal is usually described by.
+    pub fn normal_reflectance(&self) -> Vec3 {
+        self.reflectance(1.0)
+    }
+}
+
+/// Exact Fresnel equations for a conductor, averaged over both polarisations.
+fn fresnel_conductor(cos_theta: Float, eta: Float, k: Float) -> Float {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+    0.5 * (r_s + r_p)
+}
+
+/// Analytic fit of the CIE 1931 colour matching functions (Wyman et al. 2013).
+pub fn cie_xyz(lambda: Float) -> Vec3 {
+    let lobe = |mu: Float, sigma_below: Float, sigma_above: Float| {
+        let t = (lambda - mu) / if lambda < mu { sigma_below } else { sigma_above };
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7) - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Vec3 {
+    Vec3::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.969266 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z(),
+    )
+}
+
+/// Linear sRGB of the constant spectrum 1, the white point `to_rgb` balances to.
+fn white_rgb() -> &'static Vec3 {
+    static WHITE: OnceLock<Vec3> = OnceLock::new();
+    WHITE.get_or_init(|| {
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        let xyz: Vec3 = (0..=steps).map(|i| cie_xyz(LAMBDA_MIN + i as Float)).sum();
+        xyz_to_linear_srgb(xyz)
+    })
+}
+
+const SMITS_WHITE: [Float; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [Float; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [Float; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [Float; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [Float; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [Float; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [Float; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// Linear interpolation of one of Smits' ten bin spectra, centred from 380 to 720 nm.
+fn smits_basis(basis: &[Float; 10], lambda: Float) -> Float {
+    let x = ((lambda - 380.0) / (720.0 - 380.0) * 9.0).clamp(0.0, 9.0);
+    let bin = (x.floor() as usize).min(8);
+    let t = x - bin as Float;
+    basis[bin] * (1.0 - t) + basis[bin + 1] * t
+}
+
+fn smits_rgb_to_spectrum(rgb: Vec3, lambda: Float) -> Float {
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+    let basis = |spectrum: &[Float; 10]| smits_basis(spectrum, lambda);
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE) + if g <= b {
+            (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+        } else {
+            (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+        }
+    }
+    else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE) + if r <= b {
+            (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+        } else {
+            (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+        }
+    }
+    else {
+        b * basis(&SMITS_WHITE) + if r <= g {
+            (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+        } else {
+            (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_round_trips_through_spectra() {
+        let samples = 4096;
+        let mean: Vec3 = (0..samples).map(|i| {
+            let wavelengths = SampledWavelengths::sample_visible((i as Float + 0.5) / samples as Float);
+            SampledSpectrum::from_rgb(Vec3::one(), &wavelengths).to_rgb(&wavelengths)
+        }).sum::<Vec3>() / samples as Float;
+        assert!(mean.approx_eq(Vec3::one(), 0.01), "{:?}", mean);
+    }
+
+    #[test]
+    fn bk7_index_at_the_helium_d_line() {
+        // catalogue n_d of N-BK7 at 587.6 nm
+        assert!((Ior::bk7().at(587.56) - 1.5168).abs() < 1e-4, "{}", Ior::bk7().at(587.56));
+        // normal dispersion: blue bends more than red
+        assert!(Ior::bk7().at(450.0) > Ior::bk7().at(650.0));
+    }
+
+    #[test]
+    fn metals_reflect_their_measured_colours_at_normal_incidence() {
+        // linear RGB normal incidence reflectance from Hoffman's "Physics and Math of
+        // Shading" tables
+        for (ior, measured) in [(ComplexIor::gold(), Vec3::new(1.0, 0.766, 0.336)),
+                                (ComplexIor::copper(), Vec3::new(0.955, 0.638, 0.538))] {
+            let reflectance = ior.normal_reflectance();
+            assert!(reflectance.approx_eq(measured, 0.04), "{:?} vs {:?}", reflectance, measured);
+            // which at normal incidence is ((n - 1)² + k²) / ((n + 1)² + k²)
+            let closed_form = |eta: Float, k: Float| ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+            let expected = Vec3::new(closed_form(ior.eta.x(), ior.k.x()), closed_form(ior.eta.y(), ior.k.y()),
+                                     closed_form(ior.eta.z(), ior.k.z()));
+            assert!(reflectance.approx_eq(expected, 1e-5), "{:?} vs {:?}", reflectance, expected);
+        }
+    }
+
+    #[test]
+    fn metals_reflect_everything_at_grazing_angles() {
+        for ior in [ComplexIor::gold(), ComplexIor::copper(), ComplexIor::aluminium(), ComplexIor::iron()] {
+            assert!(ior.reflectance(0.0).approx_eq(Vec3::one(), 1e-6), "{:?}", ior.reflectance(0.0));
+            // and gets there steadily over the last few degrees
+            let approach: Vec<Float> = [0.1, 0.03, 0.01, 0.001].iter().map(|&cos| ior.reflectance(cos).min_element()).collect();
+            assert!(approach.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", approach);
+            assert!(approach[3] > 0.99, "{:?}", approach);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::math::math3::{Vec3};
+use crate::math::media::{fractal_noise};
+use crate::math::utils::{Float};
+
+/// Scalar pattern over space, used for blend weights and masks.  Evaluated at world
+/// space hit points.
+#[derive(Debug, Copy, Clone)]
+pub enum Texture {
+    Constant(Float),
+    /// 3D checkerboard of 0 and 1 with cells of the given size.
+    Checker(Float),
+    /// Fractal value noise in [0, 1] with the given base frequency and octaves.
+    Noise(Float, usize),
+}
+
+impl Texture {
+    pub fn value(&self, point: Vec3) -> Float {
+        match self {
+            Texture::Constant(value) => *value,
+            Texture::Checker(size) => {
+                let cells = (point.x() / *size).floor() + (point.y() / *size).floor() + (point.z() / *size).floor();
+                if (cells as i64).rem_euclid(2) == 0 { 0.0 } else { 1.0 }
+            },
+            Texture::Noise(frequency, octaves) => fractal_noise(*frequency * point, *octaves),
+        }
+    }
+}
+
+/// Tangent space normal map: RGB texels map [0, 1] to [-1, 1] with blue along the
+/// surface normal and red along the tangent, looked up by the hit's UVs.
+pub struct NormalMap {
+    width: u32,
+    height: u32,
+    texels: Vec<Vec3>,
+}
+
+impl NormalMap {
+    pub fn load(path: &str) -> image::ImageResult<NormalMap> {
+        let image = image::open(path)?.into_rgb8();
+        let texels = image.pixels()
+            .map(|pixel| Vec3::new(pixel[0] as Float, pixel[1] as Float, pixel[2] as Float) / 127.5 - Vec3::one())
+            .collect();
+        Ok(NormalMap { width: image.width(), height: image.height(), texels })
+    }
+
+    /// Procedural normal map from a function of `u`, `v` in [0, 1) to tangent space normals.
+    /// Rows are stored top down like a loaded image, so row 0 is at `v` near 1.
+    pub fn from_fn<F: Fn(Float, Float) -> Vec3>(width: u32, height: u32, normal: F) -> NormalMap {
+        let texels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                normal((x as Float + 0.5) / width as Float, 1.0 - (y as Float + 0.5) / height as Float).unit_vector()
+            })
+            .collect();
+        NormalMap { width, height, texels }
+    }
+
+    /// Bilinearly filtered normal at `uv`, wrapping around at the edges.  `v` grows
+    /// upwards, images are stored from the top row down.
+    pub fn sample(&self, uv: (Float, Float)) -> Vec3 {
+        let x = uv.0 * self.width as Float - 0.5;
+        let y = (1.0 - uv.1) * self.height as Float - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |dx: i64, dy: i64| {
+            let tx = (x0 as i64 + dx).rem_euclid(self.width as i64) as usize;
+            let ty = (y0 as i64 + dy).rem_euclid(self.height as i64) as usize;
+            self.texels[ty * self.width as usize + tx]
+        };
+        let top = (1.0 - fx) * texel(0, 0) + fx * texel(1, 0);
+        let bottom = (1.0 - fx) * texel(0, 1) + fx * texel(1, 1);
+        ((1.0 - fy) * top + fy * bottom).unit_vector()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_fn_samples_back_the_function() {
+        // tilted along both u and v, so mirroring either shows up
+        let normal = |u: Float, v: Float| Vec3::new(0.4 * (u - 0.5), 0.6 * (v - 0.5), 1.0).unit_vector();
+        let map = NormalMap::from_fn(64, 32, normal);
+        for uv in [(0.3, 0.8), (0.7, 0.2), (0.5, 0.5), (0.12, 0.37)].iter() {
+            assert!(map.sample(*uv).approx_eq(normal(uv.0, uv.1), 1e-3), "{:?}", uv);
+        }
+    }
+}
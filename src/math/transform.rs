@@ -0,0 +1,121 @@
+use crate::math::math3::{Vec3, Mat3, Mat4, Quat, Point3};
+use crate::math::utils::{Float};
+
+/// Affine transform stored together with its inverse so rays can be taken into
+/// object space without re-inverting the matrix for every intersection.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Transform {
+    /// `None` if the matrix is singular.
+    pub fn from_matrix(matrix: Mat4) -> Option<Transform> {
+        matrix.inverse().map(|inverse| Transform { matrix, inverse })
+    }
+
+    pub fn identity() -> Transform {
+        Transform { matrix: Mat4::identity(), inverse: Mat4::identity() }
+    }
+
+    pub fn translation(offset: Vec3) -> Transform {
+        Transform { matrix: Mat4::translation(offset), inverse: Mat4::translation(-offset) }
+    }
+
+    pub fn scale(factors: Vec3) -> Transform {
+        let inverse_factors = Vec3::new(1.0 / factors.x(), 1.0 / factors.y(), 1.0 / factors.z());
+        Transform { matrix: Mat4::scale(factors), inverse: Mat4::scale(inverse_factors) }
+    }
+
+    pub fn uniform_scale(factor: Float) -> Transform {
+        Transform::scale(factor * Vec3::one())
+    }
+
+    /// Right handed rotation by `angle` radians about `axis`.
+    pub fn rotation(axis: Vec3, angle: Float) -> Transform {
+        Transform::from_quat(Quat::from_axis_angle(axis, angle))
+    }
+
+    pub fn from_quat(rotation: Quat) -> Transform {
+        let linear = rotation.normalize().to_mat3();
+        Transform {
+            matrix: Mat4::from_affine(linear, Vec3::zero()),
+            inverse: Mat4::from_affine(linear.transpose(), Vec3::zero()),
+        }
+    }
+
+    /// Scale, then rotate, then translate.
+    pub fn trs(translation: Vec3, axis: Vec3, angle: Float, scale: Vec3) -> Transform {
+        Transform::scale(scale)
+            .then(&Transform::rotation(axis, angle))
+            .then(&Transform::translation(translation))
+    }
+
+    /// The transform that applies `self` first and `next` afterwards.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.matrix.transform_point(Point3(point)).0
+    }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        self.matrix.transform_vector(vector)
+    }
+
+    /// Normals transform by the inverse transpose so they stay perpendicular to
+    /// surfaces under non-uniform scaling.
+    pub fn transform_normal(&self, normal: Vec3) -> Vec3 {
+        self.inverse.linear().transpose() * normal
+    }
+
+    pub fn inverse_transform_point(&self, point: Vec3) -> Vec3 {
+        self.inverse.transform_point(Point3(point)).0
+    }
+
+    pub fn inverse_transform_vector(&self, vector: Vec3) -> Vec3 {
+        self.inverse.transform_vector(vector)
+    }
+
+    /// Upper bound on how much the inverse can stretch a vector (Frobenius norm),
+    /// used to keep object space distance estimates conservative in world space.
+    pub fn inverse_stretch_bound(&self) -> Float {
+        let linear: Mat3 = self.inverse.linear();
+        (0..3).map(|i| linear.row(i).length_squared()).sum::<Float>().sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::math3::{dot};
+
+    const EPSILON: Float = 1e-4;
+
+    #[test]
+    fn transform_normal_stays_perpendicular_under_nonuniform_scale() {
+        let m = Mat4::from_affine(Mat3::rotation(Vec3::new(1.0, 1.0, 0.0), 0.7) * Mat3::diagonal(Vec3::new(3.0, 1.0, 0.25)),
+                                  Vec3::new(1.0, -2.0, 0.5));
+        let transform = Transform::from_matrix(m).unwrap();
+        let normal = Vec3::new(1.0, 1.0, 1.0).unit_vector();
+        let tangents = [Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, -1.0)];
+        let transformed = transform.transform_normal(normal);
+        for tangent in tangents.iter() {
+            let transformed_tangent = transform.transform_vector(*tangent);
+            assert!(dot(transformed, transformed_tangent).abs() <= EPSILON);
+        }
+    }
+}
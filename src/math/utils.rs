@@ -1,6 +1,69 @@
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{Neg, Add, Sub, Mul, Div, AddAssign, SubAssign, MulAssign, DivAssign};
 
-pub const TAU: f64 = 6.283185307179586476925286766559005768394338798750211641949;
+/// Precision of the renderer's core math.  The `f32` feature trades precision for
+/// speed and memory; the fractal raymarcher always works in `f64` internally.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
 
-pub fn degrees_to_radians(degrees: f64) -> f64 {
+pub const TAU: Float = std::f64::consts::TAU as Float;
+
+pub fn degrees_to_radians(degrees: Float) -> Float {
     degrees * (TAU / 360.0)
 }
+
+/// The float operations the math types need, implemented for `f32` and `f64`.
+pub trait Real: Copy + Debug + Display + Default + PartialOrd + Sum
+    + Neg<Output = Self> + Add<Output = Self> + Sub<Output = Self>
+    + Mul<Output = Self> + Div<Output = Self>
+    + AddAssign + SubAssign + MulAssign + DivAssign {
+    const ZERO: Self;
+    const ONE: Self;
+    const TAU: Self;
+    const INFINITY: Self;
+    const MIN_POSITIVE: Self;
+
+    fn from_f64(x: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn powi(self, n: i32) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn acos(self) -> Self;
+    fn atan2(self, other: Self) -> Self;
+    fn copysign(self, sign: Self) -> Self;
+}
+
+macro_rules! real_impl {
+    ($t:ident) => {
+        impl Real for $t {
+            const ZERO: $t = 0.0;
+            const ONE: $t = 1.0;
+            const TAU: $t = std::$t::consts::PI * 2.0;
+            const INFINITY: $t = $t::INFINITY;
+            const MIN_POSITIVE: $t = $t::MIN_POSITIVE;
+
+            fn from_f64(x: f64) -> $t { x as $t }
+            fn to_f64(self) -> f64 { self as f64 }
+            fn sqrt(self) -> $t { $t::sqrt(self) }
+            fn abs(self) -> $t { $t::abs(self) }
+            fn min(self, other: $t) -> $t { $t::min(self, other) }
+            fn max(self, other: $t) -> $t { $t::max(self, other) }
+            fn powi(self, n: i32) -> $t { $t::powi(self, n) }
+            fn sin(self) -> $t { $t::sin(self) }
+            fn cos(self) -> $t { $t::cos(self) }
+            fn acos(self) -> $t { $t::acos(self) }
+            fn atan2(self, other: $t) -> $t { $t::atan2(self, other) }
+            fn copysign(self, sign: $t) -> $t { $t::copysign(self, sign) }
+        }
+    };
+}
+
+real_impl!(f32);
+real_impl!(f64);
@@ -0,0 +1,145 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use rayon::prelude::*;
+
+use crate::math::math3::{Vec3};
+use crate::math::signed_distance::{SignedDistanceField};
+
+type DVec3 = Vec3<f64>;
+
+/// Signed distances sampled on a regular grid and trilinearly interpolated between
+/// samples, for shapes that only exist as data (scans, simulations, other tools'
+/// output).  Wrap it in `SignedDistanceField::VoxelGrid` to raymarch it.
+pub struct VoxelGrid {
+    /// Position of sample (0, 0, 0).
+    pub origin: DVec3,
+    /// Spacing between neighbouring samples.
+    pub voxel_size: f64,
+    /// Number of samples along x, y and z.
+    pub dims: [usize; 3],
+    /// Samples with x varying fastest, then y, then z.
+    values: Vec<f32>,
+}
+
+impl VoxelGrid {
+    /// Grid sampling `distance` at every grid point; `dims` must be at least 2 each.
+    pub fn from_fn<F: Fn(DVec3) -> f64 + Sync>(origin: DVec3, voxel_size: f64, dims: [usize; 3],
+                                               distance: F) -> VoxelGrid {
+        assert!(dims.iter().all(|&n| n >= 2), "grids need two samples along every axis, got {:?}", dims);
+        let values = (0..dims[0] * dims[1] * dims[2]).into_par_iter().map(|index| {
+            let (x, y, z) = (index % dims[0], index / dims[0] % dims[1], index / (dims[0] * dims[1]));
+            distance(origin + voxel_size * Vec3::new(x as f64, y as f64, z as f64)) as f32
+        }).collect();
+        VoxelGrid { origin, voxel_size, dims, values }
+    }
+
+    /// Bakes `field` into a grid with `resolution` voxels along the longest side of its
+    /// bounds, plus a margin of two voxels all around.
+    pub fn from_sdf(field: &SignedDistanceField, resolution: usize) -> VoxelGrid {
+        let (min, max) = field.bounds();
+        let voxel_size = (max - min).max_element() / resolution.max(1) as f64;
+        let origin = min - DVec3::splat(2.0 * voxel_size);
+        let extent = max - min;
+        let dims = [0, 1, 2].map(|axis| (extent[axis] / voxel_size).ceil() as usize + 5);
+        VoxelGrid::from_fn(origin, voxel_size, dims, |p| field.distance_estimate(p))
+    }
+
+    /// Reads a headerless file of little endian `f32` distances, x varying fastest,
+    /// as written by `save_raw` and most volume tools' raw export.
+    pub fn load_raw(path: &str, origin: DVec3, voxel_size: f64, dims: [usize; 3]) -> io::Result<VoxelGrid> {
+        if dims.iter().any(|&n| n < 2) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "grids need two samples along every axis"));
+        }
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+        let count = dims[0] * dims[1] * dims[2];
+        if bytes.len() != 4 * count {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("expected {} samples, found {} bytes", count, bytes.len())));
+        }
+        let values = bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        Ok(VoxelGrid { origin, voxel_size, dims, values })
+    }
+
+    pub fn save_raw(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for value in &self.values {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// Corners of the box covered by samples.
+    pub fn bounds(&self) -> (DVec3, DVec3) {
+        let last = Vec3::new(self.dims[0] as f64 - 1.0, self.dims[1] as f64 - 1.0, self.dims[2] as f64 - 1.0);
+        (self.origin, self.origin + self.voxel_size * last)
+    }
+
+    fn value(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.values[x + self.dims[0] * (y + self.dims[1] * z)] as f64
+    }
+
+    /// Trilinearly interpolated distance.  Outside the grid it's the distance to the
+    /// grid box, or the distance at the nearest point of the box less the way there if
+    /// that's larger, which keeps it a lower bound.
+    pub fn distance(&self, point: DVec3) -> f64 {
+        let (min, max) = self.bounds();
+        let clamped = point.max(min).min(max);
+        let outside = (point - clamped).length();
+
+        let grid = (clamped - self.origin) / self.voxel_size;
+        let cell = |axis: usize| (grid[axis].floor() as usize).min(self.dims[axis].saturating_sub(2));
+        let (x, y, z) = (cell(0), cell(1), cell(2));
+        let (fx, fy, fz) = (grid.x() - x as f64, grid.y() - y as f64, grid.z() - z as f64);
+        let lerp = |a: f64, b: f64, t: f64| a + t * (b - a);
+        let along_x = |y: usize, z: usize| lerp(self.value(x, y, z), self.value(x + 1, y, z), fx);
+        let inside = lerp(lerp(along_x(y, z), along_x(y + 1, z), fy),
+                          lerp(along_x(y, z + 1), along_x(y + 1, z + 1), fy), fz);
+        if outside > 0.0 { outside.max(inside - outside) } else { inside }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linear(p: DVec3) -> f64 {
+        0.25 * p.x() - 0.5 * p.y() + 0.125 * p.z() + 0.3
+    }
+
+    #[test]
+    fn trilinear_interpolation_reproduces_a_linear_field() {
+        let grid = VoxelGrid::from_fn(Vec3::new(-1.0, -1.0, -1.0), 0.25, [9, 9, 9], linear);
+        for i in 0..200 {
+            let t = i as f64 / 200.0;
+            let point = Vec3::new(-1.0 + 2.0 * t, 1.0 - 1.9 * t, (7.0 * t).sin());
+            // only as exact as the f32 samples
+            assert!((grid.distance(point) - linear(point)).abs() < 1e-6, "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn raw_files_round_trip_and_must_match_the_dimensions() {
+        let path = std::env::temp_dir().join(format!("voxel_grid_test_{}.raw", std::process::id()));
+        let path = path.to_str().unwrap();
+        let grid = VoxelGrid::from_fn(Vec3::zero(), 0.5, [3, 4, 5], linear);
+        grid.save_raw(path).unwrap();
+
+        let loaded = VoxelGrid::load_raw(path, Vec3::zero(), 0.5, [3, 4, 5]).unwrap();
+        assert_eq!(loaded.values, grid.values);
+        let mismatched = VoxelGrid::load_raw(path, Vec3::zero(), 0.5, [3, 4, 6]);
+        assert_eq!(mismatched.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        std::fs::remove_file(path).unwrap();
+
+        // bad dimensions are turned down before the file is even opened
+        let flat = VoxelGrid::load_raw(path, Vec3::zero(), 0.5, [3, 1, 5]);
+        assert_eq!(flat.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    #[should_panic]
+    fn grids_need_two_samples_per_axis() {
+        VoxelGrid::from_fn(Vec3::zero(), 0.5, [4, 1, 4], linear);
+    }
+}
@@ -2,3 +2,15 @@ pub mod spherion;
 pub mod three_spheres;
 pub mod first_fractal;
 pub mod spherion_meets_fractalius;
+pub mod dispersion;
+pub mod foggy_fractal;
+pub mod smoky_fractal;
+pub mod subsurface;
+pub mod layered;
+pub mod diffuse_variants;
+pub mod brushed_metal;
+pub mod bumpy;
+pub mod metals;
+pub mod primitives;
+pub mod voxel_fractal;
+pub mod implicit_surfaces;
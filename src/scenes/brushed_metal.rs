@@ -0,0 +1,26 @@
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::materials::{Material};
+use crate::math::texture::{Texture};
+use crate::math::utils::{TAU};
+
+
+pub fn brushed_metal_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                                 Material::Mix(Box::new(Material::Lambertian(Vec3::new(0.8, 0.8, 0.8))),
+                                               Box::new(Material::Lambertian(Vec3::new(0.1, 0.1, 0.12))),
+                                               Texture::Checker(0.25))),
+            // brushed around the y axis
+            Hittable::Sphere(Vec3::new(0.0, 0.0, -1.0), 0.5,
+                                 Material::AnisotropicMetal(Vec3::new(0.95, 0.93, 0.88), 0.02, 0.35, 0.0)),
+            // brushed from pole to pole
+            Hittable::Sphere(Vec3::new(1.0, 0.0, -1.0), 0.5,
+                                 Material::AnisotropicMetal(Vec3::new(0.95, 0.64, 0.54), 0.02, 0.35, TAU / 4.0)),
+            // isotropic for comparison
+            Hittable::Sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5,
+                                 Material::AnisotropicMetal(Vec3::new(1.0, 0.78, 0.34), 0.1, 0.1, 0.0)),
+        ]
+    )
+}
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::materials::{Material};
+use crate::math::spectrum::{Ior};
+use crate::math::texture::{Texture, NormalMap};
+
+
+/// Tangent space normals of a grid of round studs, 16 around and 8 from pole to pole.
+fn studs_normal_map() -> NormalMap {
+    NormalMap::from_fn(256, 128, |u, v| {
+        let (du, dv) = ((u * 16.0).fract() - 0.5, (v * 8.0).fract() - 0.5);
+        let r2 = du * du + dv * dv;
+        if r2 < 0.35 * 0.35 { Vec3::new(du, dv, (0.35 * 0.35 - r2).sqrt()) } else { Vec3::z_axis() }
+    })
+}
+
+pub fn bumpy_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                                 Material::Bump(Box::new(Material::Lambertian(Vec3::new(0.6, 0.55, 0.45))),
+                                                Texture::Noise(3.0, 5), 0.3)),
+            // hammered metal
+            Hittable::Sphere(Vec3::new(0.0, 0.0, -1.0), 0.5,
+                                 Material::Bump(Box::new(Material::Metal(Vec3::new(0.9, 0.6, 0.4), 0.02)),
+                                                Texture::Noise(12.0, 3), 0.04)),
+            // studded plastic
+            Hittable::Sphere(Vec3::new(1.0, 0.0, -1.0), 0.5,
+                                 Material::NormalMapped(Box::new(Material::Coated(
+                                     Box::new(Material::Lambertian(Vec3::new(0.1, 0.3, 0.7))), 1.5, 0.0)),
+                                                        Arc::new(studs_normal_map()), 1.0)),
+            // frosted glass
+            Hittable::Sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5,
+                                 Material::Bump(Box::new(Material::Dielectric(Ior::Constant(1.5))),
+                                                Texture::Noise(20.0, 3), 0.02)),
+        ]
+    )
+}
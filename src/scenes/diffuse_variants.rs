@@ -0,0 +1,23 @@
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::materials::{Material};
+
+
+pub fn diffuse_variants_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                                 Material::OrenNayar(Vec3::new(0.6, 0.55, 0.5), 0.5)),
+            // velvet
+            Hittable::Sphere(Vec3::new(0.0, 0.0, -1.0), 0.5,
+                                 Material::Sheen(Box::new(Material::OrenNayar(Vec3::new(0.3, 0.02, 0.05), 0.8)),
+                                                 Vec3::new(1.0, 0.6, 0.7), 0.3)),
+            // rough clay
+            Hittable::Sphere(Vec3::new(1.0, 0.0, -1.0), 0.5,
+                                 Material::OrenNayar(Vec3::new(0.7, 0.4, 0.25), 1.0)),
+            // paper lantern
+            Hittable::Sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5,
+                                 Material::DiffuseTransmission(Vec3::new(0.5, 0.5, 0.45), Vec3::new(0.4, 0.35, 0.2))),
+        ]
+    )
+}
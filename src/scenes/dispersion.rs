@@ -0,0 +1,20 @@
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::materials::{Material};
+use crate::math::spectrum::{Ior};
+
+
+pub fn dispersion_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                                 Material::Lambertian(Vec3::new(0.8, 0.8, 0.8))),
+            Hittable::Sphere(-Vec3::z_axis(), 0.5,
+                                 Material::Dielectric(Ior::sf11())),
+            Hittable::Sphere(Vec3::new(1.0, 0.0, -1.0), 0.5,
+                                 Material::Lambertian(Vec3::new(0.7, 0.2, 0.2))),
+            Hittable::Sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5,
+                                 Material::Dielectric(Ior::bk7())),
+        ]
+    )
+}
@@ -1,6 +1,6 @@
 use crate::math::math3::{Vec3};
 use crate::math::raytracing::{Hittable};
-use crate::math::signed_distance::{SignedDistanceField};
+use crate::math::signed_distance::{SignedDistanceField, MarchSettings, MarchExhausted};
 use crate::math::materials::{Material};
 
 
@@ -11,7 +11,10 @@ pub fn first_fractal_scene() -> Hittable {
                                  Material::Metal(Vec3::new(0.1, 0.8, 0.4), 0.01)),
             Hittable::Raymarcher(SignedDistanceField::
                 SierpinskiTetrasphere(Vec3::new(0.0, 0.52, -0.0), 8),
-                                     100, 0.000005, Material::Lambertian(Vec3::new(0.5, 0.4, 0.7))),
+                                     MarchSettings::new(100, 0.000005).with_pixel_footprint(0.0005)
+                                         .with_relaxation(1.6)
+                                         .with_on_exhausted(MarchExhausted::ClosestApproach(0.01)),
+                                     Material::Lambertian(Vec3::new(0.5, 0.4, 0.7))),
             Hittable::Sphere(Vec3::new(0.0, 0.52, 0.0), 0.4,
                              Material::Metal(Vec3::new(0.9, 0.2, 0.8), 0.01)),
 ]
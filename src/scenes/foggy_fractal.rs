@@ -0,0 +1,24 @@
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::signed_distance::{SignedDistanceField, MarchSettings};
+use crate::math::materials::{Material};
+use crate::math::media::{Medium};
+
+
+pub fn foggy_fractal_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                                 Material::Lambertian(Vec3::new(0.6, 0.6, 0.55))),
+            Hittable::Raymarcher(SignedDistanceField::
+                SierpinskiTetrasphere(Vec3::new(0.0, 0.52, -0.0), 6),
+                                     MarchSettings::new(100, 0.000005).with_pixel_footprint(0.0005),
+                                     Material::Lambertian(Vec3::new(0.5, 0.4, 0.7))),
+            // forward scattering smoke ball hovering next to the fractal
+            Hittable::Volume(Box::new(Hittable::Sphere(Vec3::new(1.1, 0.2, -0.6), 0.6,
+                                                       Material::Absorb)),
+                             Medium::Homogeneous(4.0, Vec3::new(0.9, 0.9, 0.9), 0.6)),
+            Hittable::Fog(Medium::Homogeneous(0.15, Vec3::new(0.95, 0.95, 1.0), 0.3), 1.0),
+        ]
+    )
+}
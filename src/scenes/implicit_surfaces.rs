@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::implicit::{ImplicitSurface};
+use crate::math::materials::{Material};
+use crate::math::transform::{Transform};
+use crate::scenes::spherion::{blobby_spherion_character};
+
+
+/// Blobby spherion among an algebraic tanglecube, a quadric hyperboloid and a gyroid
+/// ball, each found by root finding rather than sphere tracing.
+pub fn implicit_surfaces_scene() -> Hittable {
+    // x⁴ - 5x² + y⁴ - 5y² + z⁴ - 5z² + 11.8 = 0
+    let tanglecube = ImplicitSurface::Polynomial(vec![
+        (1.0, [4, 0, 0]), (-5.0, [2, 0, 0]),
+        (1.0, [0, 4, 0]), (-5.0, [0, 2, 0]),
+        (1.0, [0, 0, 4]), (-5.0, [0, 0, 2]),
+        (11.8, [0, 0, 0]),
+    ], Vec3::splat(-2.4), Vec3::splat(2.4));
+
+    // one sheet hyperboloid x² - y² + z² = r², cut off above and below
+    let hyperboloid = ImplicitSurface::quadric([[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0]],
+                                               Vec3::zero(), -0.04,
+                                               Vec3::new(-0.5, -0.45, -0.5), Vec3::new(0.5, 0.45, 0.5));
+
+    // a gyroid sheet clipped to a ball; |gradient| of the gyroid is at most √6
+    let frequency = 12.0;
+    let gyroid = ImplicitSurface::Function(Arc::new(move |p: Vec3<f64>| {
+        let q = frequency * p;
+        let sheet = q.x().sin() * q.y().cos() + q.y().sin() * q.z().cos() + q.z().sin() * q.x().cos();
+        (sheet.abs() - 0.3).max(frequency * (p.length() - 0.35))
+    }), frequency * 6.0_f64.sqrt(), Vec3::splat(-0.36), Vec3::splat(0.36));
+
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                             Material::Lambertian(Vec3::new(0.1, 0.8, 0.4))),
+            Hittable::Instance(Arc::new(blobby_spherion_character()),
+                               Transform::translation(Vec3::new(-0.1, 0.0, 0.8))),
+            Hittable::Instance(Arc::new(Hittable::Implicit(tanglecube, Material::Metal(Vec3::new(0.8, 0.6, 0.3), 0.1))),
+                               Transform::trs(Vec3::new(0.47, -0.15, 0.9), Vec3::y_axis(), 0.4, Vec3::splat(0.15))),
+            Hittable::Instance(Arc::new(Hittable::Implicit(hyperboloid, Material::Lambertian(Vec3::new(0.8, 0.3, 0.2)))),
+                               Transform::translation(Vec3::new(-0.7, -0.05, -1.3))),
+            Hittable::Instance(Arc::new(Hittable::Implicit(gyroid, Material::Lambertian(Vec3::new(0.9, 0.9, 0.85)))),
+                               Transform::translation(Vec3::new(0.9, -0.14, 1.7))),
+        ]
+    )
+}
@@ -0,0 +1,27 @@
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::materials::{Material};
+use crate::math::texture::{Texture};
+
+
+pub fn layered_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                                 Material::Mix(Box::new(Material::Lambertian(Vec3::new(0.8, 0.8, 0.8))),
+                                               Box::new(Material::Lambertian(Vec3::new(0.2, 0.2, 0.25))),
+                                               Texture::Checker(0.5))),
+            // car paint: rough red metal flakes under a smooth clear coat
+            Hittable::Sphere(Vec3::new(0.0, 0.0, -1.0), 0.5,
+                                 Material::Coated(Box::new(Material::Metal(Vec3::new(0.7, 0.05, 0.05), 0.4)), 1.5, 0.0)),
+            // lacquered wood
+            Hittable::Sphere(Vec3::new(1.0, 0.0, -1.0), 0.5,
+                                 Material::Coated(Box::new(Material::Lambertian(Vec3::new(0.45, 0.25, 0.1))), 1.5, 0.05)),
+            // tarnished gold, masked by noise
+            Hittable::Sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5,
+                                 Material::Mix(Box::new(Material::Metal(Vec3::new(1.0, 0.78, 0.34), 0.05)),
+                                               Box::new(Material::Lambertian(Vec3::new(0.2, 0.35, 0.3))),
+                                               Texture::Noise(6.0, 4))),
+        ]
+    )
+}
@@ -0,0 +1,22 @@
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::materials::{Material};
+use crate::math::spectrum::{ComplexIor};
+use crate::math::texture::{Texture};
+
+
+pub fn metals_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                                 Material::Mix(Box::new(Material::Lambertian(Vec3::new(0.8, 0.8, 0.8))),
+                                               Box::new(Material::Lambertian(Vec3::new(0.1, 0.1, 0.12))),
+                                               Texture::Checker(0.25))),
+            Hittable::Sphere(Vec3::new(0.0, 0.0, -1.0), 0.5, Material::Conductor(ComplexIor::gold(), 0.05)),
+            Hittable::Sphere(Vec3::new(1.0, 0.0, -1.0), 0.5, Material::Conductor(ComplexIor::copper(), 0.15)),
+            Hittable::Sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5, Material::Conductor(ComplexIor::aluminium(), 0.02)),
+            Hittable::Sphere(Vec3::new(0.5, -0.3, -0.2), 0.2, Material::Conductor(ComplexIor::silver(), 0.0)),
+            Hittable::Sphere(Vec3::new(-0.5, -0.3, -0.2), 0.2, Material::Conductor(ComplexIor::titanium(), 0.3)),
+        ]
+    )
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::materials::{Material};
+use crate::math::spectrum::{ComplexIor};
+use crate::math::texture::{Texture};
+use crate::math::transform::{Transform};
+
+
+/// One of each analytic primitive standing on a real ground plane.
+pub fn primitives_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Plane(Vec3::new(0.0, -0.5, 0.0), Vec3::y_axis(),
+                            Material::Mix(Box::new(Material::Lambertian(Vec3::new(0.8, 0.8, 0.8))),
+                                          Box::new(Material::Lambertian(Vec3::new(0.3, 0.3, 0.35))),
+                                          Texture::Checker(0.5))),
+            Hittable::Instance(Arc::new(Hittable::Box(Vec3::splat(-0.3), Vec3::splat(0.3),
+                                                      Material::Lambertian(Vec3::new(0.7, 0.2, 0.2)))),
+                               Transform::trs(Vec3::new(0.0, -0.2, -1.0), Vec3::y_axis(), 0.5, Vec3::one())),
+            Hittable::Cylinder(Vec3::new(1.0, -0.5, -0.6), 0.25, 0.7, Material::Conductor(ComplexIor::copper(), 0.1)),
+            Hittable::Cone(Vec3::new(-0.9, -0.5, -1.2), 0.3, 0.8, Material::Lambertian(Vec3::new(0.2, 0.5, 0.8))),
+            Hittable::Torus(Vec3::new(0.0, -0.35, 0.4), 0.35, 0.15, Material::Conductor(ComplexIor::gold(), 0.05)),
+            Hittable::Disc(Vec3::new(1.0, -0.49, 0.6), Vec3::y_axis(), 0.35, Material::Lambertian(Vec3::new(0.9, 0.8, 0.2))),
+            Hittable::Quad(Vec3::new(-1.6, -0.5, -2.0), Vec3::new(1.2, 0.0, -0.6), Vec3::new(0.0, 1.2, 0.0),
+                           Material::Metal(Vec3::new(0.9, 0.9, 0.9), 0.0)),
+        ]
+    )
+}
@@ -0,0 +1,26 @@
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::signed_distance::{SignedDistanceField};
+use crate::math::materials::{Material};
+use crate::math::media::{Medium, DensityField};
+
+
+pub fn smoky_fractal_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                                 Material::Lambertian(Vec3::new(0.6, 0.6, 0.55))),
+            // the Sierpinski tetrasphere as smoke, bounded by a sphere around it
+            Hittable::Volume(Box::new(Hittable::Sphere(Vec3::new(0.0, 0.52, 0.0), 1.1, Material::Absorb)),
+                             Medium::Heterogeneous(DensityField::Sdf(
+                                 SignedDistanceField::SierpinskiTetrasphere(Vec3::new(0.0, 0.52, 0.0), 4), 0.03),
+                                                   40.0, Vec3::new(0.8, 0.6, 0.9), 0.2)),
+            // a noisy cloud drifting beside it
+            Hittable::Volume(Box::new(Hittable::Sphere(Vec3::new(1.2, 0.3, -1.0), 0.7, Material::Absorb)),
+                             Medium::Heterogeneous(DensityField::Product(
+                                 Box::new(DensityField::Sdf(SignedDistanceField::Sphere(Vec3::new(1.2, 0.3, -1.0), 0.7), 0.3)),
+                                 Box::new(DensityField::Noise(4.0, 4))),
+                                                   12.0, Vec3::new(0.95, 0.95, 0.95), 0.5)),
+        ]
+    )
+}
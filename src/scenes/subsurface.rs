@@ -0,0 +1,28 @@
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::signed_distance::{SignedDistanceField, MarchSettings};
+use crate::math::materials::{Material};
+
+
+pub fn subsurface_scene() -> Hittable {
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                                 Material::Lambertian(Vec3::new(0.8, 0.8, 0.8))),
+            // wax
+            Hittable::Sphere(Vec3::new(0.0, 0.0, -1.0), 0.5,
+                                 Material::Subsurface(Vec3::new(0.95, 0.8, 0.5), 0.05, 1.45)),
+            // skin
+            Hittable::Sphere(Vec3::new(1.0, 0.0, -1.0), 0.5,
+                                 Material::Subsurface(Vec3::new(0.85, 0.55, 0.45), 0.1, 1.4)),
+            // marble
+            Hittable::Sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5,
+                                 Material::Subsurface(Vec3::new(0.95, 0.95, 0.92), 0.02, 1.5)),
+            // jade fractal
+            Hittable::Raymarcher(SignedDistanceField::
+                SierpinskiTetrasphere(Vec3::new(0.0, 0.3, 0.6), 5),
+                                     MarchSettings::new(100, 0.000005).with_pixel_footprint(0.0005),
+                                     Material::Subsurface(Vec3::new(0.4, 0.8, 0.5), 0.03, 1.6)),
+        ]
+    )
+}
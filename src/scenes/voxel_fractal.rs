@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use crate::math::math3::{Vec3};
+use crate::math::raytracing::{Hittable};
+use crate::math::signed_distance::{SignedDistanceField, MarchSettings};
+use crate::math::materials::{Material};
+use crate::math::transform::{Transform};
+use crate::math::voxel_grid::{VoxelGrid};
+
+
+/// The fractal baked into a voxel grid and raymarched from the samples, next to the
+/// analytic original to compare against.
+pub fn voxel_fractal_scene() -> Hittable {
+    let baked = VoxelGrid::from_sdf(&SignedDistanceField::SierpinskiTetrasphere(Vec3::new(0.0, 0.0, 0.0), 4), 96);
+    Hittable::HittableList (
+        vec![
+            Hittable::Sphere(Vec3::new(0.0, -100.5, -1.0), 100.0,
+                             Material::Lambertian(Vec3::new(0.6, 0.6, 0.55))),
+            Hittable::Instance(Arc::new(Hittable::Raymarcher(SignedDistanceField::VoxelGrid(Arc::new(baked)),
+                                                             MarchSettings::new(200, 0.0001),
+                                                             Material::Lambertian(Vec3::new(0.7, 0.4, 0.3)))),
+                               Transform::trs(Vec3::new(0.0, 0.05, -0.6), Vec3::y_axis(), 0.0, Vec3::splat(0.55))),
+            Hittable::Instance(Arc::new(Hittable::Raymarcher(SignedDistanceField::
+                                                             SierpinskiTetrasphere(Vec3::new(0.0, 0.0, 0.0), 4),
+                                                             MarchSettings::new(100, 0.000005).with_pixel_footprint(0.0005),
+                                                             Material::Lambertian(Vec3::new(0.5, 0.4, 0.7)))),
+                               Transform::trs(Vec3::new(0.0, 0.05, 0.6), Vec3::y_axis(), 0.0, Vec3::splat(0.55))),
+        ]
+    )
+}